@@ -21,6 +21,11 @@ pub enum Subcommand {
     New(NewCommand),
     Track(TrackCommand),
     Build(BuildCommand),
+    Tags(TagsCommand),
+    Query(QueryCommand),
+    Graph(GraphCommand),
+    Reid(ReidCommand),
+    Db(DbCommand),
 }
 
 #[derive(clap::Parser, Debug)]
@@ -56,6 +61,97 @@ pub struct TrackCommand {
 /// Performs a full build if no arguments provided, partial build if path specified
 pub struct BuildCommand {
     pub path: Option<Utf8PathBuf>,
+    #[arg(long, conflicts_with = "path")]
+    /// ignore `build/cache.toml` and build every file again
+    pub force: bool,
+    #[arg(long, short, conflicts_with = "path")]
+    /// how many files to query or compile at once, by default one per core
+    pub jobs: Option<usize>,
+    #[arg(long, short, conflicts_with = "path")]
+    /// build every file that can be built, and list the ones that can't at the end
+    pub keep_going: bool,
+    #[arg(long, conflicts_with = "path")]
+    /// also write what the build did as JSON to this file, eg. for CI
+    pub report: Option<Utf8PathBuf>,
+    #[arg(long, conflicts_with = "path")]
+    /// leave private nodes out, to publish the build. links to them show up as ghosts.
+    /// notes are private unless they set `private: false`.
+    /// the build databases keep them, for personal builds and the LSP.
+    pub public: bool,
+}
+
+#[derive(clap::Parser, Debug)]
+/// Lists the tags of the last build as a tree, with how many nodes each one has
+pub struct TagsCommand {
+    /// only list this tag and the ones nested inside it, eg. `math/linalg`
+    pub tag: Option<String>,
+}
+
+#[derive(clap::Parser, Debug)]
+/// Lists the nodes of the last build matching all the filters given
+pub struct QueryCommand {
+    /// eg. `status=draft`, `year>=2024`, `tags=math` or `source` to check that it's there.
+    /// works on custom frontmatter fields and on `id`, `path`, `kind`, `title`, `names`, `tags` and `private`.
+    pub filters: Vec<String>,
+    #[arg(long)]
+    /// field to sort the nodes by
+    pub sort: Option<String>,
+    #[arg(long)]
+    pub reverse: bool,
+    #[arg(long, value_delimiter = ',')]
+    /// fields to print next to each node, eg. `--show status,date`
+    pub show: Vec<String>,
+    #[arg(long)]
+    /// only the nodes linking to this one, eg. `--links-to linalg.matrix`
+    pub links_to: Option<String>,
+    #[arg(long, requires = "links_to")]
+    /// only count the links with this relation, eg. `--rel prerequisite`
+    pub rel: Option<String>,
+}
+
+#[derive(clap::Parser, Debug)]
+/// Gives files new ids with the strategy in `[ids]`, rewriting `nodes.toml` and the build databases
+pub struct ReidCommand {
+    /// paths of the files to give new ids to
+    pub paths: Vec<Utf8PathBuf>,
+    #[arg(long, conflicts_with = "all")]
+    /// give new ids to all but one of the files sharing an id, eg. after a merge
+    pub duplicates: bool,
+    #[arg(long)]
+    /// give new ids to every file, eg. after changing strategy
+    pub all: bool,
+    #[arg(long, conflicts_with_all = ["duplicates", "all"])]
+    /// only check that no id is used by more than one file, failing otherwise
+    pub check: bool,
+}
+
+#[derive(clap::Parser, Debug)]
+/// Exports the nodes of the last build and the links between them as a graphviz dot graph
+pub struct GraphCommand {
+    #[arg(long)]
+    /// only keep the links with this relation, eg. `--rel prerequisite`
+    pub rel: Option<String>,
+    #[arg(long)]
+    /// also include links to ghosts
+    pub ghosts: bool,
+    #[arg(long, short)]
+    /// file to write the graph to, instead of printing it
+    pub output: Option<Utf8PathBuf>,
+}
+
+#[derive(clap::Parser, Debug)]
+/// Moves the build databases between the storage in `[storage]` and `build/nodes.toml` and `build/links.toml`
+pub struct DbCommand {
+    #[command(subcommand)]
+    pub action: DbAction,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum DbAction {
+    /// writes the databases to `build/nodes.toml` and `build/links.toml`, eg. to diff or back them up
+    Export,
+    /// replaces the databases with `build/nodes.toml` and `build/links.toml`, eg. after switching storage
+    Import,
 }
 
 pub const CLAP_STYLING: clap::builder::styling::Styles = clap::builder::styling::Styles::styled()
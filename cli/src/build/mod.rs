@@ -1,10 +1,21 @@
+use std::time::Duration;
+
 use camino::Utf8Path;
 use omni::{
-    build::{compile::compile, partial::partial},
+    build::{
+        cache::{Cache, CacheError},
+        directory,
+        full::{self, FullError, Phase, Report},
+        lock::BuildLock,
+        partial::partial,
+        tags,
+    },
     config::Config,
-    link, node,
+    node,
+    storage::{self, StorageError},
+    tag,
+    transaction::{self, Transaction, TransactionError},
 };
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::{args::BuildCommand, pretty};
 
@@ -14,12 +25,11 @@ pub enum Error {
     IoError(#[from] std::io::Error),
 
     #[error(transparent)]
-    TomlDeserializeError(#[from] toml::de::Error),
-
-    #[error(transparent)]
-    TomlSerializeError(#[from] toml::ser::Error),
+    #[diagnostic(transparent)]
+    TransactionError(#[from] TransactionError),
 
     #[error(transparent)]
+    #[diagnostic(transparent)]
     NodeError(#[from] node::Error),
 
     #[error(transparent)]
@@ -30,6 +40,34 @@ pub enum Error {
 
     #[error(transparent)]
     ShallowBuildError(#[from] omni::build::shallow::ShallowError),
+
+    #[error(transparent)]
+    LockError(#[from] omni::build::lock::LockError),
+
+    #[error(transparent)]
+    DirectoryError(#[from] directory::DirectoryError),
+
+    #[error(transparent)]
+    TagsError(#[from] tags::TagsError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    FullBuildError(#[from] FullError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    CacheError(#[from] CacheError),
+
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("{0} files failed to build")]
+    #[diagnostic(help("they're listed above"))]
+    FilesFailed(usize),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    StorageError(#[from] StorageError),
     // #[error("path given has no parent")]
     // #[diagnostic(help("might be root or empty?"))]
     // NoParent,
@@ -52,66 +90,122 @@ pub fn build(
     config: &Config,
     cmd: BuildCommand,
 ) -> miette::Result<(), Error> {
-    pretty::info("read configs");
-    let user_db: node::UserDb = {
-        let db_file = std::fs::read(root.as_ref().join("nodes.toml"))?;
-        toml::from_slice(&db_file)?
-    };
-    let mut nodes: node::Db = {
-        let db_file = std::fs::read(root.as_ref().join("build/nodes.toml"))?;
-        toml::from_slice(&db_file)?
-    };
-    let mut links: link::Db = {
-        let db_file = std::fs::read(root.as_ref().join("build/links.toml"))?;
-        toml::from_slice(&db_file)?
-    };
-
-    match cmd.path {
-        Some(path) => {
-            let path_canonical = path.canonicalize_utf8()?;
-            let file = user_db
-                .files
-                .iter()
-                .filter_map(|f| match f.path.canonicalize_utf8() {
-                    Ok(p) => Some((f, p)),
-                    Err(err) => {
-                        pretty::warning(format!(
-                            "invalid path found in nodes.toml for id {}. error: {}",
-                            f.id, err
-                        ));
-                        None
-                    }
-                })
-                .find(|file| file.1 == path_canonical)
-                .map(|(f, _)| f)
-                .ok_or(node::Error::UntrackedNode(path))?;
-
-            pretty::info(format!("partial {}", file.path));
-            partial(&root, config, &mut nodes, &mut links, file, true)?;
+    // the LSP might be building too
+    let lock = BuildLock::acquire(&root, Duration::from_secs(30))?;
+
+    let Some(path) = cmd.path else {
+        let options = full::Options {
+            force: cmd.force,
+            jobs: cmd.jobs,
+            keep_going: cmd.keep_going,
+            public: cmd.public,
+        };
+        let report = full::full(&lock, config, &options, |phase| match phase {
+            Phase::Extract { files, of } => pretty::info(format!("query {files} of {of} files")),
+            Phase::Resolve => pretty::info("resolve links"),
+            Phase::Compile { files, of } => pretty::info(format!("build {files} of {of} files")),
+            Phase::Pages => pretty::info("directory and tag pages"),
+        })?;
+        print_report(&report);
+
+        if let Some(path) = cmd.report {
+            std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
         }
-        None => {
-            for file in &user_db.files {
-                pretty::info(format!("partial {}", file.path));
-                partial(&root, config, &mut nodes, &mut links, file, false)?
-            }
-
-            let root_as_ref = root.as_ref();
-            pretty::info("build all");
-            user_db
-                .files
-                .par_iter()
-                .try_for_each(|file| compile(root_as_ref, &file.path, config))?;
+        if !report.failed.is_empty() {
+            return Err(Error::FilesFailed(report.failed.len()));
         }
+        return Ok(());
     };
 
-    // SAVEPOINT(nodes, links, root)
-    let new_nodes_toml = toml::to_string(&nodes)?;
-    std::fs::write(root.as_ref().join("build/nodes.toml"), new_nodes_toml)?;
-
-    let new_links_toml = toml::to_string(&links)?;
-    std::fs::write(root.as_ref().join("build/links.toml"), new_links_toml)?;
-
-    std::fs::write(root.as_ref().join("build/root"), root.as_ref().as_str())?;
+    pretty::info("read configs");
+    let user_db: node::UserDb = transaction::read_toml(root.as_ref().join("nodes.toml"))?;
+    // ids might collide after merging notes made on different machines
+    user_db.check_ids()?;
+
+    let mut storage = storage::open(&root, config)?;
+    let mut nodes = storage.load_nodes()?;
+    let mut links = storage.load_links()?;
+
+    let path_canonical = path.canonicalize_utf8()?;
+    let file = user_db
+        .files
+        .iter()
+        .filter_map(|f| match f.path.canonicalize_utf8() {
+            Ok(p) => Some((f, p)),
+            Err(err) => {
+                pretty::warning(format!(
+                    "invalid path found in nodes.toml for id {}. error: {}",
+                    f.id, err
+                ));
+                None
+            }
+        })
+        .find(|file| file.1 == path_canonical)
+        .map(|(f, _)| f)
+        .ok_or(node::Error::UntrackedNode(path))?;
+
+    pretty::info(format!("partial {}", file.path));
+    partial(
+        &lock,
+        config,
+        storage.as_mut(),
+        &mut nodes,
+        &mut links,
+        file,
+        true,
+    )?;
+
+    // SAVEPOINT(nodes, links, tags, root)
+    storage.save(&lock, &nodes, &links)?;
+
+    let tags = tag::Db::from_nodes(&nodes);
+    let transaction = Transaction::new(&lock)
+        .write_db("build/tags.toml", &tags)?
+        .write("build/root", root.as_ref().as_str());
+    Cache::forget_public(&root, transaction)?.commit()?;
+
+    pretty::info("directory pages");
+    directory::write_pages(&root, config, &nodes)?;
+
+    pretty::info("tag pages");
+    tags::write_pages(&root, config, &nodes, &tags)?;
 
     Ok(())
 }
+
+fn print_report(report: &Report) {
+    for failure in &report.failed {
+        pretty::error(format!(
+            "cannot {} {}: {}",
+            failure.stage, failure.path, failure.error
+        ));
+        if let Some(stderr) = &failure.stderr {
+            println!("{stderr}");
+        }
+    }
+
+    let timings = &report.timings;
+    pretty::msg(
+        "built",
+        format!(
+            "{} files, skipped {}, failed {}, {} ghost links",
+            report.built,
+            report.skipped,
+            report.failed.len(),
+            report.ghosts
+        ),
+    );
+    if report.private > 0 {
+        pretty::msg("left out", format!("{} private files", report.private));
+    }
+    pretty::msg(
+        "took",
+        format!(
+            "{:.2}s: query {:.2}s, resolve {:.2}s, compile {:.2}s",
+            timings.total.as_secs_f64(),
+            timings.query.as_secs_f64(),
+            timings.resolve.as_secs_f64(),
+            timings.compile.as_secs_f64()
+        ),
+    );
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use camino::Utf8Path;
+use omni::{
+    build::lock::{BuildLock, LockError},
+    config::Config,
+    storage::{self, StorageError},
+};
+
+use crate::{
+    args::{DbAction, DbCommand},
+    pretty,
+};
+
+#[derive(thiserror::Error, miette::Diagnostic, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    StorageError(#[from] StorageError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    LockError(#[from] LockError),
+}
+
+pub fn db(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    cmd: DbCommand,
+) -> miette::Result<(), Error> {
+    let root = root.as_ref();
+    // the LSP might be building too
+    let lock = BuildLock::acquire(root, Duration::from_secs(30))?;
+
+    let mut storage = storage::open(root, config)?;
+    match cmd.action {
+        DbAction::Export => {
+            storage::export_toml(&lock, storage.as_ref())?;
+            pretty::info("exported to build/nodes.toml and build/links.toml");
+        }
+        DbAction::Import => {
+            storage::import_toml(&lock, storage.as_mut())?;
+            pretty::info("imported build/nodes.toml and build/links.toml");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use camino::Utf8Path;
+use omni::{
+    config::Config,
+    graph,
+    storage::{self, StorageError},
+};
+
+use crate::{args::GraphCommand, pretty};
+
+#[derive(thiserror::Error, miette::Diagnostic, Debug)]
+pub enum Error {
+    #[error("io error")]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    StorageError(#[from] StorageError),
+}
+
+pub fn graph(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    cmd: GraphCommand,
+) -> miette::Result<(), Error> {
+    let storage = storage::open(root, config)?;
+    let nodes = storage.load_nodes()?;
+    let links = storage.load_links()?;
+
+    let dot = graph::dot(&nodes, &links, cmd.rel.as_deref(), cmd.ghosts);
+
+    match cmd.output {
+        Some(path) => {
+            std::fs::write(&path, dot)?;
+            pretty::info(format!("wrote graph to {path}"));
+        }
+        None => print!("{dot}"),
+    }
+
+    Ok(())
+}
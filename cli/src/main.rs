@@ -1,8 +1,13 @@
 pub mod args;
 pub mod build;
+pub mod db;
+pub mod graph;
 pub mod init;
 pub mod new;
 pub mod pretty;
+pub mod query;
+pub mod reid;
+pub mod tags;
 pub mod tera;
 pub mod track;
 
@@ -33,6 +38,26 @@ fn main() -> miette::Result<()> {
             let (root, config) = read_config(&cwd)?;
             build::build(&root, &config, cmd)?
         }
+        args::Subcommand::Query(cmd) => {
+            let (root, config) = read_config(&cwd)?;
+            query::query(&root, &config, cmd)?
+        }
+        args::Subcommand::Graph(cmd) => {
+            let (root, config) = read_config(&cwd)?;
+            graph::graph(&root, &config, cmd)?
+        }
+        args::Subcommand::Reid(cmd) => {
+            let (root, config) = read_config(&cwd)?;
+            reid::reid(&root, &config, cmd)?
+        }
+        args::Subcommand::Db(cmd) => {
+            let (root, config) = read_config(&cwd)?;
+            db::db(&root, &config, cmd)?
+        }
+        args::Subcommand::Tags(cmd) => {
+            let (root, config) = read_config(&cwd)?;
+            tags::tags(&root, &config, cmd)?
+        }
     }
     Ok(())
 }
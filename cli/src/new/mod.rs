@@ -1,10 +1,16 @@
 use std::io::Write;
+use std::time::Duration;
 
 use camino::{Utf8Path, Utf8PathBuf};
 use omni::{
+    build::{
+        cache::{Cache, CacheError},
+        lock::{BuildLock, LockError},
+    },
     config::Config,
-    link, node,
     omni_path::{self, OmniPath},
+    storage::{self, StorageError},
+    transaction::{Transaction, TransactionError},
 };
 use tera::Tera;
 
@@ -21,12 +27,6 @@ pub enum Error {
     #[error(transparent)]
     OmniPathError(#[from] omni_path::Error),
 
-    #[error(transparent)]
-    TomlDeserializeError(#[from] toml::de::Error),
-
-    #[error(transparent)]
-    TomlSerializeError(#[from] toml::ser::Error),
-
     #[error(transparent)]
     TrackError(#[from] track::Error),
 
@@ -50,7 +50,24 @@ pub enum Error {
     AlreadyExists,
 
     #[error(transparent)]
+    #[diagnostic(transparent)]
     CoreTrackError(#[from] omni::track::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    StorageError(#[from] StorageError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    LockError(#[from] LockError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    TransactionError(#[from] TransactionError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    CacheError(#[from] CacheError),
 }
 
 pub fn new(
@@ -125,42 +142,28 @@ pub fn new(
     file.write_all(new_content.as_bytes())?;
 
     // track file
-    let file_node = omni::track::track(root, target)?;
-
-    // run a partial build
-    // read nodes
-    let nodes_file = match std::fs::read(root.join("build/nodes.toml")) {
-        Ok(bytes) => bytes,
-        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-            pretty::debug("created build/nodes.toml");
-            std::fs::File::create(root.join("build/nodes.toml"))?;
-            vec![]
-        }
-        Err(err) => return Err(err.into()),
-    };
-    let mut nodes: node::Db = toml::from_slice(&nodes_file)?;
-
-    // read links
-    let links_file = match std::fs::read(root.join("build/links.toml")) {
-        Ok(bytes) => bytes,
-        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-            pretty::debug("created build/links.toml");
-            std::fs::File::create(root.join("build/links.toml"))?;
-            vec![]
-        }
-        Err(err) => return Err(err.into()),
-    };
-    let mut links: link::Db = toml::from_slice(&links_file)?;
-
-    omni::build::partial::partial(root, config, &mut nodes, &mut links, &file_node, true)?;
+    let file_node = omni::track::track(root, config, target)?;
+
+    // run a partial build, the LSP might be building too
+    let lock = BuildLock::acquire(root, Duration::from_secs(30))?;
+    let mut storage = storage::open(root, config)?;
+    let mut nodes = storage.load_nodes()?;
+    let mut links = storage.load_links()?;
+
+    omni::build::partial::partial(
+        &lock,
+        config,
+        storage.as_mut(),
+        &mut nodes,
+        &mut links,
+        &file_node,
+        true,
+    )?;
 
     // SAVEPOINT(nodes, links, root) after a partial build
-    let new_nodes_toml = toml::to_string(&nodes)?;
-    std::fs::write(root.join("build/nodes.toml"), new_nodes_toml)?;
-
-    let new_links_toml = toml::to_string(&links)?;
-    std::fs::write(root.join("build/links.toml"), new_links_toml)?;
+    storage.save(&lock, &nodes, &links)?;
 
-    std::fs::write(root.join("build/root"), root.as_str())?;
+    let transaction = Transaction::new(&lock).write("build/root", root.as_str());
+    Cache::forget_public(root, transaction)?.commit()?;
     Ok(())
 }
@@ -0,0 +1,104 @@
+use camino::Utf8Path;
+use std::collections::{HashMap, HashSet};
+
+use omni::{
+    config::Config,
+    link, node,
+    query::{self, Filter},
+    storage::{self, StorageError},
+};
+
+use crate::args::QueryCommand;
+
+#[derive(thiserror::Error, miette::Diagnostic, Debug)]
+pub enum Error {
+    #[error("io error")]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    StorageError(#[from] StorageError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    QueryError(#[from] query::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    NodeError(#[from] node::Error),
+
+    #[error("invalid node name `{0}`")]
+    InvalidName(String),
+}
+
+pub fn query(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    cmd: QueryCommand,
+) -> miette::Result<(), Error> {
+    let root = root.as_ref();
+    let storage = storage::open(root, config)?;
+    let nodes = storage.load_nodes()?;
+
+    let filters = cmd
+        .filters
+        .iter()
+        .map(|f| f.parse())
+        .collect::<Result<Vec<Filter>, _>>()?;
+
+    let mut found: Vec<&node::Node> = query::query(&nodes, &filters).collect();
+    let mut spans: HashMap<node::Id, link::Span> = HashMap::new();
+
+    if let Some(name) = &cmd.links_to {
+        let file_part = link::FilePart::from_typst_style(name.trim_start_matches("omni."))
+            .ok_or(Error::InvalidName(name.clone()))?;
+        let target = nodes.find_from_filepart(root, &file_part, config)?;
+
+        let links = storage.load_links()?;
+        let mut linking = HashSet::new();
+        for l in links
+            .backlinks(&target.id)
+            .filter(|l| cmd.rel.is_none() || l.rel == cmd.rel)
+        {
+            linking.insert(&l.from);
+            // links are in the order they're written, so this is the first one
+            if let Some(span) = l.span {
+                spans.entry(l.from.clone()).or_insert(span);
+            }
+        }
+        found.retain(|node| linking.contains(&node.id));
+    }
+    if let Some(field) = &cmd.sort {
+        // nodes without the field go last
+        found.sort_by(|a, b| match (a.field(field), b.field(field)) {
+            (Some(a), Some(b)) => query::order(&a, &b),
+            (a, b) => a.is_none().cmp(&b.is_none()),
+        });
+    }
+    if cmd.reverse {
+        found.reverse();
+    }
+
+    for node in found {
+        let path = node.path.strip_prefix(root).unwrap_or(&node.path);
+        // so that editors can jump to the link with `--links-to`
+        let mut line = match spans.get(&node.id) {
+            Some(span) => format!(
+                "{}:{}:{}\t{}",
+                path,
+                span.line + 1,
+                span.column + 1,
+                node.title
+            ),
+            None => format!("{}\t{}", path, node.title),
+        };
+        for field in &cmd.show {
+            if let Some(value) = node.field(field) {
+                line.push_str(&format!("\t{field}={value}"));
+            }
+        }
+        println!("{line}");
+    }
+
+    Ok(())
+}
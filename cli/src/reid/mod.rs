@@ -0,0 +1,68 @@
+use camino::Utf8Path;
+use omni::{
+    config::Config,
+    node, reid,
+    transaction::{self, TransactionError},
+};
+
+use crate::{args::ReidCommand, pretty};
+
+#[derive(thiserror::Error, miette::Diagnostic, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    TransactionError(#[from] TransactionError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    NodeError(#[from] node::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ReidError(#[from] reid::Error),
+
+    #[error("no files given")]
+    #[diagnostic(help("pass the paths of the files, or use `--duplicates` or `--all`"))]
+    NothingToReid,
+}
+
+pub fn reid(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    cmd: ReidCommand,
+) -> miette::Result<(), Error> {
+    let root = root.as_ref();
+
+    // eg. in a post-merge hook
+    if cmd.check {
+        let user_db: node::UserDb = transaction::read_toml(root.join("nodes.toml"))?;
+        for (id, files) in user_db.duplicate_ids() {
+            for file in files {
+                pretty::warning(format!("{id} is used by {}", file.path));
+            }
+        }
+        user_db.check_ids()?;
+        pretty::info("no duplicate ids");
+        return Ok(());
+    }
+
+    let which = if cmd.all {
+        reid::Which::All
+    } else if cmd.duplicates {
+        reid::Which::Duplicates
+    } else if !cmd.paths.is_empty() {
+        reid::Which::Paths(cmd.paths)
+    } else {
+        return Err(Error::NothingToReid);
+    };
+
+    let renamed = reid::reid(root, config, which)?;
+    if renamed.is_empty() {
+        pretty::info("nothing to reid");
+    }
+    for r in renamed {
+        pretty::msg("reid", format!("{} {} -> {}", r.path, r.old, r.new));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use camino::Utf8Path;
+use omni::{
+    config::Config,
+    tag,
+    transaction::{self, TransactionError},
+};
+
+use crate::args::TagsCommand;
+
+#[derive(thiserror::Error, miette::Diagnostic, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    TransactionError(#[from] TransactionError),
+
+    #[error("build/tags.toml not found")]
+    #[diagnostic(help("build the project first with `omni build`"))]
+    NotBuilt,
+
+    #[error("tag `{0}` not found")]
+    TagNotFound(String),
+}
+
+pub fn tags(
+    root: impl AsRef<Utf8Path>,
+    _config: &Config,
+    cmd: TagsCommand,
+) -> miette::Result<(), Error> {
+    let db: tag::Db = {
+        let path = root.as_ref().join("build/tags.toml");
+        if !path.exists() {
+            return Err(Error::NotBuilt);
+        }
+        transaction::read_toml(path)?
+    };
+
+    match cmd.tag {
+        Some(name) => {
+            let name = tag::normalize(&name);
+            let tag = db.find(&name).ok_or(Error::TagNotFound(name.clone()))?;
+            println!("{} ({})", tag.name, tag.nodes.len());
+            print_tree(&db, Some(&tag.name), 1);
+        }
+        None => print_tree(&db, None, 0),
+    }
+
+    Ok(())
+}
+
+/// prints the tags nested inside `parent` and their counts, indented by depth
+fn print_tree(db: &tag::Db, parent: Option<&str>, depth: usize) {
+    for tag in db.children(parent) {
+        println!(
+            "{}{} ({})",
+            "  ".repeat(depth),
+            tag::basename(&tag.name),
+            tag.nodes.len()
+        );
+        print_tree(db, Some(&tag.name), depth + 1);
+    }
+}
@@ -37,6 +37,7 @@ pub enum Error {
     IsDirectory(Utf8PathBuf),
 
     #[error(transparent)]
+    #[diagnostic(transparent)]
     CoreTrackError(#[from] omni::track::Error),
 }
 
@@ -77,6 +78,6 @@ pub fn track(
         Err(err) => return Err(err.into()),
     };
 
-    omni::track::track(root, target)?;
+    omni::track::track(root, config, target)?;
     Ok(())
 }
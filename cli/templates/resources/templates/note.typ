@@ -1,4 +1,4 @@
-#import "/resources/typst/templates/note.typ": note;
+#import "/resources/typst/templates/note.typ": note, omni-block, omni-link;
 
 {% raw %}
 #show: note.with(
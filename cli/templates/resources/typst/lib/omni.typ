@@ -2,11 +2,167 @@
 
 #let ghost-link(body) = { text[#body] }
 
+/// set by `omni build --public`
+#let is-public() = sys.inputs.at("omni-public", default: "false") == "true"
+
+/// content is a sequence of children when it's more than one thing
+#let sequence = [a *b*].func()
+
+/// the children of `it`, with the nested sequences (eg. from a function call) flattened
+#let flatten(it) = if it.func() == sequence {
+  it.children.map(flatten).flatten()
+} else {
+  (it,)
+}
+
+/// leaves out the elements that `omni-block` made private nodes, for public builds.
+/// a heading takes its section along, up to the next heading as deep or shallower.
+/// only the top level of the document is looked at, eg. not what a `#set` rule styles.
+#let without-private(body) = {
+  let children = flatten(body)
+  let private = children
+    .filter(it => it.func() == metadata and it.has("label") and it.label == <omni-private>)
+    .map(it => label(it.value))
+  if private.len() == 0 {
+    return body
+  }
+
+  // the depth of the private heading whose section is being left out
+  let hiding = none
+  let kept = ()
+  for it in children {
+    if hiding != none and it.func() == heading and it.depth <= hiding {
+      hiding = none
+    }
+    if hiding != none {
+      continue
+    }
+    if it.has("label") and it.label in private {
+      if it.func() == heading {
+        hiding = it.depth
+      }
+      continue
+    }
+    kept.push(it)
+  }
+  kept.join()
+}
+
+/// names are either a list, or a dictionary from each name
+/// to how the links using it show up, eg. `(matrix: "Matrix", matrices: "Matrices")`.
+#let split-names(names) = if type(names) == dictionary {
+  (names: names.keys(), display: names)
+} else {
+  (names: names, display: (:))
+}
+
+/// any other named argument is kept as a custom field of the node, eg. `status: "draft"`.
+/// `none` ones are left out, as they cannot be stored.
+#let custom-fields(args) = {
+  let fields = (:)
+  for (key, value) in args.named() {
+    if value != none {
+      fields.insert(key, value)
+    }
+  }
+  fields
+}
+
+/// the plain text of an alias, or "" if there's none
+#let alias-text(alias) = {
+  if type(alias) == str {
+    alias
+  } else if type(alias) != content {
+    ""
+  } else if alias.has("text") {
+    alias.text
+  } else if alias.has("children") {
+    alias
+      .children
+      .map(it => {
+        if it.has("text") {
+          it.text
+        } else if it == [ ] {
+          " "
+        } else {
+          panic("alias can only contain plain text")
+        }
+      })
+      .join()
+  } else {
+    panic("alias can only contain plain text")
+  }
+}
+
+/// a link with a relation to its target,
+/// eg. `#omni-link(<omni.matrix>, rel: "prerequisite")[matrices]`.
+/// without a `rel` it's just like `@omni.matrix[matrices]`.
+#let omni-link(target, rel: none, ..alias) = ref(
+  target,
+  supplement: metadata((alias: alias.pos().at(0, default: none), rel: rel)),
+)
+
+/// makes the element labelled `target` (eg. a heading or a theorem) a node of its own,
+/// which can be linked to with any of its `names` (by default its label).
+///
+/// ```typ
+/// = Spectral theorem <spectral-theorem>
+/// #omni-block(<spectral-theorem>, names: ("spectral", "spectral-theorem"))
+/// ```
+///
+/// a `private` block is left out of public builds, a heading with its whole section.
+#let omni-block(
+  target,
+  title: none,
+  names: none,
+  tags: (),
+  private: false,
+  ..fields,
+) = {
+  if private {
+    // found by `omni` without introspection, as leaving the block out changes what's queried
+    [#metadata(str(target)) <omni-private>]
+  }
+
+  context {
+    let found = query(target)
+    // a public build left it out already
+    if not (private and is-public() and found.len() == 0) {
+      let element = found.first()
+      let is-heading = element.func() == heading
+
+      let names = split-names(if names == none { (str(target),) } else { names })
+
+      let title = if title != none {
+        title
+      } else if is-heading and element.body.has("text") {
+        element.body.text
+      } else {
+        str(target)
+      }
+
+      [#metadata((
+        label: str(target),
+        kind: if is-heading { "heading" } else { "label" },
+        title: title,
+        names: names.names,
+        display: names.display,
+        tags: tags,
+        private: private,
+        ..custom-fields(fields),
+      )) <omni-block>]
+    }
+  }
+}
+
+/// notes are private unless they set `private: false`,
+/// as only public ones are published by `omni build --public`.
 #let omni(
   title: "",
   tags: (),
   names: (),
   private: true,
+  ..fields,
   body,
 ) = {
   let nodes_toml = read("/build/nodes.toml", encoding: none)
@@ -19,14 +175,21 @@
     config_toml,
     root,
   )
+  // set by `omni build --public`, links to private nodes show up as ghosts
+  let public = sys.inputs.at("omni-public", default: "false")
+  let wasm = plugin.transition(wasm.set_public, bytes(public))
 
   assert.ne(title, "", message: "empty title. please provide a title.")
 
+  let names = split-names(names)
+
   [#metadata((
     title: title,
     tags: tags,
-    names: names,
+    names: names.names,
+    display: names.display,
     private: private,
+    ..custom-fields(fields),
   )) <omni-frontmatter>]
 
   show ref: it => {
@@ -40,26 +203,13 @@
     let file_part = splits.at(0).replace("omni.", "")
     let heading_part = splits.at(1, default: "")
     let alias = ""
-    if type(it.supplement) == content {
-      if it.supplement.has("text") {
-        alias = it.supplement.text
-      } else if it.supplement.has("children") {
-        alias = it
-          .supplement
-          .children
-          .map(it => {
-            if it.has("text") {
-              it.text
-            } else if it == [ ] {
-              " "
-            } else {
-              panic("alias can only contain plain text")
-            }
-          })
-          .join()
-      } else {
-        panic("alias can only contain plain text")
-      }
+    let rel = none
+    if type(it.supplement) == content and it.supplement.func() == metadata {
+      // made by omni-link
+      alias = alias-text(it.supplement.value.alias)
+      rel = it.supplement.value.rel
+    } else {
+      alias = alias-text(it.supplement)
     }
 
     let res = str(wasm.parse_link(
@@ -83,6 +233,8 @@
         content: node.content,
         to: file_part,
         ghost: true,
+        rel: rel,
+        target: str(it.target),
       )) <omni-link>]
     } else {
       link(node.target)[#node.content]
@@ -90,8 +242,15 @@
         content: node.content,
         to: node.to,
         ghost: false,
+        rel: rel,
+        target: str(it.target),
       )) <omni-link>]
     }
   }
-  body
+
+  if is-public() {
+    without-private(body)
+  } else {
+    body
+  }
 }
@@ -1,14 +1,17 @@
-#import "/resources/typst/lib/omni.typ": omni
+#import "/resources/typst/lib/omni.typ": omni, omni-block, omni-link
 
 
 #let note(
   title: "",
   tags: (),
   names: (),
+  // `false` publishes the note with `omni build --public`
   private: true,
+  ..fields,
   body,
 ) = {
-  show: omni.with(title: title, tags: tags, names: names, private: private)
+  // any other field, eg. `author: "me"`, is kept in the build db too
+  show: omni.with(title: title, tags: tags, names: names, private: private, ..fields)
 
   // your custom template here
 
@@ -0,0 +1,315 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use miette::Diagnostic;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    link, node,
+    transaction::{self, Transaction, TransactionError},
+};
+
+/// relative to the root
+pub const CACHE_FILE: &str = "build/cache.toml";
+
+/// functions whose first argument is a path to a file the document depends on
+const DEPENDENCIES: &[&str] = &[
+    "import",
+    "include",
+    "image",
+    "read",
+    "json",
+    "yaml",
+    "toml",
+    "csv",
+    "xml",
+    "cbor",
+    "bibliography",
+];
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum CacheError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    TomlSerializeError(#[from] toml::ser::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    TransactionError(#[from] TransactionError),
+}
+
+/// What each file was last built from, found in `build/cache.toml`.
+/// only full builds use it, a file built in any other way is just built again next time.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Default, Clone)]
+pub struct Cache {
+    /// whether the last full build was a public one, as it's compiled differently
+    #[serde(default)]
+    pub public: bool,
+    #[serde(default)]
+    pub files: BTreeMap<node::Id, Entry>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Entry {
+    /// hash of the file, of what it imports and reads, and of the config.
+    /// if it changes the file has to be queried again.
+    pub source: String,
+    /// hash of the links from the file and of the nodes they lead to.
+    /// if it changes the file has to be compiled again, eg. to show a new title.
+    pub links: String,
+}
+
+impl Cache {
+    /// an empty cache if there's none yet
+    pub fn load(root: impl AsRef<Utf8Path>) -> Result<Self, CacheError> {
+        let path = root.as_ref().join(CACHE_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(transaction::read_toml(path)?)
+    }
+
+    /// stages an empty cache if the last full build was a public one,
+    /// for the other builds, which compile files for personal use:
+    /// otherwise the next public build would keep what they compiled.
+    pub fn forget_public<'a>(
+        root: impl AsRef<Utf8Path>,
+        transaction: Transaction<'a>,
+    ) -> Result<Transaction<'a>, CacheError> {
+        if !Self::load(root)?.public {
+            return Ok(transaction);
+        }
+        Ok(transaction.write_db(CACHE_FILE, &Self::default())?)
+    }
+
+    /// forgets the files that aren't tracked anymore
+    pub fn retain_tracked(&mut self, user_db: &node::UserDb) {
+        self.files
+            .retain(|id, _| user_db.files.iter().any(|f| &f.id == id));
+    }
+}
+
+fn hex(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// the local files a typst source depends on, as written, eg. `/resources/typst/lib/omni.typ`
+fn dependencies(source: &str) -> Vec<&str> {
+    let mut found = vec![];
+    for func in DEPENDENCIES {
+        for (i, _) in source.match_indices(func) {
+            // eg. not `omni-read`
+            let before = source[..i].chars().next_back();
+            if before.is_some_and(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+                continue;
+            }
+            let rest = &source[i + func.len()..];
+            // `#import "a.typ"`, but `image("a.png")`
+            let rest = match *func {
+                "import" | "include" => rest.trim_start(),
+                _ => match rest.strip_prefix('(') {
+                    Some(rest) => rest.trim_start(),
+                    None => continue,
+                },
+            };
+            let Some(rest) = rest.strip_prefix('"') else {
+                continue;
+            };
+            let Some(end) = rest.find('"') else {
+                continue;
+            };
+            let path = &rest[..end];
+            // packages are versioned, so they never change
+            if !path.is_empty() && !path.starts_with('@') {
+                found.push(path);
+            }
+        }
+    }
+    found
+}
+
+fn hash_file(
+    root: &Utf8Path,
+    path: &Utf8Path,
+    hasher: &mut Sha256,
+    seen: &mut BTreeSet<Utf8PathBuf>,
+) -> Result<(), CacheError> {
+    let Ok(path) = path.canonicalize_utf8() else {
+        // it might show up later
+        hasher.update(b"missing ");
+        hasher.update(path.as_str());
+        return Ok(());
+    };
+    // what's in there is accounted for by `Entry::links`, and changes every build
+    if path.starts_with(root.join("build")) || !seen.insert(path.clone()) {
+        return Ok(());
+    }
+
+    let content = std::fs::read(&path)?;
+    hasher.update(path.as_str());
+    hasher.update(&content);
+
+    if path.extension() == Some("typ") {
+        let content = String::from_utf8_lossy(&content);
+        for dependency in dependencies(&content) {
+            let dependency = match dependency.strip_prefix('/') {
+                Some(from_root) => root.join(from_root),
+                None => path.parent().unwrap_or(root).join(dependency),
+            };
+            hash_file(root, &dependency, hasher, seen)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// see `Entry::source`. `path` is relative to the root, like in `nodes.toml`.
+pub fn source_hash(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    path: impl AsRef<Utf8Path>,
+) -> Result<String, CacheError> {
+    let root = root.as_ref().canonicalize_utf8()?;
+    let mut hasher = Sha256::new();
+    hasher.update(toml::to_string(config)?);
+    hash_file(&root, &root.join(path), &mut hasher, &mut BTreeSet::new())?;
+    Ok(hex(hasher))
+}
+
+/// see `Entry::links`, for every node with links from it
+pub fn links_hashes(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    nodes: &node::Db,
+    links: &link::Db,
+) -> HashMap<node::Id, String> {
+    let by_id = nodes.by_id();
+    let mut resolved: HashMap<&link::FilePart, Option<&node::Id>> = HashMap::new();
+
+    let mut hashers: HashMap<&node::Id, Sha256> = HashMap::new();
+    for link in &links.links {
+        let hasher = hashers.entry(&link.from).or_default();
+        hasher.update(serde_json::to_string(link).unwrap_or_default());
+        let to = match &link.to {
+            link::To::Id(id) => by_id.get(id).copied(),
+            // a node might have been made with that name since
+            link::To::Ghost(file_part) => resolved
+                .entry(file_part)
+                .or_insert_with(|| {
+                    nodes
+                        .find_from_filepart(&root, file_part, config)
+                        .ok()
+                        .map(|n| &n.id)
+                })
+                .and_then(|id| by_id.get(id).copied()),
+        };
+        match to {
+            Some(node) => hasher.update(serde_json::to_string(node).unwrap_or_default()),
+            None => hasher.update(b"none"),
+        }
+    }
+
+    hashers
+        .into_iter()
+        .map(|(id, hasher)| (id.clone(), hex(hasher)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependencies() {
+        let source = r#"
+        #import "/resources/typst/templates/note.typ": note
+        #import "@preview/cetz:0.4.0"
+        #include "chapter.typ"
+        #image("figures/graph.png")
+        #let data = json( "data.json" )
+        #let omni-read(path) = none
+        #omni-read("not-a-dependency.txt")
+        "#;
+        assert_eq!(
+            dependencies(source),
+            [
+                "/resources/typst/templates/note.typ",
+                "chapter.typ",
+                "figures/graph.png",
+                "data.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_source_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+        let config = Config::default();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("build")).unwrap();
+        std::fs::write(
+            root.join("src/matrix.typ"),
+            "#import \"/lib.typ\": x\n#read(\"/build/nodes.toml\")",
+        )
+        .unwrap();
+        std::fs::write(root.join("lib.typ"), "#let x = 1").unwrap();
+        std::fs::write(root.join("build/nodes.toml"), "").unwrap();
+
+        let hash = || source_hash(root, &config, "src/matrix.typ").unwrap();
+        let first = hash();
+        assert_eq!(hash(), first);
+
+        // build files don't count
+        std::fs::write(root.join("build/nodes.toml"), "[[node]]").unwrap();
+        assert_eq!(hash(), first);
+
+        // imports do
+        std::fs::write(root.join("lib.typ"), "#let x = 2").unwrap();
+        assert_ne!(hash(), first);
+    }
+
+    #[test]
+    fn test_links_hashes() {
+        let node = |id: &str, title: &str| node::Node {
+            id: id.into(),
+            path: format!("/vault/{id}.typ").into(),
+            title: title.into(),
+            names: vec![id.into()],
+            ..Default::default()
+        };
+        let link = |from: &str, to: link::To| link::Link {
+            from: from.into(),
+            to,
+            ..Default::default()
+        };
+
+        let mut nodes = node::Db {
+            nodes: vec![node("vector", "Vector"), node("matrix", "Matrix")],
+        };
+        let links = link::Db {
+            links: vec![link("matrix", link::To::Id("vector".into()))],
+        };
+        let config = Config::default();
+        let hashes = |nodes: &node::Db| links_hashes("/vault", &config, nodes, &links);
+
+        let matrix = node::Id::from("matrix");
+        let first = hashes(&nodes);
+        assert!(first.contains_key(&matrix));
+        assert!(!first.contains_key(&node::Id::from("vector")));
+        assert_eq!(hashes(&nodes)[&matrix], first[&matrix]);
+
+        // the linked node changed title
+        nodes.nodes[0].title = "Vectors".into();
+        assert_ne!(hashes(&nodes)[&matrix], first[&matrix]);
+    }
+}
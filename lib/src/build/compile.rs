@@ -1,4 +1,4 @@
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use miette::Diagnostic;
 use thiserror::Error;
 
@@ -7,6 +7,10 @@ use crate::{
     format::{src_to_build_path, typst},
 };
 
+/// the `sys.inputs` key telling `omni.typ` that it's a public build,
+/// where links to private nodes show up as ghosts
+pub const PUBLIC_INPUT: &str = "omni-public";
+
 #[derive(Debug, Error, Diagnostic)]
 pub enum CompileError {
     #[error("cannot shallow build (compile) a file with .{0} format")]
@@ -24,26 +28,53 @@ pub enum CompileError {
     IoError(#[from] std::io::Error),
 }
 
+/// compiles the file at `path`, for a public build if `public`
 pub fn compile(
     root: impl AsRef<Utf8Path>,
     path: impl AsRef<Utf8Path>,
     config: &Config,
+    public: bool,
 ) -> Result<(), CompileError> {
     let my_path_canon = root.as_ref().join(&path).canonicalize_utf8()?;
     let extension = my_path_canon.extension().ok_or(CompileError::NoFormat)?;
 
     if extension == "typ" {
-        compile_typst(root, &my_path_canon, config)
+        compile_typst(root, &my_path_canon, config, public)
     } else {
         Err(CompileError::InvalidFormat(extension.to_string()))
     }
 }
 
+/// the files compiling the file at `path` (canonical) makes, per `[typst] output_format`
+pub fn outputs(
+    root: impl AsRef<Utf8Path>,
+    path: impl AsRef<Utf8Path>,
+    config: &Config,
+) -> Vec<Utf8PathBuf> {
+    let Some(out_html) = src_to_build_path(&root, &path, "html") else {
+        return vec![];
+    };
+    let mut out_pdf = out_html.clone();
+    out_pdf.set_extension("pdf");
+
+    match config.typst.output_format {
+        config::TypstOutputFormat::Html => vec![out_html],
+        config::TypstOutputFormat::Pdf => vec![out_pdf],
+        config::TypstOutputFormat::HtmlAndPdf => vec![out_html, out_pdf],
+    }
+}
+
 pub fn compile_typst(
     root: impl AsRef<Utf8Path>,
     path: impl AsRef<Utf8Path>,
     config: &Config,
+    public: bool,
 ) -> Result<(), CompileError> {
+    let inputs: &[(&str, &str)] = if public {
+        &[(PUBLIC_INPUT, "true")]
+    } else {
+        &[]
+    };
     let out_html = src_to_build_path(&root, &path, "html").expect("both paths should be canonical");
 
     let mut out_pdf = out_html.clone();
@@ -57,17 +88,17 @@ pub fn compile_typst(
 
     match config.typst.output_format {
         config::TypstOutputFormat::Html => {
-            typst::compile(&root, &path, out_html, typst::Format::Html, true)?;
+            typst::compile(&root, &path, out_html, typst::Format::Html, inputs, true)?;
         }
         config::TypstOutputFormat::Pdf => {
-            typst::compile(&root, &path, out_pdf, typst::Format::Pdf, true)?;
+            typst::compile(&root, &path, out_pdf, typst::Format::Pdf, inputs, true)?;
         }
         config::TypstOutputFormat::HtmlAndPdf => {
             let root = root.as_ref();
             let path = path.as_ref();
             let (html_result, pdf_result) = rayon::join(
-                || typst::compile(root, path, out_html, typst::Format::Html, true),
-                || typst::compile(root, path, out_pdf, typst::Format::Pdf, true),
+                || typst::compile(root, path, out_html, typst::Format::Html, inputs, true),
+                || typst::compile(root, path, out_pdf, typst::Format::Pdf, inputs, true),
             );
             html_result?;
             pdf_result?;
@@ -0,0 +1,292 @@
+use std::collections::BTreeSet;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::{
+    build::html,
+    config::Config,
+    node::{self, Node, NodeKind},
+};
+
+/// files named like this (with any extension) back their directory
+pub const INDEX_STEM: &str = "index";
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum DirectoryError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+fn notes_dir(root: &Utf8Path, config: &Config) -> Utf8PathBuf {
+    let mut notes_dir = root.to_path_buf();
+    if let Some(prefix_dir) = &config.project.prefix_dir {
+        notes_dir.push(prefix_dir);
+    }
+    notes_dir
+}
+
+fn is_index(node: &Node) -> bool {
+    node.kind != NodeKind::Directory
+        && node.path.extension().is_some()
+        && node.path.file_stem() == Some(INDEX_STEM)
+}
+
+/// the directory a directory node stands for
+pub fn dir_path(node: &Node) -> &Utf8Path {
+    match node.path.file_stem() {
+        Some(INDEX_STEM) if node.path.extension().is_some() => {
+            node.path.parent().unwrap_or(&node.path)
+        }
+        _ => &node.path,
+    }
+}
+
+/// whether the page of a directory node is generated, as it has no index file
+pub fn is_generated(node: &Node) -> bool {
+    node.kind == NodeKind::Directory && dir_path(node) == node.path
+}
+
+/// Replaces the directory nodes with new ones,
+/// one for each directory (inside the notes directory) containing a file node.
+///
+/// a directory is named after itself and the `dir_aliases` pointing to it,
+/// and takes everything else from its index file, if there is one.
+pub fn directories(root: impl AsRef<Utf8Path>, config: &Config, nodes: &mut node::Db) {
+    let notes_dir = notes_dir(root.as_ref(), config);
+
+    nodes.nodes.retain(|n| n.kind != NodeKind::Directory);
+
+    let dirs: BTreeSet<&Utf8Path> = nodes
+        .nodes
+        .iter()
+        .filter(|n| n.kind == NodeKind::File)
+        .filter_map(|n| n.path.strip_prefix(&notes_dir).ok())
+        .flat_map(|rel| rel.ancestors().skip(1))
+        .filter(|dir| !dir.as_str().is_empty())
+        .collect();
+
+    let new_nodes: Vec<Node> = dirs
+        .into_iter()
+        .map(|rel| {
+            let path = notes_dir.join(rel);
+            let index = nodes
+                .nodes
+                .iter()
+                .find(|n| is_index(n) && n.parent.is_none() && n.path.parent() == Some(&path));
+
+            let name = rel.file_name().unwrap_or(rel.as_str()).to_string();
+            let mut aliases: Vec<String> = config
+                .dir_aliases
+                .iter()
+                .filter(|(_, to)| to.as_path() == rel)
+                .map(|(from, _)| from.clone())
+                .collect();
+            aliases.sort();
+
+            Node {
+                id: node::Id::dir(rel),
+                path: index.map_or(path, |i| i.path.clone()),
+                kind: NodeKind::Directory,
+                title: index.map_or(name.clone(), |i| i.title.clone()),
+                names: std::iter::once(name).chain(aliases).collect(),
+                display: Default::default(),
+                tags: index.map_or(vec![], |i| i.tags.clone()),
+                private: index.is_some_and(|i| i.private),
+                parent: None,
+                fields: index.map_or(Default::default(), |i| i.fields.clone()),
+            }
+        })
+        .collect();
+
+    nodes.nodes.extend(new_nodes);
+}
+
+/// the file and directory nodes directly inside a directory node,
+/// directories first.
+pub fn children<'a>(nodes: &'a node::Db, dir: &Node) -> Vec<&'a Node> {
+    let path = dir_path(dir);
+
+    let mut children: Vec<&Node> = nodes
+        .nodes
+        .iter()
+        .filter(|n| n.parent.is_none() && n.id != dir.id)
+        .filter(|n| match n.kind {
+            NodeKind::Directory => dir_path(n).parent() == Some(path),
+            _ => n.path.parent() == Some(path) && n.path != dir.path,
+        })
+        .collect();
+
+    children.sort_by(|a, b| {
+        (a.kind != NodeKind::Directory, &a.title).cmp(&(b.kind != NodeKind::Directory, &b.title))
+    });
+    children
+}
+
+/// the generated page of a directory node without an index file
+pub fn page(root: impl AsRef<Utf8Path>, config: &Config, nodes: &node::Db, dir: &Node) -> String {
+    let root = root.as_ref();
+    let Some(page) = html::node_page(root, dir, "html") else {
+        return String::new();
+    };
+
+    let items = children(nodes, dir)
+        .into_iter()
+        .map(|child| html::node_item(&html::node_href(root, config, &page, child), child))
+        .collect();
+
+    html::listing_page(&dir.title, &[("", items)])
+}
+
+/// removes the generated pages of the directory nodes in `nodes` that aren't in `kept`,
+/// eg. the ones with only private files in a public build
+pub fn remove_pages(
+    root: impl AsRef<Utf8Path>,
+    nodes: &node::Db,
+    kept: &node::Db,
+) -> Result<(), DirectoryError> {
+    let root = root.as_ref();
+
+    for dir in nodes.nodes.iter().filter(|n| is_generated(n)) {
+        if kept.nodes.iter().any(|n| n.id == dir.id) {
+            continue;
+        }
+        if let Some(page) = html::node_page(root, dir, "html")
+            && page.exists()
+        {
+            std::fs::remove_file(page)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// writes the pages of the directory nodes without an index file
+pub fn write_pages(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    nodes: &node::Db,
+) -> Result<(), DirectoryError> {
+    let root = root.as_ref();
+
+    for dir in nodes.nodes.iter().filter(|n| is_generated(n)) {
+        let Some(out) = html::node_page(root, dir, "html") else {
+            continue;
+        };
+        if let Some(parent) = out.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(out, page(root, config, nodes, dir))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn file(id: &str, path: &str, title: &str, tags: &[&str]) -> Node {
+        Node {
+            id: id.into(),
+            path: path.into(),
+            title: title.into(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_directories() {
+        let mut nodes = node::Db {
+            nodes: vec![
+                file(
+                    "id1",
+                    "/vault/notes/cs/linear-algebra/vector.typ",
+                    "Vector",
+                    &["linalg"],
+                ),
+                file(
+                    "id2",
+                    "/vault/notes/cs/linear-algebra/matrix.typ",
+                    "Matrix",
+                    &[],
+                ),
+                file(
+                    "id3",
+                    "/vault/notes/cs/index.typ",
+                    "Computer Science",
+                    &["cs"],
+                ),
+                file("id4", "/vault/notes/top.typ", "Top", &[]),
+            ],
+        };
+
+        let config = Config {
+            project: crate::config::Project {
+                prefix_dir: Some("notes".into()),
+                ..Default::default()
+            },
+            dir_aliases: HashMap::from([("linalg".into(), "cs/linear-algebra".into())]),
+            ..Default::default()
+        };
+
+        directories("/vault", &config, &mut nodes);
+        // running it again doesn't pile up directories
+        directories("/vault", &config, &mut nodes);
+
+        let dirs: Vec<&Node> = nodes
+            .nodes
+            .iter()
+            .filter(|n| n.kind == NodeKind::Directory)
+            .collect();
+        assert_eq!(
+            dirs,
+            [
+                &Node {
+                    id: "/cs".into(),
+                    path: "/vault/notes/cs/index.typ".into(),
+                    kind: NodeKind::Directory,
+                    title: "Computer Science".into(),
+                    names: vec!["cs".into()],
+                    tags: vec!["cs".into()],
+                    ..Default::default()
+                },
+                &Node {
+                    id: "/cs/linear-algebra".into(),
+                    path: "/vault/notes/cs/linear-algebra".into(),
+                    kind: NodeKind::Directory,
+                    title: "linear-algebra".into(),
+                    names: vec!["linear-algebra".into(), "linalg".into()],
+                    ..Default::default()
+                },
+            ]
+        );
+
+        let linalg = nodes
+            .find_from_filepart(
+                "/vault",
+                &crate::link::FilePart::Name("linalg".into()),
+                &config,
+            )
+            .unwrap();
+        assert_eq!(linalg.id, "/cs/linear-algebra".into());
+
+        let titles = |dir: &Node| {
+            children(&nodes, dir)
+                .iter()
+                .map(|n| n.title.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles(dirs[0]), ["linear-algebra"]);
+        assert_eq!(titles(dirs[1]), ["Matrix", "Vector"]);
+
+        let page = page("/vault", &config, &nodes, dirs[1]);
+        assert!(page.contains(
+            "<li><a href=\"vector.html\">Vector</a> <span class=\"tag\">#linalg</span></li>"
+        ));
+    }
+}
@@ -0,0 +1,665 @@
+//! Full builds, done in phases so that the order files are visited in doesn't matter:
+//! first every file is queried in parallel, then its links are resolved against all the nodes,
+//! and at last the files are compiled in parallel.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use miette::Diagnostic;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Serialize, Serializer};
+use thiserror::Error;
+
+use crate::{
+    build::{
+        cache::{self, Cache, CacheError, Entry},
+        compile::{self, CompileError},
+        directory::{self, DirectoryError},
+        lock::BuildLock,
+        shallow::{self, Extracted, ShallowError},
+        tags::{self, TagsError},
+    },
+    config::Config,
+    format::typst,
+    link, node,
+    storage::{self, StorageError},
+    tag,
+    transaction::{self, Transaction, TransactionError},
+};
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum FullError {
+    #[error("cannot {stage} {path}")]
+    FileError {
+        path: Utf8PathBuf,
+        stage: Stage,
+        #[source]
+        #[diagnostic_source]
+        source: FileError,
+    },
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    TransactionError(#[from] TransactionError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    NodeError(#[from] node::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    StorageError(#[from] StorageError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    CacheError(#[from] CacheError),
+
+    #[error(transparent)]
+    DirectoryError(#[from] DirectoryError),
+
+    #[error(transparent)]
+    TagsError(#[from] TagsError),
+
+    #[error(transparent)]
+    ThreadPoolError(#[from] rayon::ThreadPoolBuildError),
+}
+
+/// why a single file couldn't be built
+#[derive(Debug, Error, Diagnostic)]
+pub enum FileError {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ShallowError(#[from] ShallowError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    CompileError(#[from] CompileError),
+}
+
+impl FileError {
+    /// what typst printed, if it's typst that failed
+    pub fn typst_stderr(&self) -> Option<&str> {
+        match self {
+            Self::ShallowError(ShallowError::TypstQueryError(typst::QueryError::TypstError(
+                _,
+                stderr,
+            )))
+            | Self::CompileError(CompileError::TypstCompileError(
+                typst::CompileError::TypstError(_, stderr),
+            )) => Some(stderr),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// build every file, ignoring `build/cache.toml`
+    pub force: bool,
+    /// how many files are queried or compiled at once, by default one per core
+    pub jobs: Option<usize>,
+    /// build every file that can be, listing the ones that can't in the report instead of stopping
+    pub keep_going: bool,
+    /// leave private nodes out of the compiled files and the pages, eg. to publish them.
+    /// the build databases keep them, for personal builds and the LSP.
+    pub public: bool,
+}
+
+/// what a full build is doing, to report progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// querying the files that changed since the last build
+    Extract { files: usize, of: usize },
+    /// resolving the links against all the nodes
+    Resolve,
+    /// compiling the files whose sources or linked nodes changed
+    Compile { files: usize, of: usize },
+    /// writing the directory and tag pages
+    Pages,
+}
+
+/// the part of the build a file failed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Query,
+    Compile,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::Query => write!(f, "query"),
+            Stage::Compile => write!(f, "compile"),
+        }
+    }
+}
+
+/// a file that couldn't be built, with `Options::keep_going`
+#[derive(Debug, Clone, Serialize)]
+pub struct Failure {
+    pub id: node::Id,
+    /// as in `nodes.toml`
+    pub path: Utf8PathBuf,
+    pub stage: Stage,
+    pub error: String,
+    /// what typst printed, if it's typst that failed
+    pub stderr: Option<String>,
+}
+
+impl Failure {
+    fn new(file: &node::File, stage: Stage, err: &FileError) -> Self {
+        let stderr = err.typst_stderr().map(|s| s.trim_end().to_string());
+        Self {
+            id: file.id.clone(),
+            path: file.path.clone(),
+            stage,
+            error: match stderr {
+                // the error would repeat it otherwise
+                Some(_) => format!("typst failed to {stage} the file"),
+                None => err.to_string(),
+            },
+            stderr,
+        }
+    }
+}
+
+fn seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// how long each phase took, in seconds once serialized
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Timings {
+    #[serde(serialize_with = "seconds")]
+    pub query: Duration,
+    #[serde(serialize_with = "seconds")]
+    pub resolve: Duration,
+    #[serde(serialize_with = "seconds")]
+    pub compile: Duration,
+    #[serde(serialize_with = "seconds")]
+    pub total: Duration,
+}
+
+/// what a full build did
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    /// how many files are tracked
+    pub files: usize,
+    /// how many files were compiled
+    pub built: usize,
+    /// how many files were up to date
+    pub skipped: usize,
+    /// how many files were left out as they're private, in a public build
+    pub private: usize,
+    pub failed: Vec<Failure>,
+    /// how many links lead to nodes that don't exist
+    pub ghosts: usize,
+    pub timings: Timings,
+}
+
+/// the files something was done to, with what came of it, and the ones that failed
+type Done<'a, T> = (Vec<(&'a node::File, T)>, Vec<Failure>);
+
+/// runs `f` on each of `files` in parallel.
+/// the ones that fail are returned apart with `keep_going`, otherwise the first failure is the error.
+fn each_file<'a, T: Send>(
+    files: &[&'a node::File],
+    stage: Stage,
+    keep_going: bool,
+    f: impl Fn(&node::File) -> Result<T, FileError> + Sync,
+) -> Result<Done<'a, T>, FullError> {
+    if !keep_going {
+        let done = files
+            .par_iter()
+            .map(|file| match f(file) {
+                Ok(t) => Ok((*file, t)),
+                Err(source) => Err(FullError::FileError {
+                    path: file.path.clone(),
+                    stage,
+                    source,
+                }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok((done, vec![]));
+    }
+
+    let results: Vec<_> = files.par_iter().map(|file| (*file, f(file))).collect();
+    let mut done = vec![];
+    let mut failed = vec![];
+    for (file, result) in results {
+        match result {
+            Ok(t) => done.push((file, t)),
+            Err(err) => failed.push(Failure::new(file, stage, &err)),
+        }
+    }
+    Ok((done, failed))
+}
+
+/// ids of the files with links to nodes that aren't there anymore,
+/// eg. to a block that was removed, which have to be queried again to find out what they link to now
+fn dangling(nodes: &node::Db, links: &link::Db) -> HashSet<node::Id> {
+    let ids: HashSet<&node::Id> = nodes.nodes.iter().map(|n| &n.id).collect();
+    links
+        .links
+        .iter()
+        .filter(|l| matches!(&l.to, link::To::Id(id) if !ids.contains(id)))
+        .map(|l| l.from.clone())
+        .collect()
+}
+
+/// the nodes with links to a node whose names changed, ie. some node got or lost
+/// one of them (or moved), as they might have been written with a name that
+/// leads to another node now, or to more than one
+fn renamed(old: &node::Db, new: &node::Db, links: &link::Db) -> HashSet<node::Id> {
+    fn names(db: &node::Db) -> HashSet<(&node::Id, &String, &Utf8Path)> {
+        db.nodes
+            .iter()
+            .flat_map(|n| {
+                n.names
+                    .iter()
+                    .map(move |name| (&n.id, name, n.path.as_path()))
+            })
+            .collect()
+    }
+    let (old_names, new_names) = (names(old), names(new));
+    let changed: HashSet<&String> = old_names
+        .symmetric_difference(&new_names)
+        .map(|(_, name, _)| *name)
+        .collect();
+    if changed.is_empty() {
+        return HashSet::new();
+    }
+
+    let targets: HashSet<&node::Id> = old
+        .nodes
+        .iter()
+        .filter(|n| n.names.iter().any(|name| changed.contains(name)))
+        .map(|n| &n.id)
+        .collect();
+    links
+        .links
+        .iter()
+        .filter(|l| matches!(&l.to, link::To::Id(id) if targets.contains(id)))
+        .map(|l| l.from.clone())
+        .collect()
+}
+
+/// queries `files` in parallel and puts their nodes in `nodes`
+fn extract<'a>(
+    lock: &BuildLock,
+    nodes: &mut node::Db,
+    files: &[&'a node::File],
+    keep_going: bool,
+) -> Result<Done<'a, Extracted>, FullError> {
+    let root = lock.root();
+    let (extracted, failed) = each_file(files, Stage::Query, keep_going, |file| {
+        Ok(shallow::extract(root, file)?)
+    })?;
+
+    for (file, extracted) in &extracted {
+        shallow::merge_nodes(nodes, file, extracted);
+    }
+    Ok((extracted, failed))
+}
+
+/// builds all the files in `nodes.toml` that changed since the last full build,
+/// and saves the build databases and the cache.
+///
+/// with `Options::keep_going` the files that fail keep what they had from the last build,
+/// and are built again next time.
+pub fn full(
+    lock: &BuildLock,
+    config: &Config,
+    options: &Options,
+    on_phase: impl Fn(Phase),
+) -> Result<Report, FullError> {
+    let root = lock.root();
+    let start = Instant::now();
+    let mut report = Report::default();
+
+    let user_db: node::UserDb = transaction::read_toml(root.join("nodes.toml"))?;
+    // ids might collide after merging notes made on different machines
+    user_db.check_ids()?;
+    report.files = user_db.files.len();
+
+    let mut storage = storage::open(root, config)?;
+    let mut nodes = storage.load_nodes()?;
+    let mut links = storage.load_links()?;
+
+    let mut cache = if options.force {
+        Cache::default()
+    } else {
+        Cache::load(root)?
+    };
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.jobs.unwrap_or(0))
+        .build()?;
+
+    // PHASE 1: query the files that changed, all of them before any link is resolved
+    let sources = pool.install(|| {
+        user_db
+            .files
+            .par_iter()
+            .map(|file| {
+                Ok((
+                    file.id.clone(),
+                    cache::source_hash(root, config, &file.path)?,
+                ))
+            })
+            .collect::<Result<HashMap<_, _>, CacheError>>()
+    })?;
+
+    let built: HashSet<&node::Id> = nodes.nodes.iter().map(|n| &n.id).collect();
+    let changed: Vec<&node::File> = user_db
+        .files
+        .iter()
+        .filter(|file| {
+            // the build database might have been deleted while the cache wasn't
+            let unchanged = cache
+                .files
+                .get(&file.id)
+                .is_some_and(|entry| entry.source == sources[&file.id])
+                && built.contains(&file.id);
+            !unchanged
+        })
+        .collect();
+    let changed_ids: HashSet<&node::Id> = changed.iter().map(|file| &file.id).collect();
+
+    on_phase(Phase::Extract {
+        files: changed.len(),
+        of: user_db.files.len(),
+    });
+    let old_nodes = nodes.clone();
+    let (mut extracted, failed) =
+        pool.install(|| extract(lock, &mut nodes, &changed, options.keep_going))?;
+    report.failed.extend(failed);
+    directory::directories(root, config, &mut nodes);
+
+    // links from files that didn't change might lead to a block that's gone now,
+    // or use a name that leads somewhere else now
+    let mut stale = dangling(&nodes, &links);
+    stale.extend(renamed(&old_nodes, &nodes, &links));
+    let dependants: Vec<&node::File> = user_db
+        .files
+        .iter()
+        .filter(|file| stale.contains(&file.id) && !changed_ids.contains(&file.id))
+        .collect();
+    if !dependants.is_empty() {
+        on_phase(Phase::Extract {
+            files: dependants.len(),
+            of: user_db.files.len(),
+        });
+        let (more, failed) =
+            pool.install(|| extract(lock, &mut nodes, &dependants, options.keep_going))?;
+        extracted.extend(more);
+        report.failed.extend(failed);
+    }
+
+    directory::directories(root, config, &mut nodes);
+    report.timings.query = start.elapsed();
+
+    // PHASE 2: resolve the links, now that every node is known
+    on_phase(Phase::Resolve);
+    let phase_start = Instant::now();
+    for (file, extracted) in &extracted {
+        shallow::merge_links(root, config, &nodes, &mut links, file, extracted);
+    }
+
+    // ghosts from the files that didn't change might have found their node
+    let mut resolved: HashMap<link::FilePart, Option<node::Id>> = HashMap::new();
+    for link in &mut links.links {
+        let link::To::Ghost(file_part) = &link.to else {
+            continue;
+        };
+        let id = resolved.entry(file_part.clone()).or_insert_with(|| {
+            nodes
+                .find_from_filepart(root, file_part, config)
+                .ok()
+                .map(|n| n.id.clone())
+        });
+        if let Some(id) = id {
+            link.to = link::To::Id(id.clone());
+        }
+    }
+    report.ghosts = links
+        .links
+        .iter()
+        .filter(|l| matches!(l.to, link::To::Ghost(_)))
+        .count();
+
+    // SAVEPOINT(nodes, links): so that links resolve while compiling,
+    // both at once so that they agree even if compiling fails.
+    // private nodes are kept, the typst plugin makes links to them ghosts in public builds
+    storage.save(lock, &nodes, &links)?;
+    report.timings.resolve = phase_start.elapsed();
+
+    // PHASE 3: compile the files whose sources or linked nodes changed, or whose outputs are gone
+    let phase_start = Instant::now();
+    let failed_to_query: HashSet<node::Id> = report.failed.iter().map(|f| f.id.clone()).collect();
+    let root_canonical = root.canonicalize_utf8()?;
+
+    // private files aren't published, not even what a personal build made of them
+    let private_ids = if options.public {
+        nodes.private_ids()
+    } else {
+        HashSet::new()
+    };
+    let private: Vec<&node::File> = user_db
+        .files
+        .iter()
+        .filter(|file| private_ids.contains(&file.id))
+        .collect();
+    for file in &private {
+        if let Ok(path) = root_canonical.join(&file.path).canonicalize_utf8() {
+            for out in compile::outputs(&root_canonical, path, config) {
+                if out.exists() {
+                    std::fs::remove_file(out)?;
+                }
+            }
+        }
+        cache.files.remove(&file.id);
+    }
+    report.private = private.len();
+
+    // everything looks different in the other kind of build
+    let kind_changed = cache.public != options.public;
+    cache.public = options.public;
+
+    let mut links_hashes = cache::links_hashes(root, config, &nodes, &links);
+    let mut entries: HashMap<node::Id, Entry> = HashMap::new();
+    let compilable: Vec<&node::File> = user_db
+        .files
+        .iter()
+        .filter(|file| !failed_to_query.contains(&file.id) && !private_ids.contains(&file.id))
+        .collect();
+    let outdated: Vec<&node::File> = compilable
+        .iter()
+        .copied()
+        .filter(|file| {
+            let entry = Entry {
+                source: sources[&file.id].clone(),
+                links: links_hashes.remove(&file.id).unwrap_or_default(),
+            };
+            let outputs_exist = root_canonical
+                .join(&file.path)
+                .canonicalize_utf8()
+                .is_ok_and(|path| {
+                    compile::outputs(&root_canonical, path, config)
+                        .iter()
+                        .all(|out| out.exists())
+                });
+            let up_to_date =
+                outputs_exist && !kind_changed && cache.files.get(&file.id) == Some(&entry);
+            entries.insert(file.id.clone(), entry);
+            !up_to_date
+        })
+        .collect();
+
+    on_phase(Phase::Compile {
+        files: outdated.len(),
+        of: user_db.files.len(),
+    });
+    let (compiled, failed) = pool.install(|| {
+        each_file(&outdated, Stage::Compile, options.keep_going, |file| {
+            Ok(compile::compile(root, &file.path, config, options.public)?)
+        })
+    })?;
+    report.failed.extend(failed);
+    report.built = compiled.len();
+    report.skipped = compilable.len() - outdated.len();
+    report.timings.compile = phase_start.elapsed();
+
+    for (file, ()) in compiled {
+        if let Some(entry) = entries.remove(&file.id) {
+            cache.files.insert(file.id.clone(), entry);
+        }
+    }
+    // so that they're built again next time
+    for failure in &report.failed {
+        cache.files.remove(&failure.id);
+    }
+    cache.retain_tracked(&user_db);
+
+    // SAVEPOINT(tags, root, cache): the nodes and links didn't change since compiling
+    let tags = tag::Db::from_nodes(&nodes);
+    Transaction::new(lock)
+        .write_db("build/tags.toml", &tags)?
+        .write("build/root", root.as_str())
+        .write_db(cache::CACHE_FILE, &cache)?
+        .commit()?;
+
+    on_phase(Phase::Pages);
+    if options.public {
+        // the directories left with only private files are gone too
+        let mut public = nodes.public();
+        directory::directories(root, config, &mut public);
+        directory::remove_pages(root, &nodes, &public)?;
+        directory::write_pages(root, config, &public)?;
+        tags::write_pages(root, config, &public, &tag::Db::from_nodes(&public))?;
+    } else {
+        directory::write_pages(root, config, &nodes)?;
+        tags::write_pages(root, config, &nodes, &tags)?;
+    }
+
+    report.timings.total = start.elapsed();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dangling() {
+        let node = |id: &str| node::Node {
+            id: id.into(),
+            path: format!("/vault/{id}.typ").into(),
+            title: id.into(),
+            names: vec![id.into()],
+            ..Default::default()
+        };
+        let link = |from: &str, to: link::To| link::Link {
+            from: from.into(),
+            to,
+            ..Default::default()
+        };
+
+        let nodes = node::Db {
+            nodes: vec![node("vector"), node("matrix")],
+        };
+        let links = link::Db {
+            links: vec![
+                link("vector", link::To::Id("matrix".into())),
+                link("matrix", link::To::Id("vector:addition".into())),
+                link(
+                    "matrix",
+                    link::To::Ghost(link::FilePart::Name("tensor".into())),
+                ),
+            ],
+        };
+
+        assert_eq!(dangling(&nodes, &links), HashSet::from(["matrix".into()]));
+    }
+
+    #[test]
+    fn test_renamed() {
+        let node = |id: &str, names: &[&str]| node::Node {
+            id: id.into(),
+            path: format!("/vault/{id}.typ").into(),
+            title: id.into(),
+            names: names.iter().map(|n| n.to_string()).collect(),
+            ..Default::default()
+        };
+        let link = |from: &str, to: &str| link::Link {
+            from: from.into(),
+            to: link::To::Id(to.into()),
+            ..Default::default()
+        };
+
+        let old = node::Db {
+            nodes: vec![
+                node("vector", &["vector"]),
+                node("matrix", &["matrix", "matrices"]),
+                node("tensor", &["tensor"]),
+            ],
+        };
+        let links = link::Db {
+            links: vec![
+                link("vector", "matrix"),
+                link("tensor", "vector"),
+                link("matrix", "tensor"),
+            ],
+        };
+        assert_eq!(renamed(&old, &old, &links), HashSet::new());
+
+        // `matrices` is somewhere else now, `vector` is ambiguous
+        let new = node::Db {
+            nodes: vec![
+                node("vector", &["vector"]),
+                node("matrix", &["matrix"]),
+                node("tensor", &["tensor"]),
+                node("arrow", &["vector", "matrices"]),
+            ],
+        };
+        assert_eq!(
+            renamed(&old, &new, &links),
+            HashSet::from(["vector".into(), "tensor".into()])
+        );
+    }
+
+    #[test]
+    fn test_failure() {
+        let file = node::File {
+            id: "id1".into(),
+            path: "vector.typ".into(),
+        };
+
+        let err = FileError::CompileError(CompileError::TypstCompileError(
+            typst::CompileError::TypstError(1, "error: unknown variable: x\n".into()),
+        ));
+        let failure = Failure::new(&file, Stage::Compile, &err);
+        assert_eq!(failure.error, "typst failed to compile the file");
+        assert_eq!(
+            failure.stderr.as_deref(),
+            Some("error: unknown variable: x")
+        );
+
+        let err = FileError::ShallowError(ShallowError::MissingFrontmatter);
+        let failure = Failure::new(&file, Stage::Query, &err);
+        assert_eq!(failure.error, err.to_string());
+        assert_eq!(failure.stderr, None);
+
+        let json = serde_json::to_value(&failure).unwrap();
+        assert_eq!(json["stage"], "query");
+        assert_eq!(json["path"], "vector.typ");
+    }
+}
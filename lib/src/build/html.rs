@@ -0,0 +1,135 @@
+//! helpers for the pages generated by the build, like the directory and tag listings.
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{
+    build::directory,
+    config::{Config, TypstOutputFormat},
+    format::src_to_build_path,
+    node::Node,
+};
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// the extension of the built pages that links should point to
+pub fn extension(config: &Config) -> &'static str {
+    match config.typst.output_format {
+        TypstOutputFormat::Pdf => "pdf",
+        TypstOutputFormat::Html | TypstOutputFormat::HtmlAndPdf => "html",
+    }
+}
+
+/// where the built page of a node ends up
+pub fn node_page(root: &Utf8Path, node: &Node, extension: &str) -> Option<Utf8PathBuf> {
+    if directory::is_generated(node) {
+        src_to_build_path(root, node.path.join(directory::INDEX_STEM), "html")
+    } else {
+        src_to_build_path(root, &node.path, extension)
+    }
+}
+
+/// a relative link from the page at `from` to the one at `to`,
+/// including the `#label` of sub-document nodes.
+pub fn href(from: &Utf8Path, to: &Utf8Path) -> String {
+    let from_dir = from.parent().unwrap_or(from);
+    let common = from_dir
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut href = Utf8PathBuf::new();
+    for _ in from_dir.components().skip(common) {
+        href.push("..");
+    }
+    for component in to.components().skip(common) {
+        href.push(component);
+    }
+    href.to_string()
+}
+
+/// a relative link from the page at `from` to the page of `node`
+pub fn node_href(root: &Utf8Path, config: &Config, from: &Utf8Path, node: &Node) -> String {
+    let Some(to) = node_page(root, node, extension(config)) else {
+        return String::new();
+    };
+    match &node.parent {
+        Some(parent) => format!("{}#{}", href(from, &to), parent.label),
+        None => href(from, &to),
+    }
+}
+
+/// an html page with a title and a list
+pub fn listing_page(title: &str, sections: &[(&str, Vec<String>)]) -> String {
+    let mut body = String::new();
+    for (heading, items) in sections.iter().filter(|(_, items)| !items.is_empty()) {
+        if !heading.is_empty() {
+            body.push_str(&format!("<h2>{}</h2>\n", escape(heading)));
+        }
+        body.push_str("<ul>\n");
+        for item in items {
+            body.push_str(&format!("<li>{item}</li>\n"));
+        }
+        body.push_str("</ul>\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n{body}</body>\n</html>\n",
+        title = escape(title),
+    )
+}
+
+/// a list item linking to a node, followed by its tags and custom fields
+pub fn node_item(href: &str, node: &Node) -> String {
+    let tags: String = node
+        .tags
+        .iter()
+        .map(|t| format!(" <span class=\"tag\">#{}</span>", escape(t)))
+        .collect();
+    let fields: String = node
+        .fields
+        .iter()
+        .map(|(k, v)| {
+            format!(
+                " <span class=\"field\" data-field=\"{}\">{}</span>",
+                escape(k),
+                escape(&v.to_string())
+            )
+        })
+        .collect();
+    format!(
+        "<a href=\"{}\">{}</a>{}{}",
+        escape(href),
+        escape(&node.title),
+        tags,
+        fields
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_href() {
+        assert_eq!(
+            href(
+                "/vault/build/tags/math/linalg.html".into(),
+                "/vault/build/tags/math/linalg/spectral.html".into()
+            ),
+            "linalg/spectral.html"
+        );
+        assert_eq!(
+            href(
+                "/vault/build/tags/math/linalg.html".into(),
+                "/vault/build/cs/vector.html".into()
+            ),
+            "../../cs/vector.html"
+        );
+    }
+}
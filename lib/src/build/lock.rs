@@ -0,0 +1,123 @@
+use std::io::{Seek, Write};
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use miette::Diagnostic;
+use thiserror::Error;
+
+/// lock file guarding `nodes.toml` and the build databases
+pub const LOCK_FILE: &str = "build/lock";
+
+const RETRY_EVERY: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum LockError {
+    #[error("the build is locked by another process ({0})")]
+    Locked(String),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+/// Exclusive access to the build databases,
+/// so that concurrent builds (eg. the CLI and the LSP) don't overwrite each other.
+///
+/// This is an advisory lock held by the OS on `build/lock`,
+/// so it is released when this is dropped, or when the process dies.
+/// The file itself is left in place, as removing it would let two processes
+/// lock different files at the same path.
+#[derive(Debug)]
+pub struct BuildLock {
+    root: Utf8PathBuf,
+    _file: std::fs::File,
+}
+
+impl BuildLock {
+    /// takes the lock, failing right away if someone else has it
+    pub fn try_acquire(root: impl AsRef<Utf8Path>) -> Result<Self, LockError> {
+        let path = root.as_ref().join(LOCK_FILE);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {
+                // only for the error message of whoever waits on us
+                file.set_len(0)?;
+                file.rewind()?;
+                write!(file, "pid {}", std::process::id())?;
+                Ok(Self {
+                    root: root.as_ref().to_path_buf(),
+                    _file: file,
+                })
+            }
+            Err(std::fs::TryLockError::WouldBlock) => {
+                let holder = std::fs::read_to_string(&path).unwrap_or_default();
+                Err(LockError::Locked(holder))
+            }
+            Err(std::fs::TryLockError::Error(err)) => Err(err.into()),
+        }
+    }
+
+    /// takes the lock, waiting up to `timeout` for someone else to release it
+    pub fn acquire(root: impl AsRef<Utf8Path>, timeout: Duration) -> Result<Self, LockError> {
+        let start = std::time::Instant::now();
+        loop {
+            match Self::try_acquire(&root) {
+                Err(LockError::Locked(_)) if start.elapsed() < timeout => {
+                    std::thread::sleep(RETRY_EVERY);
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// root of the project this locks
+    pub fn root(&self) -> &Utf8Path {
+        &self.root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+
+        let lock = BuildLock::try_acquire(root).unwrap();
+        assert!(root.join(LOCK_FILE).exists());
+        assert!(matches!(
+            BuildLock::try_acquire(root),
+            Err(LockError::Locked(_))
+        ));
+        assert!(matches!(
+            BuildLock::acquire(root, Duration::from_millis(250)),
+            Err(LockError::Locked(_))
+        ));
+
+        drop(lock);
+        BuildLock::try_acquire(root).unwrap();
+    }
+
+    #[test]
+    fn test_leftover_lock_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+
+        // eg. left behind by a build that crashed
+        std::fs::create_dir_all(root.join("build")).unwrap();
+        std::fs::write(root.join(LOCK_FILE), "pid 1").unwrap();
+
+        BuildLock::try_acquire(root).unwrap();
+    }
+}
@@ -1,4 +1,10 @@
+pub mod cache;
 pub mod compile;
+pub mod directory;
+pub mod full;
+mod html;
+pub mod lock;
 pub mod partial;
 pub mod shallow;
 mod shallow_typst;
+pub mod tags;
@@ -1,13 +1,16 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use camino::Utf8Path;
 use miette::Diagnostic;
 use thiserror::Error;
 
 use crate::{
-    build::shallow::{ShallowError, shallow},
+    build::{
+        lock::BuildLock,
+        shallow::{ShallowError, shallow},
+    },
     config::Config,
     link, node,
+    storage::{Storage, StorageError},
 };
 
 #[derive(Debug, Error, Diagnostic)]
@@ -22,98 +25,175 @@ pub enum PartialError {
     IoError(#[from] std::io::Error),
 
     #[error(transparent)]
-    TomlSerializeError(#[from] toml::ser::Error),
+    #[diagnostic(transparent)]
+    StorageError(#[from] StorageError),
 }
 
+/// the file of the node `id` (which links come from),
+/// looked up in `storage` as it only changes when built, or in `nodes` if it is new
+fn source_file(
+    storage: &dyn Storage,
+    nodes: &node::Db,
+    config: &Config,
+    id: &node::Id,
+) -> Result<node::File, PartialError> {
+    let node = match storage.node_by_id(id)? {
+        Some(node) => node,
+        None => nodes.find_from_id(id, config)?.clone(),
+    };
+    Ok(node::File {
+        id: node.id,
+        path: node.path,
+    })
+}
+
+/// builds `file`, and the files whose links to it changed.
+/// the databases are saved in `storage` before building those,
+/// as the typst plugin has to see the new nodes.
 pub fn partial(
-    root: impl AsRef<Utf8Path>,
+    lock: &BuildLock,
     config: &Config,
+    storage: &mut dyn Storage,
     nodes: &mut node::Db,
     links: &mut link::Db,
     file: &node::File,
     compile: bool,
 ) -> Result<(), PartialError> {
-    // first shallow myself
-    shallow(&root, config, nodes, links, file, compile)?;
+    let root = lock.root();
+    let old_sub_ids: Vec<node::Id> = nodes
+        .nodes
+        .iter()
+        .filter(|n| n.parent.as_ref().is_some_and(|p| p.id == file.id))
+        .map(|n| n.id.clone())
+        .collect();
+
+    // how links to me showed up, so that we know if they have to be compiled again
+    let old_displays: HashMap<node::Id, (String, BTreeMap<String, String>)> = nodes
+        .nodes
+        .iter()
+        .filter(|n| n.id == file.id || old_sub_ids.contains(&n.id))
+        .map(|n| (n.id.clone(), (n.title.clone(), n.display.clone())))
+        .collect();
+
+    let old_dirs: HashSet<node::Id> = nodes
+        .nodes
+        .iter()
+        .filter(|n| n.kind == node::NodeKind::Directory)
+        .map(|n| n.id.clone())
+        .collect();
 
-    let mut dependants: Vec<node::File> = vec![];
+    // first shallow myself
+    shallow(root, config, nodes, links, file, compile)?;
 
     // find all ghosts that would be updated
-    // also i might have changed title, which we cannot know.
+    // also i might have changed title or display forms,
     // so find all nodes that DONT have an alias and that point to me.
 
-    let mut file_parts_cache: HashMap<link::FilePart, Option<&node::Node>> = HashMap::new();
-    let mut node_id_cache: HashMap<node::Id, &node::Node> = HashMap::new();
+    // links to my sub-document nodes count as links to me
+    let is_me = |node: &node::Node| {
+        node.id == file.id || node.parent.as_ref().is_some_and(|p| p.id == file.id)
+    };
+    let my_ids: Vec<node::Id> = nodes
+        .nodes
+        .iter()
+        .filter(|n| is_me(n))
+        .map(|n| n.id.clone())
+        .collect();
+    let redisplayed: Vec<node::Id> = nodes
+        .nodes
+        .iter()
+        .filter(|n| is_me(n))
+        .filter(|n| {
+            old_displays
+                .get(&n.id)
+                .is_none_or(|(title, display)| *title != n.title || *display != n.display)
+        })
+        .map(|n| n.id.clone())
+        .collect();
+
+    // the nodes this build changed, everything else is as in the storage
+    let fresh: Vec<&node::Node> = nodes
+        .nodes
+        .iter()
+        .filter(|n| is_me(n) || n.kind == node::NodeKind::Directory)
+        .collect();
+
+    // what each ghost resolves to now, if it's me or a new directory
+    let mut resolved: HashMap<link::FilePart, Option<node::Id>> = HashMap::new();
+    let mut seen: HashSet<node::Id> = HashSet::new();
+    let mut dependants: Vec<node::File> = vec![];
 
     for link in &mut links.links {
-        if let link::To::Ghost(filepart) = &link.to {
-            let node = match file_parts_cache.get(filepart) {
-                Some(Some(n)) => *n,
-                Some(None) => {
-                    continue;
+        let depends = match &link.to {
+            link::To::Ghost(filepart) => {
+                if !resolved.contains_key(filepart) {
+                    let name = match filepart {
+                        link::FilePart::Name(name) | link::FilePart::PathAndName(_, name) => name,
+                    };
+                    // only the nodes with that name can be found, so it's enough to look at them
+                    let mut candidates: Vec<node::Node> = storage
+                        .nodes_by_name(name)?
+                        .into_iter()
+                        .filter(|n| !is_me(n) && n.kind != node::NodeKind::Directory)
+                        .collect();
+                    candidates.extend(
+                        fresh
+                            .iter()
+                            .filter(|n| n.names.contains(name))
+                            .map(|n| (*n).clone()),
+                    );
+                    let candidates = node::Db { nodes: candidates };
+
+                    let id = match candidates.find_from_filepart(root, filepart, config) {
+                        // i might also be the first file in a directory someone links to
+                        Ok(n)
+                            if is_me(n)
+                                || (n.kind == node::NodeKind::Directory
+                                    && !old_dirs.contains(&n.id)) =>
+                        {
+                            Some(n.id.clone())
+                        }
+                        Ok(_) | Err(node::Error::NameNotFound(_)) => None,
+                        Err(err) => return Err(err.into()),
+                    };
+                    resolved.insert(filepart.clone(), id);
                 }
-                None => match nodes.find_from_filepart(&root, filepart, config) {
-                    Ok(n) => {
-                        file_parts_cache.insert(filepart.clone(), Some(n));
-                        n
-                    }
-                    Err(node::Error::NameNotFound(_)) => {
-                        file_parts_cache.insert(filepart.clone(), None);
-                        continue;
+                match resolved.get(filepart).cloned().flatten() {
+                    Some(id) => {
+                        // NOTE: we don't even really need to do this, as it is done later on by shallow.
+                        link.to = link::To::Id(id);
+                        true
                     }
-                    Err(err) => return Err(err.into()),
-                },
-            };
-
-            if node.id != file.id {
-                continue;
-            }
-
-            // NOTE: we don't even really need to do this, as it is done later on by shallow.
-            link.to = link::To::Id(file.id.clone());
-
-            let other = match node_id_cache.get(&link.from) {
-                Some(n) => *n,
-                None => {
-                    let n = nodes.find_from_id(&link.from, config)?;
-                    node_id_cache.insert(link.from.clone(), n);
-                    n
+                    None => false,
                 }
-            };
-
-            dependants.push(node::File {
-                id: other.id.clone(),
-                path: other.path.clone(),
-            });
-        } else if let link::To::Id(maybe_my_id) = &link.to
-            && compile
-            && link.alias.is_none()
-            && maybe_my_id == &file.id
-        {
-            // someone who links to me has no alias, so we might need to update their titles,
-            // but only if we also compile, as this is only a visual change
-            let other = match node_id_cache.get(&link.from) {
-                Some(n) => *n,
-                None => {
-                    let n = nodes.find_from_id(&link.from, config)?;
-                    node_id_cache.insert(link.from.clone(), n);
-                    n
-                }
-            };
+            }
+            // someone who links to me has no alias, so we need to update their titles
+            // if mine changed, but only if we also compile, as this is only a visual change.
+            // or someone links to one of my sub-document nodes that is now gone,
+            // which makes their link a ghost.
+            link::To::Id(maybe_my_id) => {
+                (compile && link.alias.is_none() && redisplayed.contains(maybe_my_id))
+                    || (old_sub_ids.contains(maybe_my_id) && !my_ids.contains(maybe_my_id))
+            }
+        };
+        if !depends {
+            continue;
+        }
 
-            dependants.push(node::File {
-                id: other.id.clone(),
-                path: other.path.clone(),
-            });
+        // built once, however many links to me it has
+        if seen.insert(link.from.clone()) {
+            dependants.push(source_file(storage, nodes, config, &link.from)?);
         }
     }
 
-    // SAVEPOINT(nodes): as dependants rely on the new node existing/being changed (if they're typst)
-    let new_nodes_toml = toml::to_string(nodes)?;
-    std::fs::write(root.as_ref().join("build/nodes.toml"), new_nodes_toml)?;
+    // SAVEPOINT(nodes, links): as dependants rely on the new node existing/being changed
+    // (if they're typst)
+    if !dependants.is_empty() {
+        storage.save(lock, nodes, links)?;
+    }
 
     for dependant in dependants {
-        shallow(&root, config, nodes, links, &dependant, compile)?;
+        shallow(root, config, nodes, links, &dependant, compile)?;
     }
 
     Ok(())
@@ -125,7 +205,7 @@ mod tests {
     use camino::Utf8PathBuf;
     use tempfile::tempdir;
 
-    use crate::node::Node;
+    use crate::{node::Node, storage::toml_storage::TomlStorage};
 
     use super::*;
 
@@ -140,20 +220,16 @@ mod tests {
                 Node {
                     id: "id1".into(),
                     path: root.join("vector.typ"),
-                    kind: node::NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 Node {
                     id: "id2".into(),
                     path: root.join("gem.typ"),
-                    kind: node::NodeKind::File,
                     title: "Gaussian Elimination".into(),
                     names: vec!["gem".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
             ],
         };
@@ -163,14 +239,12 @@ mod tests {
                 link::Link {
                     from: "id1".into(),
                     to: link::To::Ghost(link::FilePart::Name("matrix".into())),
-                    location: None,
-                    alias: None,
+                    ..Default::default()
                 },
                 link::Link {
                     from: "id2".into(),
                     to: link::To::Ghost(link::FilePart::Name("matrix".into())),
-                    location: None,
-                    alias: None,
+                    ..Default::default()
                 },
             ],
         };
@@ -230,9 +304,20 @@ mod tests {
         };
 
         std::fs::create_dir(root.join("build"))?;
+        let lock = BuildLock::try_acquire(&root)?;
+        let mut storage = TomlStorage::new(&root);
+        storage.save(&lock, &nodes, &links)?;
 
         // We are not interested in compilation in this example
-        partial(&root, &config, &mut nodes, &mut links, &file, false)?;
+        partial(
+            &lock,
+            &config,
+            &mut storage,
+            &mut nodes,
+            &mut links,
+            &file,
+            false,
+        )?;
 
         assert_eq!(
             nodes.nodes,
@@ -240,29 +325,24 @@ mod tests {
                 Node {
                     id: "id1".into(),
                     path: root.join("vector.typ"),
-                    kind: node::NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 Node {
                     id: "id2".into(),
                     path: root.join("gem.typ"),
-                    kind: node::NodeKind::File,
                     title: "Gaussian Elimination".into(),
                     names: vec!["gem".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 Node {
                     id: file.id,
                     path: root.join(file.path),
-                    kind: node::NodeKind::File,
                     title: "Matrix".into(),
                     names: vec!["matrix".into(), "matrices".into()],
                     tags: vec!["linalg".into(), "matrix".into(), "linear".into()],
-                    private: false,
+                    ..Default::default()
                 }
             ]
         );
@@ -273,14 +353,12 @@ mod tests {
                 link::Link {
                     from: "id1".into(),
                     to: link::To::Id("id3".into()),
-                    location: None,
-                    alias: None
+                    ..Default::default()
                 },
                 link::Link {
                     from: "id2".into(),
                     to: link::To::Id("id3".into()),
-                    location: None,
-                    alias: None
+                    ..Default::default()
                 },
             ],
         );
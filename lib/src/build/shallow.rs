@@ -1,15 +1,16 @@
-use camino::Utf8Path;
+use std::collections::BTreeMap;
+
+use camino::{Utf8Path, Utf8PathBuf};
 use miette::Diagnostic;
 use serde::Deserialize;
 use thiserror::Error;
 
 use crate::{
-    build::{compile, shallow_typst},
+    build::{compile, directory, shallow_typst},
     config::Config,
     format::typst,
     link, node,
 };
-use shallow_typst::shallow_typst;
 
 #[derive(Debug, Error, Diagnostic)]
 pub enum ShallowError {
@@ -40,13 +41,18 @@ pub enum ShallowError {
     IoError(#[from] std::io::Error),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub(super) struct Frontmatter {
     pub(super) title: String,
     pub(super) tags: Vec<String>,
     pub(super) names: Vec<String>,
     #[serde(default)]
+    pub(super) display: BTreeMap<String, String>,
+    #[serde(default)]
     pub(super) private: bool,
+    /// everything else
+    #[serde(flatten)]
+    pub(super) fields: BTreeMap<String, node::Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -56,6 +62,178 @@ pub(super) struct TypstLink {
     pub(super) to: String,
     #[serde(default)]
     pub(super) ghost: bool,
+    #[serde(default)]
+    pub(super) rel: Option<String>,
+    /// the label linked to, eg. `omni.matrix:addition`
+    #[serde(default)]
+    pub(super) target: Option<String>,
+}
+
+/// a labelled element made into a node of its own with `omni-block`
+#[derive(Debug, Deserialize, Clone)]
+pub(super) struct TypstBlock {
+    pub(super) label: String,
+    pub(super) kind: node::NodeKind,
+    pub(super) title: String,
+    #[serde(default)]
+    pub(super) names: Vec<String>,
+    #[serde(default)]
+    pub(super) display: BTreeMap<String, String>,
+    #[serde(default)]
+    pub(super) tags: Vec<String>,
+    #[serde(default)]
+    pub(super) private: bool,
+    /// everything else
+    #[serde(flatten)]
+    pub(super) fields: BTreeMap<String, node::Value>,
+}
+
+/// What querying a file found, before it's merged into the databases.
+#[derive(Debug)]
+pub struct Extracted {
+    /// canonical
+    pub(super) path: Utf8PathBuf,
+    pub(super) frontmatter: Frontmatter,
+    pub(super) blocks: Vec<TypstBlock>,
+    pub(super) links: Vec<TypstLink>,
+    /// where each of `links` is in the file, if it's written there
+    pub(super) spans: Vec<Option<link::Span>>,
+}
+
+/// queries `file`, without touching the databases, so it can be done for many files at once
+pub fn extract(root: impl AsRef<Utf8Path>, file: &node::File) -> Result<Extracted, ShallowError> {
+    // figure out the file format (for now accept only typst) and reject invalid formats
+    let my_path_canon = root.as_ref().join(&file.path).canonicalize_utf8()?;
+    let extension = file.path.extension().ok_or(ShallowError::NoFormat)?;
+
+    if extension == "typ" {
+        shallow_typst::extract_typst(root, &my_path_canon)
+    } else {
+        Err(ShallowError::InvalidFormat(extension.to_string()))
+    }
+}
+
+/// the id of the node of the file `extracted` comes from
+fn file_id(nodes: &node::Db, file: &node::File, extracted: &Extracted) -> node::Id {
+    // WARN: this assumes that paths in build/nodes.toml are already canonical and valid
+    nodes
+        .nodes
+        .iter()
+        .find(|node| node.kind == node::NodeKind::File && node.path == extracted.path)
+        .map(|node| node.id.clone())
+        .unwrap_or(file.id.clone())
+}
+
+/// puts the nodes of `file` found in `extracted` in `nodes`, replacing the old ones
+pub fn merge_nodes(nodes: &mut node::Db, file: &node::File, extracted: &Extracted) {
+    let frontmatter = extracted.frontmatter.clone();
+    let my_id = file_id(nodes, file, extracted);
+
+    match nodes.nodes.iter_mut().find(|node| node.id == my_id) {
+        Some(node) => {
+            node.title = frontmatter.title;
+            node.names = frontmatter.names;
+            node.display = frontmatter.display;
+            node.fields = frontmatter.fields;
+            node.tags = frontmatter.tags;
+            node.private = frontmatter.private;
+        }
+        None => nodes.nodes.push(node::Node {
+            id: my_id.clone(),
+            path: extracted.path.clone(),
+            kind: node::NodeKind::File,
+            title: frontmatter.title,
+            names: frontmatter.names,
+            display: frontmatter.display,
+            tags: frontmatter.tags,
+            private: frontmatter.private,
+            parent: None,
+            fields: frontmatter.fields,
+        }),
+    }
+
+    // replace all my sub-document nodes
+    nodes
+        .nodes
+        .retain(|n| n.parent.as_ref().is_none_or(|p| p.id != my_id));
+    nodes
+        .nodes
+        .extend(extracted.blocks.iter().cloned().map(|block| node::Node {
+            id: my_id.sub(&block.label),
+            path: extracted.path.clone(),
+            kind: block.kind,
+            title: block.title,
+            names: block.names,
+            display: block.display,
+            tags: block.tags,
+            private: block.private,
+            parent: Some(node::Parent {
+                id: my_id.clone(),
+                label: block.label,
+            }),
+            fields: block.fields,
+        }));
+}
+
+/// where a queried link leads, found in `nodes` from the label it links to like the typst plugin does,
+/// so that it doesn't matter which files were built before this one.
+/// what the plugin said is kept if that's not possible, eg. for an ambiguous name.
+fn resolve(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    nodes: &node::Db,
+    link: &TypstLink,
+) -> Option<link::To> {
+    let file_part = link
+        .target
+        .as_deref()
+        .and_then(|t| t.strip_prefix("omni."))
+        .map(|t| t.split_once(':').map_or(t, |(file_part, _)| file_part))
+        .and_then(link::FilePart::from_typst_style);
+
+    if let Some(file_part) = file_part {
+        match nodes.find_from_filepart(&root, &file_part, config) {
+            Ok(node) => return Some(link::To::Id(node.id.clone())),
+            Err(node::Error::NameNotFound(_)) => return Some(link::To::Ghost(file_part)),
+            Err(_) => {}
+        }
+    }
+
+    match link.ghost {
+        false => Some(link::To::Id(link.to.clone().into())),
+        true => link::FilePart::from_typst_style(&link.to).map(link::To::Ghost),
+    }
+}
+
+/// puts the links of `file` found in `extracted` in `links`, replacing the old ones.
+/// `nodes` should already have everything they might lead to.
+pub fn merge_links(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    nodes: &node::Db,
+    links: &mut link::Db,
+    file: &node::File,
+    extracted: &Extracted,
+) {
+    let my_id = file_id(nodes, file, extracted);
+
+    // remove all links from my_id
+    links.links.retain(|l| l.from != my_id);
+
+    // add new links
+    for (l, span) in extracted.links.iter().zip(&extracted.spans) {
+        let Some(to) = resolve(&root, config, nodes, l) else {
+            continue;
+        };
+        links.links.push(link::Link {
+            from: my_id.clone(),
+            to,
+            location: None, // TODO:
+            alias: None,    // TODO:
+            rel: l.rel.clone(),
+            span: *span,
+        });
+    }
 }
 
 /// between shallow builds you should also save nodes.toml and links.toml
@@ -67,15 +245,16 @@ pub fn shallow(
     file: &node::File,
     compile: bool,
 ) -> Result<(), ShallowError> {
-    // figure out the file format (for now accept only typst) and reject invalid formats
-    let my_path_canon = root.as_ref().join(&file.path).canonicalize_utf8()?;
-    let extension = file.path.extension().ok_or(ShallowError::NoFormat)?;
+    let extracted = extract(&root, file)?;
+    merge_nodes(nodes, file, &extracted);
+    // a new file might make a new directory, which links can point to
+    directory::directories(&root, config, nodes);
+    merge_links(&root, config, nodes, links, file, &extracted);
 
-    if extension == "typ" {
-        shallow_typst(root, &my_path_canon, config, nodes, links, file, compile)?;
-    } else {
-        return Err(ShallowError::InvalidFormat(extension.to_string()));
-    }
+    // compile to html and pdf
+    if compile {
+        compile::compile_typst(&root, &extracted.path, config, false)?;
+    };
 
     Ok(())
 }
@@ -100,11 +279,9 @@ mod tests {
             nodes: vec![Node {
                 id: "id1".into(),
                 path: root.join("vector.typ"),
-                kind: node::NodeKind::File,
                 title: "Vector".into(),
                 names: vec!["vector".into()],
-                tags: vec![],
-                private: false,
+                ..Default::default()
             }],
         };
 
@@ -112,8 +289,7 @@ mod tests {
             links: vec![link::Link {
                 from: "id2".into(),
                 to: link::To::Id("id4555".into()),
-                location: None,
-                alias: None,
+                ..Default::default()
             }],
         };
 
@@ -158,20 +334,17 @@ mod tests {
                 Node {
                     id: "id1".into(),
                     path: root.join("vector.typ"),
-                    kind: node::NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 Node {
                     id: file.id,
                     path: root.join(file.path),
-                    kind: node::NodeKind::File,
                     title: "Matrix".into(),
                     names: vec!["matrix".into(), "matrices".into()],
                     tags: vec!["linalg".into(), "matrix".into(), "linear".into()],
-                    private: false,
+                    ..Default::default()
                 }
             ]
         );
@@ -182,14 +355,12 @@ mod tests {
                 link::Link {
                     from: "id2".into(),
                     to: link::To::Id("id1".into()),
-                    location: None,
-                    alias: None
+                    ..Default::default()
                 },
                 link::Link {
                     from: "id2".into(),
                     to: link::To::Ghost(link::FilePart::Name("singularity".into())),
-                    location: None,
-                    alias: None
+                    ..Default::default()
                 }
             ],
         );
@@ -200,6 +371,68 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_merge_nodes() {
+        let frontmatter = |private: bool| Frontmatter {
+            title: "Matrix".into(),
+            tags: vec![],
+            names: vec!["matrix".into()],
+            display: Default::default(),
+            private,
+            fields: Default::default(),
+        };
+        let file = node::File {
+            id: "id1".into(),
+            path: "matrix.typ".into(),
+        };
+        let extracted = |private: bool| Extracted {
+            path: "/vault/matrix.typ".into(),
+            frontmatter: frontmatter(private),
+            blocks: vec![],
+            links: vec![],
+            spans: vec![],
+        };
+
+        let mut nodes = node::Db { nodes: vec![] };
+        merge_nodes(&mut nodes, &file, &extracted(true));
+        assert_eq!(nodes.nodes.len(), 1);
+        assert!(nodes.nodes[0].private);
+
+        // the file was made public since
+        merge_nodes(&mut nodes, &file, &extracted(false));
+        assert_eq!(nodes.nodes.len(), 1);
+        assert!(!nodes.nodes[0].private);
+    }
+
+    #[test]
+    fn test_frontmatter_custom_fields() {
+        let raw = r#"{
+            "title": "Matrix",
+            "tags": [],
+            "names": ["matrix"],
+            "private": false,
+            "status": "draft",
+            "year": 2025,
+            "authors": ["me", "you"]
+        }"#;
+
+        let frontmatter: Frontmatter = serde_json::from_str(raw).unwrap();
+        assert_eq!(
+            frontmatter.fields,
+            BTreeMap::from([
+                ("status".into(), node::Value::String("draft".into())),
+                ("year".into(), node::Value::Integer(2025)),
+                (
+                    "authors".into(),
+                    node::Value::Array(vec![
+                        node::Value::String("me".into()),
+                        node::Value::String("you".into())
+                    ])
+                ),
+            ])
+        );
+    }
+
     #[test]
     fn test_shallow_build_format_fail() -> Result<(), Box<dyn std::error::Error>> {
         let tempdir = tempdir()?;
@@ -210,11 +443,9 @@ mod tests {
             nodes: vec![Node {
                 id: "id1".into(),
                 path: root.join("vector.typ"),
-                kind: node::NodeKind::File,
                 title: "Vector".into(),
                 names: vec!["vector".into()],
-                tags: vec![],
-                private: false,
+                ..Default::default()
             }],
         };
         let mut links = link::Db { links: vec![] };
@@ -1,24 +1,40 @@
-use camino::{Utf8Path, Utf8PathBuf};
+use camino::Utf8Path;
 
 use crate::{
-    build::{
-        compile::compile_typst,
-        shallow::{Frontmatter, ShallowError},
-    },
-    config::Config,
-    format::typst,
-    link, node,
+    build::shallow::{Extracted, Frontmatter, ShallowError, TypstBlock, TypstLink},
+    format::{syntax::Syntax, typst},
+    link,
 };
 
-pub fn shallow_typst(
+/// finds where each of the queried `links` is in `content`,
+/// matching them in order with the ones written in the source.
+/// links coming from other files (eg. with `#include`) are not found.
+fn link_spans(content: &str, links: &[TypstLink]) -> Vec<Option<link::Span>> {
+    let found = Syntax::Typst.find_links_in(content);
+    let mut next = 0;
+
+    links
+        .iter()
+        .map(|l| {
+            let target = l.target.as_deref()?.strip_prefix("omni.")?;
+            let (raw_file_part, raw_heading_part) = target.split_once(':').unwrap_or((target, ""));
+            let file_part = link::FilePart::from_typst_style(raw_file_part)?;
+            let heading_part = link::HeadingPart::from_typst_style(raw_heading_part);
+
+            let i = found[next..].iter().position(|(_, span)| {
+                span.file_part == file_part && span.heading_part == heading_part
+            })?;
+            next += i + 1;
+            Some(found[next - 1].0)
+        })
+        .collect()
+}
+
+/// queries the typst file at `my_path_canon` for its frontmatter, blocks and links
+pub fn extract_typst(
     root: impl AsRef<Utf8Path>,
-    my_path_canon: &Utf8PathBuf,
-    config: &Config,
-    nodes: &mut node::Db,
-    links: &mut link::Db,
-    file: &node::File,
-    compile: bool,
-) -> Result<(), ShallowError> {
+    my_path_canon: &Utf8Path,
+) -> Result<Extracted, ShallowError> {
     let frontmatter_query_params = &typst::QueryParams {
         format: typst::Format::Html,
         silent: true,
@@ -26,7 +42,7 @@ pub fn shallow_typst(
         field: Some("value"),
     };
 
-    let new_links_query_params = &typst::QueryParams {
+    let many_query_params = &typst::QueryParams {
         format: typst::Format::Html,
         silent: true,
         one: false,
@@ -34,7 +50,7 @@ pub fn shallow_typst(
     };
 
     let root_as_ref = root.as_ref();
-    let (frontmatter, new_links) = rayon::join(
+    let (frontmatter, (links, blocks)) = rayon::join(
         || {
             typst::query(
                 root_as_ref,
@@ -52,75 +68,80 @@ pub fn shallow_typst(
             })
         },
         || {
-            typst::query(
-                root_as_ref,
-                my_path_canon,
-                "<omni-link>",
-                new_links_query_params,
+            rayon::join(
+                || typst::query(root_as_ref, my_path_canon, "<omni-link>", many_query_params),
+                || {
+                    typst::query(
+                        root_as_ref,
+                        my_path_canon,
+                        "<omni-block>",
+                        many_query_params,
+                    )
+                },
             )
         },
     );
 
     let frontmatter: Frontmatter = frontmatter?;
-    let new_links: Vec<super::shallow::TypstLink> = new_links?;
-
-    // WARN: this assumes that paths in build/nodes.toml are already canonical and valid
-    let maybe_node = nodes
-        .nodes
-        .iter_mut()
-        .find(|node| &node.path == my_path_canon);
-
-    // update node, and get my id while i'm at it
-    let my_id = match maybe_node {
-        Some(node) => {
-            node.title = frontmatter.title;
-            node.names = frontmatter.names;
-            node.tags = frontmatter.tags;
+    let links: Vec<TypstLink> = links?;
+    let blocks: Vec<TypstBlock> = blocks?;
 
-            &node.id
-        }
-        None => {
-            nodes.nodes.push(node::Node {
-                id: file.id.clone(),
-                path: my_path_canon.clone(),
-                kind: node::NodeKind::File,
-                title: frontmatter.title,
-                names: frontmatter.names,
-                tags: frontmatter.tags,
-                private: frontmatter.private,
-            });
+    let content = std::fs::read_to_string(my_path_canon)?;
+    let spans = link_spans(&content, &links);
 
-            &file.id
-        }
-    };
+    Ok(Extracted {
+        path: my_path_canon.to_path_buf(),
+        frontmatter,
+        blocks,
+        links,
+        spans,
+    })
+}
 
-    // remove all links from my_id
-    links.links.retain(|l| &l.from != my_id);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // add new links
-    let new_links = new_links.iter().filter_map(|l| {
-        let to = match l.ghost {
-            false => link::To::Id(l.to.clone().into()),
-            true => {
-                let filepart = link::FilePart::from_typst_style(&l.to);
-                link::To::Ghost(filepart?)
-            }
+    #[test]
+    fn test_link_spans() {
+        let content = "= Vectors\n\nsee @omni.matrix and @omni.matrix:addition,\nthen #omni-link(<omni.linalg.vector>, rel: \"example\")[vectors] and @omni.matrix.";
+        let typst_link = |target: Option<&str>| TypstLink {
+            content: String::new(),
+            to: String::new(),
+            ghost: false,
+            rel: None,
+            target: target.map(|t| t.to_string()),
         };
+        let links = [
+            typst_link(Some("omni.matrix")),
+            typst_link(Some("omni.matrix:addition")),
+            // from an included file
+            typst_link(Some("omni.tensor")),
+            typst_link(Some("omni.linalg.vector")),
+            typst_link(Some("omni.matrix")),
+            typst_link(None),
+        ];
 
-        Some(link::Link {
-            from: my_id.clone(),
-            to,
-            location: None, // TODO:
-            alias: None,    // TODO:
-        })
-    });
-
-    links.links.extend(new_links);
-
-    // compile to html and pdf
-    if compile {
-        compile_typst(root, my_path_canon, config)?;
-    };
-
-    Ok(())
+        let span = |start, end, line, column| {
+            Some(link::Span {
+                start,
+                end,
+                line,
+                column,
+                end_column: column + end - start,
+            })
+        };
+        assert_eq!(
+            link_spans(content, &links),
+            [
+                span(15, 27, 2, 4),
+                span(32, 53, 2, 21),
+                None,
+                span(71, 91, 3, 16),
+                span(122, 134, 3, 67),
+                None,
+            ]
+        );
+        assert_eq!(&content[71..91], "<omni.linalg.vector>");
+    }
 }
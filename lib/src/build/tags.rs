@@ -0,0 +1,189 @@
+use std::collections::HashSet;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::{build::html, config::Config, node, tag};
+
+/// where the tag pages go, relative to the root.
+/// it is reserved to omni, so that it never holds the output of a note.
+pub const TAGS_DIR: &str = "build/_omni/tags";
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum TagsError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+/// where the page of a tag ends up, eg. `build/_omni/tags/math/linalg.html`
+pub fn tag_page(root: &Utf8Path, name: &str) -> Utf8PathBuf {
+    root.join(TAGS_DIR).join(format!("{name}.html"))
+}
+
+/// where the page listing the top level tags ends up
+pub fn index_page(root: &Utf8Path) -> Utf8PathBuf {
+    root.join(TAGS_DIR).with_extension("html")
+}
+
+fn tag_item(root: &Utf8Path, from: &Utf8Path, tag: &tag::Tag) -> String {
+    format!(
+        "<a href=\"{}\">#{}</a> ({})",
+        html::escape(&html::href(from, &tag_page(root, &tag.name))),
+        html::escape(&tag.name),
+        tag.nodes.len()
+    )
+}
+
+/// the page of a tag, listing the tags nested inside it and all of its nodes
+pub fn page(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    nodes: &node::Db,
+    tags: &tag::Db,
+    tag: &tag::Tag,
+) -> String {
+    let root = root.as_ref();
+    let page = tag_page(root, &tag.name);
+
+    let children = tags
+        .children(Some(&tag.name))
+        .map(|child| tag_item(root, &page, child))
+        .collect();
+
+    let mut tagged: Vec<&node::Node> = tag
+        .nodes
+        .iter()
+        .filter_map(|id| nodes.find_from_id(id, config).ok())
+        .collect();
+    tagged.sort_by(|a, b| a.title.cmp(&b.title));
+    let tagged = tagged
+        .into_iter()
+        .map(|node| html::node_item(&html::node_href(root, config, &page, node), node))
+        .collect();
+
+    html::listing_page(
+        &format!("#{}", tag.name),
+        &[("tags", children), ("nodes", tagged)],
+    )
+}
+
+/// the tag pages found in `dir`, eg. written by a previous build
+fn old_pages(dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>, TagsError> {
+    let mut pages = vec![];
+    let mut to_visit = vec![dir.to_path_buf()];
+    while let Some(current) = to_visit.pop() {
+        if !current.is_dir() {
+            continue;
+        }
+        for entry in current.read_dir_utf8()? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                to_visit.push(entry.into_path());
+            } else if entry.path().extension() == Some("html") {
+                pages.push(entry.into_path());
+            }
+        }
+    }
+    Ok(pages)
+}
+
+/// writes the page of each tag, and one listing the top level tags.
+/// the pages of tags that are gone are removed.
+pub fn write_pages(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    nodes: &node::Db,
+    tags: &tag::Db,
+) -> Result<(), TagsError> {
+    let root = root.as_ref();
+
+    let mut written = HashSet::new();
+    for tag in &tags.tags {
+        let out = tag_page(root, &tag.name);
+        if let Some(parent) = out.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&out, page(root, config, nodes, tags, tag))?;
+        written.insert(out);
+    }
+
+    for old in old_pages(&root.join(TAGS_DIR))? {
+        if !written.contains(&old) {
+            std::fs::remove_file(&old)?;
+            // only removes the directories left empty
+            if let Some(parent) = old.parent() {
+                let _ = std::fs::remove_dir(parent);
+            }
+        }
+    }
+
+    let index = index_page(root);
+    let items = tags
+        .children(None)
+        .map(|tag| tag_item(root, &index, tag))
+        .collect();
+    if let Some(parent) = index.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(index, html::listing_page("tags", &[("", items)]))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_page() {
+        let nodes = node::Db {
+            nodes: vec![node::Node {
+                id: "id1".into(),
+                path: "/vault/math/spectral.typ".into(),
+                title: "Spectral theorem".into(),
+                tags: vec!["math/linalg".into()],
+                ..Default::default()
+            }],
+        };
+        let tags = tag::Db::from_nodes(&nodes);
+        let math = tags.find("math").unwrap();
+
+        let page = page("/vault", &Config::default(), &nodes, &tags, math);
+        assert!(page.contains("<li><a href=\"math/linalg.html\">#math/linalg</a> (1)</li>"));
+        assert!(page.contains(
+            "<li><a href=\"../../math/spectral.html\">Spectral theorem</a> <span class=\"tag\">#math/linalg</span></li>"
+        ));
+    }
+
+    #[test]
+    fn test_write_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+
+        // the output of a note at `<root>/tags/math.typ`
+        std::fs::create_dir_all(root.join("build/tags")).unwrap();
+        std::fs::write(root.join("build/tags/math.html"), "note").unwrap();
+
+        let mut nodes = node::Db {
+            nodes: vec![node::Node {
+                id: "id1".into(),
+                path: root.join("spectral.typ"),
+                title: "Spectral theorem".into(),
+                tags: vec!["math/linalg".into()],
+                ..Default::default()
+            }],
+        };
+        let config = Config::default();
+        write_pages(root, &config, &nodes, &tag::Db::from_nodes(&nodes)).unwrap();
+        assert!(tag_page(root, "math/linalg").exists());
+
+        nodes.nodes[0].tags = vec!["physics".into()];
+        write_pages(root, &config, &nodes, &tag::Db::from_nodes(&nodes)).unwrap();
+        assert!(!tag_page(root, "math/linalg").exists());
+        assert!(!tag_page(root, "math").exists());
+        assert!(tag_page(root, "physics").exists());
+        assert!(index_page(root).exists());
+        assert!(root.join("build/tags/math.html").exists());
+    }
+}
@@ -1,8 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use camino::{Utf8Path, Utf8PathBuf};
 use miette::Diagnostic;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 #[cfg(target_arch = "wasm32")]
 use std::hash::{BuildHasherDefault, DefaultHasher};
 use thiserror::Error;
@@ -11,24 +11,42 @@ static OMNI_TOML: &str = "omni.toml";
 
 /// config contained in `omni.toml`,
 /// which also counts as project root.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default, Clone)]
 pub struct Config {
     pub project: Project,
 
     #[serde(default)]
     pub typst: Typst,
 
+    #[serde(default)]
+    pub ids: Ids,
+
+    #[serde(default)]
+    pub storage: Storage,
+
     // we need a non-random hasher because wasi doesn't support having a random seed
     #[cfg(target_arch = "wasm32")]
-    #[serde(default)]
+    #[serde(default, serialize_with = "sorted")]
     pub dir_aliases: HashMap<String, Utf8PathBuf, BuildHasherDefault<DefaultHasher>>,
 
     #[cfg(not(target_arch = "wasm32"))]
-    #[serde(default)]
+    #[serde(default, serialize_with = "sorted")]
     pub dir_aliases: HashMap<String, Utf8PathBuf>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// the aliases by name, as a HashMap's order changes from run to run,
+/// which would change the hash of the config in `build/cache.toml`
+fn sorted<S: Serializer, H>(
+    aliases: &HashMap<String, Utf8PathBuf, H>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    aliases
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct Project {
     pub name: String,
 
@@ -46,27 +64,91 @@ impl Default for Project {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default, Clone)]
 pub struct Typst {
     #[serde(default)]
     pub output_format: TypstOutputFormat,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum TypstOutputFormat {
     Html,
     Pdf,
     #[serde(rename = "html+pdf")]
+    #[default]
     HtmlAndPdf,
 }
 
-impl Default for TypstOutputFormat {
+/// how the ids of new files are made, in `[ids]`
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Ids {
+    #[serde(default)]
+    pub strategy: IdStrategy,
+
+    /// strftime pattern used by the `pattern` strategy, eg. `%Y%m%d`
+    #[serde(default = "default_id_pattern")]
+    pub pattern: String,
+
+    /// how many random hex digits follow the pattern
+    #[serde(default = "default_id_random_digits")]
+    pub random_digits: usize,
+}
+
+fn default_id_pattern() -> String {
+    String::from("%Y%m%d")
+}
+
+fn default_id_random_digits() -> usize {
+    4
+}
+
+impl Default for Ids {
     fn default() -> Self {
-        Self::HtmlAndPdf
+        Self {
+            strategy: Default::default(),
+            pattern: default_id_pattern(),
+            random_digits: default_id_random_digits(),
+        }
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum IdStrategy {
+    /// `YYYYMMDDHHMM`, plus a hex counter if the minute is taken.
+    /// collides easily when notes are made on more than one machine.
+    #[default]
+    Timestamp,
+    /// eg. `01JA2Z3XK4M5N6P7Q8R9S0T1V2`
+    Ulid,
+    /// eg. `0192a3b4-c5d6-7e8f-9a0b-1c2d3e4f5a6b`
+    #[serde(rename = "uuidv7")]
+    UuidV7,
+    /// the file name, eg. `spectral-theorem`, plus a counter if it's taken
+    Slug,
+    /// `pattern` formatted with the current time, plus `random_digits` random hex digits
+    Pattern,
+}
+
+/// where the build databases are kept, in `[storage]`
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default, Clone)]
+pub struct Storage {
+    #[serde(default)]
+    pub backend: StorageBackend,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// `build/nodes.toml` and `build/links.toml`
+    #[default]
+    Toml,
+    /// `build/omni.db`, needs omni to be built with the `sqlite` feature.
+    /// `build/nodes.toml` is still written for the typst plugin.
+    Sqlite,
+}
+
 #[derive(Error, Debug, Diagnostic)]
 pub enum Error {
     #[error("the pwd provided is not a directory. the developer did something wrong!")]
@@ -91,12 +173,113 @@ pub fn find_project_root(pwd: impl AsRef<Utf8Path>) -> Result<Utf8PathBuf, Error
     Err(Error::NoProjectRoot)
 }
 
+/// directories never looked through for projects, on top of the hidden ones
+const SKIPPED_DIRS: [&str; 3] = ["build", "node_modules", "target"];
+
+/// how deep inside `dir` projects are looked for
+const MAX_DEPTH: usize = 6;
+
+/// finds all project roots in `dir`:
+/// the one `dir` is in, if there is one, otherwise the ones found by looking through it.
+/// projects are not looked for inside other projects, nor in hidden, skipped or unreadable
+/// directories, nor deeper than [`MAX_DEPTH`].
+pub fn find_project_roots(dir: impl AsRef<Utf8Path>) -> Result<Vec<Utf8PathBuf>, Error> {
+    match find_project_root(&dir) {
+        Ok(root) => return Ok(vec![root]),
+        Err(Error::NoProjectRoot) => {}
+        Err(err) => return Err(err),
+    }
+
+    let mut roots = vec![];
+    let mut to_visit = vec![(dir.as_ref().canonicalize_utf8()?, 0)];
+    while let Some((current, depth)) = to_visit.pop() {
+        if current.join(OMNI_TOML).exists() {
+            roots.push(current);
+            continue;
+        }
+        if depth == MAX_DEPTH {
+            continue;
+        }
+
+        let Ok(entries) = current.read_dir_utf8() else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let skipped = name.starts_with('.') || SKIPPED_DIRS.contains(&name);
+            if !skipped && entry.file_type().is_ok_and(|t| t.is_dir()) {
+                to_visit.push((entry.into_path(), depth + 1));
+            }
+        }
+    }
+    roots.sort();
+
+    Ok(roots)
+}
+
 #[cfg(test)]
 mod tests {
     use tempfile::tempdir;
 
     use super::*;
 
+    #[test]
+    fn test_sorted_dir_aliases() {
+        let names = [
+            "zeta", "alpha", "mu", "beta", "omega", "gamma", "kappa", "delta",
+        ];
+        let config = Config {
+            dir_aliases: names
+                .iter()
+                .map(|n| (n.to_string(), Utf8PathBuf::from(*n)))
+                .collect(),
+            ..Default::default()
+        };
+
+        let serialized = toml::to_string(&config).unwrap();
+        let found: Vec<&str> = serialized
+            .lines()
+            .skip_while(|l| *l != "[dir_aliases]")
+            .skip(1)
+            .filter_map(|l| l.split_once(" = ").map(|(name, _)| name))
+            .collect();
+        let mut names = names.to_vec();
+        names.sort();
+        assert_eq!(found, names);
+    }
+
+    #[test]
+    fn test_find_project_roots() {
+        let dir = tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path())
+            .unwrap()
+            .canonicalize_utf8()
+            .unwrap();
+
+        for project in [
+            "notes",
+            "work/wiki",
+            "notes/nested",
+            ".hidden",
+            "code/node_modules/pkg",
+            "a/b/c/d/e/f/too-deep",
+        ] {
+            std::fs::create_dir_all(dir.join(project)).unwrap();
+            std::fs::write(dir.join(project).join(OMNI_TOML), "").unwrap();
+        }
+        std::fs::create_dir_all(dir.join("other/empty")).unwrap();
+
+        assert_eq!(
+            find_project_roots(&dir).unwrap(),
+            [dir.join("notes"), dir.join("work/wiki")]
+        );
+        assert_eq!(
+            find_project_roots(dir.join("notes/nested")).unwrap(),
+            [dir.join("notes/nested")]
+        );
+        assert!(find_project_roots(dir.join("other")).unwrap().is_empty());
+    }
+
     #[test]
     fn test_config_deserialization_all_specified() {
         let raw_toml = r#"
@@ -107,6 +290,13 @@ mod tests {
         [typst]
         output_format = "html+pdf"
 
+        [ids]
+        strategy = "pattern"
+        pattern = "%Y-%m-%d"
+
+        [storage]
+        backend = "sqlite"
+
         [dir_aliases]
         linalg = "Linear Algebra"
         "#;
@@ -122,6 +312,14 @@ mod tests {
                 typst: Typst {
                     output_format: TypstOutputFormat::HtmlAndPdf,
                 },
+                ids: Ids {
+                    strategy: IdStrategy::Pattern,
+                    pattern: "%Y-%m-%d".into(),
+                    random_digits: 4,
+                },
+                storage: Storage {
+                    backend: StorageBackend::Sqlite,
+                },
                 dir_aliases: HashMap::from([("linalg".into(), "Linear Algebra".into())])
             }
         )
@@ -143,6 +341,8 @@ mod tests {
                     prefix_dir: None,
                 },
                 typst: Typst::default(),
+                ids: Ids::default(),
+                storage: Storage::default(),
                 dir_aliases: HashMap::new()
             }
         )
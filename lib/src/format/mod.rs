@@ -1,3 +1,5 @@
+pub mod outline;
+pub mod syntax;
 pub mod typst;
 
 use camino::{Utf8Path, Utf8PathBuf};
@@ -0,0 +1,441 @@
+use crate::{format::syntax::Syntax, link::HeadingPart};
+
+/// A heading found in a source file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Heading {
+    /// number of `=` in front of the heading
+    pub level: usize,
+    pub title: String,
+    /// slug-ized version of the title
+    pub slug: String,
+    /// label attached to the heading,
+    /// eg. `= Addition <addition>` in typst, `# Addition {#addition}` in markdown
+    /// or a `:CUSTOM_ID:` property in org
+    pub label: Option<String>,
+    /// slugs of all parent headings, from the outermost one
+    pub parents: Vec<String>,
+    /// line where the heading is (0 indexed)
+    pub line: usize,
+    /// line where the section of this heading ends (exclusive)
+    pub end_line: usize,
+}
+
+/// A label that is not attached to a heading,
+/// eg. `$ a + b $ <sum>` in typst or `<<sum>>` in org
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+    pub name: String,
+    /// line where the label is (0 indexed)
+    pub line: usize,
+}
+
+/// Headings and labels of a file, parsed straight from the source.
+///
+/// This does not evaluate the file at all, so for example in typst,
+/// headings made with `#heading(...)` or coming from other files (eg. with `#include`)
+/// will not be found.
+#[derive(Debug, PartialEq, Default)]
+pub struct Outline {
+    pub headings: Vec<Heading>,
+    pub labels: Vec<Label>,
+}
+
+fn is_typst_label_char(c: char) -> bool {
+    // https://typst.app/docs/reference/foundations/label#syntax
+    c.is_alphanumeric() || matches!(c, '_' | '-' | ':' | '.')
+}
+
+/// labels starting with `omni-` are used internally (eg. `<omni-frontmatter>`)
+fn is_user_label(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(is_typst_label_char) && !name.starts_with("omni-")
+}
+
+/// splits `Title <label>` into `("Title", Some("label"))`
+fn split_typst_label(raw: &str) -> (&str, Option<&str>) {
+    if let Some(without_end) = raw.strip_suffix('>')
+        && let Some(start) = without_end.rfind('<')
+    {
+        let name = &without_end[start + 1..];
+        if is_user_label(name) {
+            return (without_end[..start].trim_end(), Some(name));
+        }
+    }
+
+    (raw, None)
+}
+
+/// splits `Title {#label}` into `("Title", Some("label"))`
+fn split_markdown_label(raw: &str) -> (&str, Option<&str>) {
+    if let Some(without_end) = raw.strip_suffix('}')
+        && let Some(start) = without_end.rfind("{#")
+    {
+        let name = &without_end[start + 2..];
+        if !name.is_empty() && !name.contains(char::is_whitespace) {
+            return (without_end[..start].trim_end(), Some(name));
+        }
+    }
+
+    (raw, None)
+}
+
+/// removes org tags from a heading, eg. `Title :math:linalg:` becomes `Title`
+fn strip_org_tags(raw: &str) -> &str {
+    if let Some(without_end) = raw.strip_suffix(':')
+        && let Some(start) = without_end.rfind(char::is_whitespace)
+        && without_end[start..].trim_start().starts_with(':')
+    {
+        return without_end[..start].trim_end();
+    }
+
+    raw
+}
+
+fn find_typst_labels(line: &str) -> impl Iterator<Item = &str> {
+    line.char_indices()
+        .filter(|(_, c)| *c == '<')
+        .filter_map(move |(start, _)| {
+            let preceded_ok = line[..start]
+                .chars()
+                .next_back()
+                .is_none_or(|c| c.is_whitespace() || matches!(c, ']' | ')' | '}' | '$'));
+            if !preceded_ok {
+                return None;
+            }
+
+            let len = line[start + 1..].find('>')?;
+            let name = &line[start + 1..start + 1 + len];
+            is_user_label(name).then_some(name)
+        })
+}
+
+fn find_org_targets(line: &str) -> impl Iterator<Item = &str> {
+    line.match_indices("<<").filter_map(move |(start, _)| {
+        let len = line[start + 2..].find(">>")?;
+        let name = &line[start + 2..start + 2 + len];
+        (!name.is_empty() && !name.starts_with('<')).then_some(name)
+    })
+}
+
+/// returns the level and the rest of the line if the line is a heading
+fn heading_level(line: &str, syntax: Syntax) -> Option<(usize, &str)> {
+    let (marker, line) = match syntax {
+        Syntax::Typst => ('=', line.trim_start()),
+        Syntax::Markdown => ('#', line.trim_start()),
+        // org headings must start at the beginning of the line
+        Syntax::Org => ('*', line),
+    };
+
+    let level = line.chars().take_while(|c| *c == marker).count();
+    if level > 0 && line[level..].starts_with(' ') {
+        Some((level, line[level..].trim()))
+    } else {
+        None
+    }
+}
+
+fn is_raw_block_delimiter(line: &str, syntax: Syntax, in_raw_block: bool) -> bool {
+    let trimmed = line.trim_start();
+    match syntax {
+        Syntax::Typst | Syntax::Markdown => trimmed.starts_with("```"),
+        Syntax::Org => {
+            let lower = trimmed.to_lowercase();
+            if in_raw_block {
+                lower.starts_with("#+end_")
+            } else {
+                lower.starts_with("#+begin_src") || lower.starts_with("#+begin_example")
+            }
+        }
+    }
+}
+
+impl Heading {
+    /// the full heading path to this heading, eg. `operations.addition`
+    pub fn heading_part(&self) -> HeadingPart {
+        if self.parents.is_empty() {
+            HeadingPart::Heading(self.slug.clone())
+        } else {
+            HeadingPart::PathAndHeading(self.parents.clone(), self.slug.clone())
+        }
+    }
+}
+
+impl Outline {
+    /// finds the lines (end exclusive) that a heading part points to.
+    ///
+    /// a heading part matches a heading by its label first, then by its slug
+    /// (with the parents ending in the given path),
+    /// and falls back to the line of a standalone label.
+    pub fn find(&self, heading_part: &HeadingPart) -> Option<std::ops::Range<usize>> {
+        let (path, name): (&[String], &str) = match heading_part {
+            HeadingPart::Heading(name) => (&[], name),
+            HeadingPart::PathAndHeading(path, name) => (path, name),
+        };
+
+        let by_label = self
+            .headings
+            .iter()
+            .find(|h| path.is_empty() && h.label.as_deref() == Some(name));
+        let by_slug = || {
+            self.headings
+                .iter()
+                .find(|h| h.slug == name && h.parents.ends_with(path))
+        };
+
+        if let Some(heading) = by_label.or_else(by_slug) {
+            return Some(heading.line..heading.end_line);
+        }
+
+        if !path.is_empty() {
+            return None;
+        }
+        self.labels
+            .iter()
+            .find(|l| l.name == name)
+            .map(|l| l.line..l.line + 1)
+    }
+
+    pub fn parse(source: &str, syntax: Syntax) -> Self {
+        let mut outline = Outline::default();
+
+        // indices of headings whose section hasn't ended yet
+        let mut open: Vec<usize> = vec![];
+        let mut in_raw_block = false;
+        let mut line_count = 0;
+
+        for (line_no, line) in source.lines().enumerate() {
+            line_count = line_no + 1;
+
+            if is_raw_block_delimiter(line, syntax, in_raw_block) {
+                in_raw_block = !in_raw_block;
+                continue;
+            }
+            if in_raw_block {
+                continue;
+            }
+
+            if let Some((level, rest)) = heading_level(line, syntax) {
+                let (title, label) = match syntax {
+                    Syntax::Typst => split_typst_label(rest),
+                    Syntax::Markdown => split_markdown_label(rest),
+                    Syntax::Org => (strip_org_tags(rest), None),
+                };
+
+                while let Some(&i) = open.last()
+                    && outline.headings[i].level >= level
+                {
+                    outline.headings[i].end_line = line_no;
+                    open.pop();
+                }
+
+                outline.headings.push(Heading {
+                    level,
+                    title: title.to_string(),
+                    slug: slug::slugify(title),
+                    label: label.map(|l| l.to_string()),
+                    parents: open
+                        .iter()
+                        .map(|i| outline.headings[*i].slug.clone())
+                        .collect(),
+                    line: line_no,
+                    end_line: line_no + 1,
+                });
+                open.push(outline.headings.len() - 1);
+                continue;
+            }
+
+            let labels: Vec<&str> = match syntax {
+                Syntax::Typst => find_typst_labels(line).collect(),
+                Syntax::Markdown => vec![],
+                Syntax::Org => {
+                    if let Some(custom_id) = line.trim().strip_prefix(":CUSTOM_ID:")
+                        && let Some(&i) = open.last()
+                        && outline.headings[i].label.is_none()
+                    {
+                        outline.headings[i].label = Some(custom_id.trim().to_string());
+                    }
+                    find_org_targets(line).collect()
+                }
+            };
+
+            outline.labels.extend(labels.into_iter().map(|name| Label {
+                name: name.to_string(),
+                line: line_no,
+            }));
+        }
+
+        for i in open {
+            outline.headings[i].end_line = line_count;
+        }
+
+        outline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outline_find() {
+        let source = indoc::indoc!(
+            r#"
+            = Operations <ops>
+            == Addition
+            $ a + b $ <sum>
+            = Properties
+            == Addition
+            "#
+        );
+        let outline = Outline::parse(source, Syntax::Typst);
+
+        assert_eq!(
+            outline.find(&HeadingPart::Heading("ops".into())),
+            Some(0..3)
+        );
+        assert_eq!(
+            outline.find(&HeadingPart::Heading("addition".into())),
+            Some(1..3)
+        );
+        assert_eq!(
+            outline.find(&HeadingPart::PathAndHeading(
+                vec!["properties".into()],
+                "addition".into()
+            )),
+            Some(4..5)
+        );
+        assert_eq!(
+            outline.find(&HeadingPart::Heading("sum".into())),
+            Some(2..3)
+        );
+        assert_eq!(outline.find(&HeadingPart::Heading("nope".into())), None);
+    }
+
+    #[test]
+    fn test_outline_parse() {
+        let source = indoc::indoc!(
+            r#"
+            #metadata("hello") <omni-frontmatter>
+            = Operations <ops>
+            == Addition
+            $ a + b $ <sum>
+            == Scalar Multiplication <scalar>
+            ```typ
+            = Not a heading
+            ```
+            = Properties
+            the identity is <not a label>
+            "#
+        );
+
+        let outline = Outline::parse(source, Syntax::Typst);
+
+        assert_eq!(
+            outline.headings,
+            [
+                Heading {
+                    level: 1,
+                    title: "Operations".into(),
+                    slug: "operations".into(),
+                    label: Some("ops".into()),
+                    parents: vec![],
+                    line: 1,
+                    end_line: 8,
+                },
+                Heading {
+                    level: 2,
+                    title: "Addition".into(),
+                    slug: "addition".into(),
+                    label: None,
+                    parents: vec!["operations".into()],
+                    line: 2,
+                    end_line: 4,
+                },
+                Heading {
+                    level: 2,
+                    title: "Scalar Multiplication".into(),
+                    slug: "scalar-multiplication".into(),
+                    label: Some("scalar".into()),
+                    parents: vec!["operations".into()],
+                    line: 4,
+                    end_line: 8,
+                },
+                Heading {
+                    level: 1,
+                    title: "Properties".into(),
+                    slug: "properties".into(),
+                    label: None,
+                    parents: vec![],
+                    line: 8,
+                    end_line: 10,
+                },
+            ]
+        );
+
+        assert_eq!(
+            outline.labels,
+            [Label {
+                name: "sum".into(),
+                line: 3
+            }]
+        );
+
+        assert_eq!(
+            outline.headings[2].heading_part(),
+            HeadingPart::PathAndHeading(vec!["operations".into()], "scalar-multiplication".into())
+        );
+    }
+
+    #[test]
+    fn test_outline_parse_markdown_and_org() {
+        let source = indoc::indoc!(
+            r#"
+            # Operations {#ops}
+            ## Addition
+            ```md
+            # Not a heading
+            ```
+            "#
+        );
+
+        let outline = Outline::parse(source, Syntax::Markdown);
+        assert_eq!(
+            outline
+                .headings
+                .iter()
+                .map(|h| (h.title.as_str(), h.label.as_deref(), h.end_line))
+                .collect::<Vec<_>>(),
+            [("Operations", Some("ops"), 5), ("Addition", None, 5)]
+        );
+
+        let source = indoc::indoc!(
+            r#"
+            * Operations :math:
+            :PROPERTIES:
+            :CUSTOM_ID: ops
+            :END:
+            ** Addition
+            the <<sum>> of two matrices
+            #+begin_src org
+            * Not a heading
+            #+end_src
+            "#
+        );
+
+        let outline = Outline::parse(source, Syntax::Org);
+        assert_eq!(
+            outline
+                .headings
+                .iter()
+                .map(|h| (h.title.as_str(), h.label.as_deref(), h.parents.len()))
+                .collect::<Vec<_>>(),
+            [("Operations", Some("ops"), 0), ("Addition", None, 1)]
+        );
+        assert_eq!(
+            outline.labels,
+            [Label {
+                name: "sum".into(),
+                line: 5
+            }]
+        );
+    }
+}
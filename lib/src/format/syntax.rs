@@ -0,0 +1,876 @@
+use std::ops::Range;
+
+use camino::Utf8Path;
+
+use crate::{
+    link::{FilePart, HeadingPart, Span},
+    omni_path::OmniPath,
+};
+
+/// The omni link syntax of each supported format.
+///
+/// - typst: `@omni.path.name:heading_path.heading` (the supplement is ignored),
+///   or `#omni-link(<omni.path.name:heading_path.heading>, ..)`
+/// - markdown: `[[omni:path/name#heading_path#heading|alias]]`
+/// - org: `[[omni:path/name#heading_path#heading][alias]]` or with `|alias` like markdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Typst,
+    Markdown,
+    Org,
+}
+
+/// An omni link found in a line of text.
+///
+/// ranges are in UTF-16 code units, which is what LSP positions count in.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LinkSpan {
+    /// range of the whole link inside the line
+    pub range: Range<usize>,
+    /// range of the file part inside the line, including the `omni.`/`omni:` preamble
+    pub file_range: Range<usize>,
+    pub file_part: FilePart,
+    pub heading_part: Option<HeadingPart>,
+    pub alias: Option<String>,
+    /// the relation of the link, eg. `prerequisite`
+    pub rel: Option<String>,
+}
+
+/// What is being typed right before the cursor.
+#[derive(Debug, PartialEq)]
+pub enum CompletionContext {
+    /// the file part is being typed, eg. `@omni.lin|` or `[[omni:lin|`.
+    /// `typed` is everything after the `@` or `[[`.
+    File { typed: String },
+    /// the heading part is being typed, eg. `@omni.matrix:ope|`.
+    /// `typed` is everything after the first `:` or `#`.
+    Heading { file_part: FilePart, typed: String },
+    /// a tag is being typed in the frontmatter,
+    /// eg. `tags: ("math/lin|` or `tags: [math/lin|` or `#+filetags: :math/lin|`.
+    /// `typed` is the part of the tag typed so far.
+    Tag { typed: String },
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '/')
+}
+
+/// the tag chars right before the cursor
+fn typed_tag(before_cursor: &str) -> &str {
+    let start = before_cursor
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_tag_char(*c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(before_cursor.len());
+    &before_cursor[start..]
+}
+
+fn is_typst_ref_char(c: char) -> bool {
+    // https://typst.app/docs/reference/foundations/label#syntax
+    c.is_alphanumeric() || matches!(c, '_' | '-' | ':' | '.')
+}
+
+/// parses `path/name`
+fn file_part_from_wiki_style(raw: &str) -> Option<FilePart> {
+    let mut splits: Vec<_> = raw.split('/').map(|s| s.to_string()).collect();
+    let name = splits.pop()?;
+    if name.is_empty() || splits.iter().any(|s| s.is_empty()) {
+        None
+    } else if splits.is_empty() {
+        Some(FilePart::Name(name))
+    } else {
+        Some(FilePart::PathAndName(splits, name))
+    }
+}
+
+/// parses `heading_path#heading`
+fn heading_part_from_wiki_style(raw: &str) -> Option<HeadingPart> {
+    let mut splits: Vec<_> = raw
+        .split('#')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect();
+    let heading = splits.pop()?;
+    if splits.is_empty() {
+        Some(HeadingPart::Heading(heading))
+    } else {
+        Some(HeadingPart::PathAndHeading(splits, heading))
+    }
+}
+
+/// What a typst line starts in, as comments and raw blocks can span many lines.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+enum TypstContext {
+    #[default]
+    Markup,
+    /// inside `/* */`, which can be nested
+    Comment(usize),
+    /// inside raw text opened by this many backticks
+    Raw(usize),
+}
+
+/// the number of `c` in a row starting at `i`
+fn run_of(chars: &[char], i: usize, c: char) -> usize {
+    chars[i..].iter().take_while(|&&other| other == c).count()
+}
+
+/// finds the links in a typst line, skipping comments and raw text,
+/// starting (and leaving) `context` as it is at the end of the line.
+fn find_typst_links(chars: &[char], context: &mut TypstContext) -> Vec<LinkSpan> {
+    let mut spans = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        match *context {
+            TypstContext::Comment(depth) => {
+                if chars[i..].starts_with(&['/', '*']) {
+                    *context = TypstContext::Comment(depth + 1);
+                    i += 2;
+                } else if chars[i..].starts_with(&['*', '/']) {
+                    *context = match depth {
+                        1 => TypstContext::Markup,
+                        _ => TypstContext::Comment(depth - 1),
+                    };
+                    i += 2;
+                } else {
+                    i += 1;
+                }
+                continue;
+            }
+            TypstContext::Raw(fence) => {
+                let run = run_of(chars, i, '`');
+                if run == fence {
+                    *context = TypstContext::Markup;
+                }
+                i += run.max(1);
+                continue;
+            }
+            TypstContext::Markup => {}
+        }
+
+        match chars[i] {
+            // escaped, eg. `\@omni.matrix`
+            '\\' => {
+                i += 2;
+                continue;
+            }
+            // but not in urls, eg. `https://`
+            '/' if chars.get(i + 1) == Some(&'/') && (i == 0 || chars[i - 1] != ':') => break,
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                *context = TypstContext::Comment(1);
+                i += 2;
+                continue;
+            }
+            '`' => {
+                let run = run_of(chars, i, '`');
+                // two backticks are just empty raw text
+                if run != 2 {
+                    *context = TypstContext::Raw(run);
+                }
+                i += run;
+                continue;
+            }
+            _ => {}
+        }
+
+        // `@omni.matrix`, or `<omni.matrix>` as the target of `#omni-link`
+        let is_label = chars[i] == '<';
+        if !(chars[i] == '@' || is_label) || (i > 0 && is_typst_ref_char(chars[i - 1])) {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        let mut end = i + 1;
+        while end < chars.len() && is_typst_ref_char(chars[end]) {
+            end += 1;
+        }
+        if is_label {
+            if chars.get(end) != Some(&'>') {
+                i += 1;
+                continue;
+            }
+            i = end + 1;
+        } else {
+            // typst refs cannot end with `.` or `:`
+            while end > start + 1 && matches!(chars[end - 1], '.' | ':') {
+                end -= 1;
+            }
+            i = end.max(start + 1);
+        }
+
+        let reference: String = chars[start + 1..end].iter().collect();
+        let Some(rest) = reference.strip_prefix("omni.") else {
+            continue;
+        };
+
+        let (raw_file_part, raw_heading_part) = rest.split_once(':').unwrap_or((rest, ""));
+        if raw_file_part.is_empty() {
+            continue;
+        }
+        let Some(file_part) = FilePart::from_typst_style(raw_file_part) else {
+            continue;
+        };
+
+        let file_start = start + 1;
+        spans.push(LinkSpan {
+            range: start..if is_label { end + 1 } else { end },
+            file_range: file_start..file_start + "omni.".len() + raw_file_part.chars().count(),
+            file_part,
+            heading_part: HeadingPart::from_typst_style(raw_heading_part),
+            alias: None, // by design always empty in typst
+            rel: None,
+        });
+    }
+
+    spans
+}
+
+/// parses attributes like `rel=cites year=2024`,
+/// where values with spaces are quoted, eg. `rel="cites heavily"`.
+/// anything that isn't `key=value` is skipped.
+fn parse_attributes(attributes: &str) -> Vec<(&str, String)> {
+    let mut parsed = vec![];
+    let mut rest = attributes.trim_start();
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = &rest[key_end..];
+
+        let Some(after_eq) = rest.strip_prefix('=') else {
+            rest = rest.trim_start();
+            continue;
+        };
+
+        let value = if let Some(quoted) = after_eq.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut consumed = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        consumed = i + 1;
+                        break;
+                    }
+                    c => value.push(c),
+                }
+            }
+            rest = &quoted[consumed..];
+            value
+        } else {
+            let value_end = after_eq.find(char::is_whitespace).unwrap_or(after_eq.len());
+            rest = &after_eq[value_end..];
+            after_eq[..value_end].to_string()
+        };
+
+        if !key.is_empty() {
+            parsed.push((key, value));
+        }
+        rest = rest.trim_start();
+    }
+    parsed
+}
+
+fn find_wiki_links(chars: &[char], org: bool) -> Vec<LinkSpan> {
+    let line: String = chars.iter().collect();
+    let mut spans = vec![];
+
+    // work on byte offsets, and convert them to char offsets at the end
+    let to_char = |byte: usize| line[..byte].chars().count();
+
+    let mut search_from = 0;
+    while let Some(found) = line[search_from..].find("[[") {
+        let start = search_from + found;
+        let Some(len) = line[start + 2..].find("]]") else {
+            break;
+        };
+        let mut end = start + 2 + len + 2;
+
+        // attributes right after the link, eg. `{rel=prerequisite}`
+        let mut rel = None;
+        if line[end..].starts_with('{')
+            && let Some(len) = line[end..].find('}')
+        {
+            rel = parse_attributes(&line[end + 1..end + len])
+                .into_iter()
+                .find(|(key, _)| *key == "rel")
+                .map(|(_, value)| value)
+                .filter(|value| !value.is_empty());
+            if rel.is_some() {
+                end += len + 1;
+            }
+        }
+        search_from = end;
+
+        let inner = &line[start + 2..start + 2 + len];
+        let Some(target_and_alias) = inner.strip_prefix("omni:") else {
+            continue;
+        };
+
+        let (target, alias) = match target_and_alias.split_once('|') {
+            Some((target, alias)) => (target, Some(alias)),
+            None if org => match target_and_alias.split_once("][") {
+                Some((target, alias)) => (target, Some(alias)),
+                None => (target_and_alias, None),
+            },
+            None => (target_and_alias, None),
+        };
+
+        let (raw_file_part, raw_heading_part) = target.split_once('#').unwrap_or((target, ""));
+        let Some(file_part) = file_part_from_wiki_style(raw_file_part) else {
+            continue;
+        };
+
+        let file_start = start + 2;
+        spans.push(LinkSpan {
+            range: to_char(start)..to_char(end),
+            file_range: to_char(file_start)
+                ..to_char(file_start + "omni:".len() + raw_file_part.len()),
+            file_part,
+            heading_part: heading_part_from_wiki_style(raw_heading_part),
+            alias: alias.filter(|a| !a.is_empty()).map(|a| a.to_string()),
+            rel,
+        });
+    }
+
+    spans
+}
+
+/// converts the ranges of `spans`, found in `chars` in chars, to UTF-16 code units
+fn to_utf16(chars: &[char], spans: Vec<LinkSpan>) -> Vec<LinkSpan> {
+    let mut offsets = Vec::with_capacity(chars.len() + 1);
+    let mut offset = 0;
+    offsets.push(0);
+    for c in chars {
+        offset += c.len_utf16();
+        offsets.push(offset);
+    }
+    let convert = |range: Range<usize>| offsets[range.start]..offsets[range.end];
+
+    spans
+        .into_iter()
+        .map(|span| LinkSpan {
+            range: convert(span.range),
+            file_range: convert(span.file_range),
+            ..span
+        })
+        .collect()
+}
+
+/// the byte offset in `line` of the UTF-16 code unit at `utf16`
+fn utf16_to_byte(line: &str, utf16: usize) -> usize {
+    let mut units = 0;
+    for (byte, c) in line.char_indices() {
+        if units >= utf16 {
+            return byte;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+impl Syntax {
+    pub fn from_language_id(language_id: &str) -> Option<Self> {
+        match language_id {
+            "typst" => Some(Self::Typst),
+            "markdown" => Some(Self::Markdown),
+            "org" => Some(Self::Org),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: impl AsRef<Utf8Path>) -> Option<Self> {
+        match path.as_ref().extension()? {
+            "typ" => Some(Self::Typst),
+            "md" => Some(Self::Markdown),
+            "org" => Some(Self::Org),
+            _ => None,
+        }
+    }
+
+    /// finds all omni links in a single line
+    pub fn find_links(self, line: &str) -> Vec<LinkSpan> {
+        let chars: Vec<char> = line.chars().collect();
+        let spans = match self {
+            Syntax::Typst => find_typst_links(&chars, &mut TypstContext::default()),
+            Syntax::Markdown => find_wiki_links(&chars, false),
+            Syntax::Org => find_wiki_links(&chars, true),
+        };
+        to_utf16(&chars, spans)
+    }
+
+    /// finds all omni links in a whole file, along with where they are.
+    /// unlike [`Syntax::find_links`], this knows about comments and raw text spanning lines.
+    pub fn find_links_in(self, content: &str) -> Vec<(Span, LinkSpan)> {
+        let mut found = vec![];
+        let mut line_start = 0;
+        let mut context = TypstContext::default();
+        for (line_no, line) in content.split('\n').enumerate() {
+            let chars: Vec<char> = line.chars().collect();
+            let spans = match self {
+                Syntax::Typst => find_typst_links(&chars, &mut context),
+                Syntax::Markdown => find_wiki_links(&chars, false),
+                Syntax::Org => find_wiki_links(&chars, true),
+            };
+            for span in to_utf16(&chars, spans) {
+                found.push((
+                    Span {
+                        start: line_start + utf16_to_byte(line, span.range.start),
+                        end: line_start + utf16_to_byte(line, span.range.end),
+                        line: line_no,
+                        column: span.range.start,
+                        end_column: span.range.end,
+                    },
+                    span,
+                ));
+            }
+            line_start += line.len() + 1;
+        }
+        found
+    }
+
+    /// finds the omni link that contains the UTF-16 code unit at `column`
+    pub fn link_at(self, line: &str, column: usize) -> Option<LinkSpan> {
+        self.find_links(line)
+            .into_iter()
+            .find(|span| span.range.contains(&column))
+    }
+
+    /// figures out what kind of omni link is being typed at the end of `before_cursor`
+    pub fn completion_context(self, before_cursor: &str) -> Option<CompletionContext> {
+        self.link_completion_context(before_cursor)
+            .or_else(|| self.tag_completion_context(before_cursor))
+    }
+
+    fn tag_completion_context(self, before_cursor: &str) -> Option<CompletionContext> {
+        let typed = match self {
+            Syntax::Typst => {
+                // inside a string after `tags:`
+                let (_, after) = before_cursor.rsplit_once("tags:")?;
+                if after.matches('"').count() % 2 == 0 {
+                    return None;
+                }
+                let (_, typed) = after.rsplit_once('"')?;
+                typed
+            }
+            Syntax::Markdown => {
+                let after = before_cursor.trim_start().strip_prefix("tags:")?;
+                typed_tag(after)
+            }
+            Syntax::Org => {
+                let trimmed = before_cursor.trim_start();
+                let prefix = "#+filetags:";
+                if !trimmed
+                    .get(..prefix.len())
+                    .is_some_and(|p| p.eq_ignore_ascii_case(prefix))
+                {
+                    return None;
+                }
+                typed_tag(&trimmed[prefix.len()..])
+            }
+        };
+
+        if !typed.chars().all(is_tag_char) {
+            return None;
+        }
+        Some(CompletionContext::Tag {
+            typed: typed.to_string(),
+        })
+    }
+
+    fn link_completion_context(self, before_cursor: &str) -> Option<CompletionContext> {
+        match self {
+            Syntax::Typst => {
+                let start = before_cursor
+                    .char_indices()
+                    .rev()
+                    .take_while(|(_, c)| is_typst_ref_char(*c))
+                    .last()
+                    .map(|(i, _)| i)
+                    .unwrap_or(before_cursor.len());
+
+                if !before_cursor[..start].ends_with('@') {
+                    return None;
+                }
+
+                let typed = &before_cursor[start..];
+                match typed.strip_prefix("omni.") {
+                    Some(rest) => match rest.split_once(':') {
+                        Some((raw_file_part, raw_heading_part)) => {
+                            Some(CompletionContext::Heading {
+                                file_part: FilePart::from_typst_style(raw_file_part)?,
+                                typed: raw_heading_part.to_string(),
+                            })
+                        }
+                        None => Some(CompletionContext::File {
+                            typed: typed.to_string(),
+                        }),
+                    },
+                    None if "omni.".starts_with(typed) => Some(CompletionContext::File {
+                        typed: typed.to_string(),
+                    }),
+                    None => None,
+                }
+            }
+            Syntax::Markdown | Syntax::Org => {
+                let start = before_cursor.rfind("[[")? + 2;
+                let typed = &before_cursor[start..];
+                if typed.contains("]]") || typed.contains('|') || typed.contains(']') {
+                    return None;
+                }
+
+                match typed.strip_prefix("omni:") {
+                    Some(rest) => match rest.split_once('#') {
+                        Some((raw_file_part, raw_heading_part)) => {
+                            Some(CompletionContext::Heading {
+                                file_part: file_part_from_wiki_style(raw_file_part)?,
+                                typed: raw_heading_part.to_string(),
+                            })
+                        }
+                        None => Some(CompletionContext::File {
+                            typed: typed.to_string(),
+                        }),
+                    },
+                    None if "omni:".starts_with(typed) => Some(CompletionContext::File {
+                        typed: typed.to_string(),
+                    }),
+                    None => None,
+                }
+            }
+        }
+    }
+
+    /// formats the file part of a link, including the preamble, eg. `omni.linalg.matrix`
+    pub fn format_file_part(self, omni_path: &OmniPath) -> String {
+        match self {
+            Syntax::Typst => omni_path.as_typst_style(),
+            Syntax::Markdown | Syntax::Org => omni_path.as_wiki_style(),
+        }
+    }
+
+    /// formats the heading part of a link, eg. `operations.addition`
+    pub fn format_heading_part(self, heading_part: &HeadingPart) -> String {
+        match self {
+            Syntax::Typst => heading_part.as_typst_style(),
+            Syntax::Markdown | Syntax::Org => match heading_part {
+                HeadingPart::Heading(heading) => heading.clone(),
+                HeadingPart::PathAndHeading(path, heading) => {
+                    format!("{}#{}", path.join("#"), heading)
+                }
+            },
+        }
+    }
+
+    /// escapes `text` so that it can go inside a double quoted string,
+    /// like the title in the frontmatter of a template.
+    /// org titles are not quoted, so only line breaks are removed.
+    pub fn escape_string(self, text: &str) -> String {
+        match self {
+            Syntax::Typst | Syntax::Markdown => text
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n"),
+            Syntax::Org => text.replace('\n', " "),
+        }
+    }
+
+    /// formats a whole link, eg. `@omni.linalg.matrix:addition` or `[[omni:linalg/matrix]]`
+    pub fn format_link(self, omni_path: &OmniPath, heading_part: Option<&HeadingPart>) -> String {
+        let file_part = self.format_file_part(omni_path);
+        match (self, heading_part) {
+            (Syntax::Typst, None) => format!("@{}", file_part),
+            (Syntax::Typst, Some(heading_part)) => {
+                format!("@{}:{}", file_part, self.format_heading_part(heading_part))
+            }
+            (Syntax::Markdown | Syntax::Org, None) => format!("[[{}]]", file_part),
+            (Syntax::Markdown | Syntax::Org, Some(heading_part)) => {
+                format!(
+                    "[[{}#{}]]",
+                    file_part,
+                    self.format_heading_part(heading_part)
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_typst_links() {
+        let line = "see @omni.linalg.matrix:operations.addition[ALIAS], @fig and @omni.vector.";
+        assert_eq!(
+            Syntax::Typst.find_links(line),
+            [
+                LinkSpan {
+                    range: 4..43,
+                    file_range: 5..23,
+                    file_part: FilePart::PathAndName(vec!["linalg".into()], "matrix".into()),
+                    heading_part: Some(HeadingPart::PathAndHeading(
+                        vec!["operations".into()],
+                        "addition".into()
+                    )),
+                    alias: None,
+                    rel: None,
+                },
+                LinkSpan {
+                    range: 61..73,
+                    file_range: 62..73,
+                    file_part: FilePart::Name("vector".into()),
+                    heading_part: None,
+                    alias: None,
+                    rel: None,
+                },
+            ]
+        );
+
+        let line = "#omni-link(<omni.matrix:addition>, rel: \"example\") <fig> <omni.vector";
+        assert_eq!(
+            Syntax::Typst.find_links(line),
+            [LinkSpan {
+                range: 11..33,
+                file_range: 12..23,
+                file_part: FilePart::Name("matrix".into()),
+                heading_part: Some(HeadingPart::Heading("addition".into())),
+                alias: None,
+                rel: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_find_wiki_links() {
+        let line = "a [[omni:linalg/matrix#operations#addition|matrix addition]] b [[other]] [[omni:vector]]";
+        assert_eq!(
+            Syntax::Markdown.find_links(line),
+            [
+                LinkSpan {
+                    range: 2..60,
+                    file_range: 4..22,
+                    file_part: FilePart::PathAndName(vec!["linalg".into()], "matrix".into()),
+                    heading_part: Some(HeadingPart::PathAndHeading(
+                        vec!["operations".into()],
+                        "addition".into()
+                    )),
+                    alias: Some("matrix addition".into()),
+                    rel: None,
+                },
+                LinkSpan {
+                    range: 73..88,
+                    file_range: 75..86,
+                    file_part: FilePart::Name("vector".into()),
+                    heading_part: None,
+                    alias: None,
+                    rel: None,
+                },
+            ]
+        );
+
+        let line = "[[omni:vector#norm][the norm]]";
+        assert_eq!(
+            Syntax::Org.find_links(line),
+            [LinkSpan {
+                range: 0..30,
+                file_range: 2..13,
+                file_part: FilePart::Name("vector".into()),
+                heading_part: Some(HeadingPart::Heading("norm".into())),
+                alias: Some("the norm".into()),
+                rel: None,
+            }]
+        );
+
+        let line = "needs [[omni:matrix|matrices]]{rel=prerequisite} first";
+        assert_eq!(
+            Syntax::Markdown.find_links(line),
+            [LinkSpan {
+                range: 6..48,
+                file_range: 8..19,
+                file_part: FilePart::Name("matrix".into()),
+                heading_part: None,
+                alias: Some("matrices".into()),
+                rel: Some("prerequisite".into()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_typst_comments_and_raw() {
+        let content = indoc::indoc! {r#"
+            see @omni.vector // not @omni.matrix, see https://omni.org/@omni.x
+            /* a comment
+               with @omni.matrix /* nested */ @omni.tensor */ then @omni.field
+            `@omni.inline` and ``@omni.empty_raw``
+            ```typst
+            @omni.in_raw
+            ```
+            \@omni.escaped
+        "#};
+
+        let found: Vec<FilePart> = Syntax::Typst
+            .find_links_in(content)
+            .into_iter()
+            .map(|(_, span)| span.file_part)
+            .collect();
+        assert_eq!(
+            found,
+            [
+                FilePart::Name("vector".into()),
+                FilePart::Name("field".into()),
+                FilePart::Name("empty_raw".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_attributes() {
+        assert_eq!(
+            parse_attributes(r#"rel="cites heavily" year=2024 flag note="a \"b\"""#),
+            [
+                ("rel", "cites heavily".to_string()),
+                ("year", "2024".to_string()),
+                ("note", r#"a "b""#.to_string()),
+            ]
+        );
+
+        let line = r#"[[omni:paper]]{rel="cites heavily"} and more"#;
+        assert_eq!(
+            Syntax::Markdown.find_links(line)[0].rel,
+            Some("cites heavily".into())
+        );
+        assert_eq!(Syntax::Markdown.find_links(line)[0].range, 0..35);
+    }
+
+    #[test]
+    fn test_link_at() {
+        let line = "é [[omni:vector]]";
+        assert_eq!(
+            Syntax::Markdown.link_at(line, 5).map(|s| s.file_part),
+            Some(FilePart::Name("vector".into()))
+        );
+        assert_eq!(Syntax::Markdown.link_at(line, 1), None);
+    }
+
+    #[test]
+    fn test_escape_string() {
+        let title = r#"the "dot" \ product"#;
+        assert_eq!(
+            Syntax::Typst.escape_string(title),
+            r#"the \"dot\" \\ product"#
+        );
+        assert_eq!(Syntax::Org.escape_string(title), title);
+    }
+
+    #[test]
+    fn test_utf16_ranges() {
+        // 𝐀 is 2 UTF-16 code units, but 1 char
+        let line = "𝐀 @omni.vector";
+        let spans = Syntax::Typst.find_links(line);
+        assert_eq!(spans[0].range, 3..15);
+        assert_eq!(spans[0].file_range, 4..15);
+        assert!(Syntax::Typst.link_at(line, 3).is_some());
+        assert!(Syntax::Typst.link_at(line, 2).is_none());
+
+        let (span, _) = &Syntax::Typst.find_links_in(&format!("= A\n{line}"))[0];
+        assert_eq!(span.column, 3);
+        // 4 bytes for 𝐀
+        assert_eq!(span.start, "= A\n".len() + 5);
+    }
+
+    #[test]
+    fn test_completion_context() {
+        assert_eq!(
+            Syntax::Typst.completion_context("see @"),
+            Some(CompletionContext::File { typed: "".into() })
+        );
+        assert_eq!(
+            Syntax::Typst.completion_context("see @omni.lin"),
+            Some(CompletionContext::File {
+                typed: "omni.lin".into()
+            })
+        );
+        assert_eq!(
+            Syntax::Typst.completion_context("see @omni.linalg.matrix:ope"),
+            Some(CompletionContext::Heading {
+                file_part: FilePart::PathAndName(vec!["linalg".into()], "matrix".into()),
+                typed: "ope".into()
+            })
+        );
+        assert_eq!(Syntax::Typst.completion_context("see @fig"), None);
+        assert_eq!(Syntax::Typst.completion_context("a: b"), None);
+
+        assert_eq!(
+            Syntax::Markdown.completion_context("see [[omni:"),
+            Some(CompletionContext::File {
+                typed: "omni:".into()
+            })
+        );
+        assert_eq!(
+            Syntax::Org.completion_context("see [[omni:linalg/matrix#operations#"),
+            Some(CompletionContext::Heading {
+                file_part: FilePart::PathAndName(vec!["linalg".into()], "matrix".into()),
+                typed: "operations#".into()
+            })
+        );
+        assert_eq!(
+            Syntax::Markdown.completion_context("see [[omni:vector]] and"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tag_completion_context() {
+        let tag = |typed: &str| {
+            Some(CompletionContext::Tag {
+                typed: typed.into(),
+            })
+        };
+
+        assert_eq!(
+            Syntax::Typst.completion_context(r##"  tags: ("math", "math/lin"##),
+            tag("math/lin")
+        );
+        assert_eq!(
+            Syntax::Typst.completion_context(r##"  tags: ("math", ""##),
+            tag("")
+        );
+        assert_eq!(
+            Syntax::Typst.completion_context(r##"  tags: ("math"),"##),
+            None
+        );
+        assert_eq!(
+            Syntax::Markdown.completion_context("tags: [math, phy"),
+            tag("phy")
+        );
+        assert_eq!(
+            Syntax::Org.completion_context("#+FILETAGS: :math:phy"),
+            tag("phy")
+        );
+        assert_eq!(Syntax::Markdown.completion_context("title: phy"), None);
+    }
+
+    #[test]
+    fn test_format_link() {
+        let omni_path = OmniPath::new(vec!["linalg".into()], "matrix".into());
+        let heading_part = HeadingPart::PathAndHeading(vec!["ops".into()], "addition".into());
+
+        assert_eq!(
+            Syntax::Typst.format_link(&omni_path, Some(&heading_part)),
+            "@omni.linalg.matrix:ops.addition"
+        );
+        assert_eq!(
+            Syntax::Markdown.format_link(&omni_path, Some(&heading_part)),
+            "[[omni:linalg/matrix#ops#addition]]"
+        );
+        assert_eq!(
+            Syntax::Org.format_link(&omni_path, None),
+            "[[omni:linalg/matrix]]"
+        );
+    }
+}
@@ -20,12 +20,13 @@ pub enum CompileError {
     IoError(#[from] std::io::Error),
 }
 
-/// Runs `typst compile` on `target`
+/// Runs `typst compile` on `target`, with `inputs` in `sys.inputs`
 pub fn compile(
     root: impl AsRef<Utf8Path>,
     target: impl AsRef<Utf8Path>,
     output: impl AsRef<Utf8Path>,
     format: Format,
+    inputs: &[(&str, &str)],
     silent: bool,
 ) -> Result<(), CompileError> {
     let root = root.as_ref();
@@ -41,6 +42,10 @@ pub fn compile(
         .arg("--root")
         .arg(root);
 
+    for (key, value) in inputs {
+        command.arg("--input").arg(format!("{key}={value}"));
+    }
+
     if !silent {
         command.stderr(Stdio::inherit());
     }
@@ -100,6 +105,7 @@ mod tests {
             root.join("note.typ"),
             root.join("note.pdf"),
             Format::Pdf,
+            &[],
             false,
         )?;
 
@@ -122,6 +128,7 @@ mod tests {
             root.join("note.typ"),
             root.join("note.pdf"),
             Format::Pdf,
+            &[],
             true,
         )
         .unwrap();
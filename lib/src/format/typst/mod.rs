@@ -7,13 +7,9 @@ pub use query::QueryError;
 pub use query::QueryParams;
 pub use query::query;
 
+#[derive(Default)]
 pub enum Format {
+    #[default]
     Pdf,
     Html,
 }
-
-impl Default for Format {
-    fn default() -> Self {
-        Self::Pdf
-    }
-}
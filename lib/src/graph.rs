@@ -0,0 +1,136 @@
+//! exports the nodes and the links between them as a graph.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    link::{self, To},
+    node,
+    omni_path::OmniPath,
+};
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// the graph in the graphviz dot format, with the relation of each link as its label.
+/// only the links with relation `rel` are kept if it's set,
+/// and links to ghosts are left out unless `ghosts` is set.
+pub fn dot(nodes: &node::Db, links: &link::Db, rel: Option<&str>, ghosts: bool) -> String {
+    let mut out = String::from("digraph omni {\n");
+
+    for node in &nodes.nodes {
+        out.push_str(&format!(
+            "    {} [label={}];\n",
+            quote(&node.id.0),
+            quote(&node.title)
+        ));
+    }
+
+    let mut ghost_nodes = BTreeSet::new();
+    let mut edges = vec![];
+    for link in &links.links {
+        if rel.is_some() && link.rel.as_deref() != rel {
+            continue;
+        }
+
+        let to = match &link.to {
+            To::Id(id) => quote(&id.0),
+            To::Ghost(_) if !ghosts => continue,
+            To::Ghost(file_part) => {
+                let ghost = OmniPath::from(file_part.clone());
+                let mut name = ghost.path.join("/");
+                if !name.is_empty() {
+                    name.push('/');
+                }
+                name.push_str(&ghost.name);
+                ghost_nodes.insert(name.clone());
+                quote(&format!("ghost:{name}"))
+            }
+        };
+
+        let edge = match &link.rel {
+            Some(rel) => format!(
+                "    {} -> {} [label={}];\n",
+                quote(&link.from.0),
+                to,
+                quote(rel)
+            ),
+            None => format!("    {} -> {};\n", quote(&link.from.0), to),
+        };
+        // the same link can appear more than once in a file
+        if !edges.contains(&edge) {
+            edges.push(edge);
+        }
+    }
+
+    for name in ghost_nodes {
+        out.push_str(&format!(
+            "    {} [label={}, style=dashed];\n",
+            quote(&format!("ghost:{name}")),
+            quote(&name)
+        ));
+    }
+    for edge in edges {
+        out.push_str(&edge);
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot() {
+        let node = |id: &str, title: &str| node::Node {
+            id: id.into(),
+            path: format!("/vault/{id}.typ").into(),
+            title: title.into(),
+            ..Default::default()
+        };
+        let link = |from: &str, to: To, rel: Option<&str>| link::Link {
+            from: from.into(),
+            to,
+            rel: rel.map(|r| r.to_string()),
+            ..Default::default()
+        };
+
+        let nodes = node::Db {
+            nodes: vec![node("id1", "Vector"), node("id2", "The \"matrix\"")],
+        };
+        let links = link::Db {
+            links: vec![
+                link("id2", To::Id("id1".into()), Some("prerequisite")),
+                link("id2", To::Id("id1".into()), Some("prerequisite")),
+                link("id1", To::Id("id2".into()), None),
+                link(
+                    "id1",
+                    To::Ghost(link::FilePart::Name("tensor".into())),
+                    None,
+                ),
+            ],
+        };
+
+        assert_eq!(
+            dot(&nodes, &links, None, true),
+            indoc::indoc!(
+                r#"digraph omni {
+                    "id1" [label="Vector"];
+                    "id2" [label="The \"matrix\""];
+                    "ghost:tensor" [label="tensor", style=dashed];
+                    "id2" -> "id1" [label="prerequisite"];
+                    "id1" -> "id2";
+                    "id1" -> "ghost:tensor";
+                }
+                "#
+            )
+        );
+
+        let only_prerequisites = dot(&nodes, &links, Some("prerequisite"), false);
+        assert!(only_prerequisites.contains("\"id2\" -> \"id1\""));
+        assert!(!only_prerequisites.contains("\"id1\" -> \"id2\""));
+        assert!(!only_prerequisites.contains("ghost"));
+    }
+}
@@ -2,7 +2,13 @@ pub mod build;
 pub mod config;
 pub mod format;
 pub mod get_template;
+pub mod graph;
 pub mod link;
 pub mod node;
 pub mod omni_path;
+pub mod query;
+pub mod reid;
+pub mod storage;
+pub mod tag;
 pub mod track;
+pub mod transaction;
@@ -1,3 +1,5 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use camino::{Utf8Path, Utf8PathBuf};
 use miette::Diagnostic;
 use serde::{Deserialize, Serialize};
@@ -5,25 +7,53 @@ use thiserror::Error;
 
 use crate::{config::Config, node};
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum To {
     Id(node::Id),
     Ghost(FilePart),
 }
 
+impl Default for To {
+    fn default() -> Self {
+        To::Id(node::Id::default())
+    }
+}
+
 /// Fully resolved link.
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
 pub struct Link {
     pub from: node::Id,
     pub to: To,
     #[serde(flatten)]
     pub location: Option<Location>,
     pub alias: Option<String>,
+    /// what kind of link this is, eg. `prerequisite`, `example`, `contradicts` or `cites`.
+    /// None for plain links.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rel: Option<String>,
+    /// where the link is in the source file of `from`.
+    /// None if it couldn't be found, eg. when it comes from an included file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+}
+
+/// Where something is in a file.
+/// `start` and `end` are byte offsets, while `line` and `column` (in UTF-16
+/// code units) are those of `start`, counted from 0 like lsp positions.
+/// links never span lines, so `end` is at `end_column` of the same line.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    #[serde(default)]
+    pub end_column: usize,
 }
 
 /// Represents a location inside the Node.
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum Location {
     /// stable and unique identifier inside the Node
@@ -78,7 +108,7 @@ impl FilePart {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum HeadingPart {
     /// Matches one heading under any heading path.
@@ -99,6 +129,33 @@ pub enum HeadingPart {
     PathAndHeading(Vec<String>, String),
 }
 
+impl HeadingPart {
+    /// parses the part after the `:` in a typst ref, eg. `operations.addition`.
+    /// returns None if the raw heading part is empty.
+    pub fn from_typst_style(raw: &str) -> Option<Self> {
+        if raw.is_empty() {
+            return None;
+        }
+
+        let mut heading_splits: Vec<_> = raw.split(".").map(|s| s.to_string()).collect();
+        let heading = heading_splits.pop()?;
+        if heading_splits.is_empty() {
+            Some(HeadingPart::Heading(heading))
+        } else {
+            Some(HeadingPart::PathAndHeading(heading_splits, heading))
+        }
+    }
+
+    pub fn as_typst_style(&self) -> String {
+        match self {
+            HeadingPart::Heading(heading) => heading.clone(),
+            HeadingPart::PathAndHeading(path, heading) => {
+                format!("{}.{}", path.join("."), heading)
+            }
+        }
+    }
+}
+
 /// Generic form of an unresolved link, which is pretty much what we get straight out of the user.
 #[derive(Debug, PartialEq)]
 pub struct UnresolvedLink {
@@ -106,6 +163,7 @@ pub struct UnresolvedLink {
     pub file_part: FilePart,
     pub heading_part: Option<HeadingPart>,
     pub alias: Option<String>,
+    pub rel: Option<String>,
 }
 
 #[derive(Error, Debug, Diagnostic)]
@@ -138,6 +196,8 @@ impl UnresolvedLink {
             to: to_target,
             location: None, // TODO:
             alias: self.alias,
+            rel: self.rel,
+            span: None,
         })
     }
 }
@@ -151,6 +211,29 @@ pub struct Db {
     pub links: Vec<Link>,
 }
 
+impl Db {
+    /// the links pointing to the node `id`
+    pub fn backlinks<'a>(&'a self, id: &'a node::Id) -> impl Iterator<Item = &'a Link> {
+        self.links
+            .iter()
+            .filter(move |l| matches!(&l.to, To::Id(to) if to == id))
+    }
+
+    /// how many nodes link to `id` with each relation, skipping plain links
+    pub fn relations_to<'a>(&'a self, id: &'a node::Id) -> BTreeMap<&'a str, usize> {
+        let mut relations: BTreeMap<&str, BTreeSet<&node::Id>> = BTreeMap::new();
+        for link in self.backlinks(id) {
+            if let Some(rel) = &link.rel {
+                relations.entry(rel).or_default().insert(&link.from);
+            }
+        }
+        relations
+            .into_iter()
+            .map(|(rel, from)| (rel, from.len()))
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -165,20 +248,16 @@ mod tests {
                 node::Node {
                     id: "id1".into(),
                     path: "linear-algebra/vector.typ".into(),
-                    kind: node::NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 node::Node {
                     id: "id2".into(),
                     path: "programming/rust/vector.typ".into(),
-                    kind: node::NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
             ],
         }
@@ -191,6 +270,8 @@ mod tests {
                 prefix_dir: None,
             },
             typst: config::Typst::default(),
+            ids: config::Ids::default(),
+            storage: Default::default(),
             dir_aliases: HashMap::from([("linalg".into(), "linear-algebra".into())]),
         }
     }
@@ -208,6 +289,7 @@ mod tests {
             ),
             heading_part: None,
             alias: Some("alias".into()),
+            rel: None,
         };
 
         assert_eq!(
@@ -215,8 +297,8 @@ mod tests {
             Link {
                 from: "id1".into(),
                 to: To::Id("id2".into()),
-                location: None,
                 alias: Some("alias".into()),
+                ..Default::default()
             },
         );
     }
@@ -231,6 +313,7 @@ mod tests {
             file_part: FilePart::Name("matrix".into()),
             heading_part: None,
             alias: None,
+            rel: None,
         };
 
         assert_eq!(
@@ -238,8 +321,7 @@ mod tests {
             Link {
                 from: "id1".into(),
                 to: To::Ghost(FilePart::Name("matrix".into())),
-                location: None,
-                alias: None,
+                ..Default::default()
             },
         );
     }
@@ -255,11 +337,28 @@ mod tests {
             file_part: FilePart::Name("vector".into()),
             heading_part: None,
             alias: None,
+            rel: None,
         };
 
         link.try_resolve("", &config, &db).unwrap();
     }
 
+    #[test]
+    fn test_heading_part_from_typst_style() {
+        assert_eq!(HeadingPart::from_typst_style(""), None);
+        assert_eq!(
+            HeadingPart::from_typst_style("addition"),
+            Some(HeadingPart::Heading("addition".into()))
+        );
+        assert_eq!(
+            HeadingPart::from_typst_style("operations.addition"),
+            Some(HeadingPart::PathAndHeading(
+                vec!["operations".into()],
+                "addition".into()
+            ))
+        );
+    }
+
     #[test]
     fn test_links_db_serializing() {
         let db = Db {
@@ -269,6 +368,8 @@ mod tests {
                     to: To::Id("id2".into()),
                     location: Some(Location::Label("addition".into())),
                     alias: Some("matrix addition".into()),
+                    rel: Some("prerequisite".into()),
+                    ..Default::default()
                 },
                 Link {
                     from: "id1".into(),
@@ -278,6 +379,7 @@ mod tests {
                         "addition".into(),
                     ])),
                     alias: Some("perform an addition".into()),
+                    ..Default::default()
                 },
                 Link {
                     from: "id1".into(),
@@ -285,8 +387,7 @@ mod tests {
                         vec!["linalg".into()],
                         "matrix".into(),
                     )),
-                    location: None,
-                    alias: None,
+                    ..Default::default()
                 },
             ],
         };
@@ -296,6 +397,7 @@ mod tests {
             from = "id1"
             label = "addition"
             alias = "matrix addition"
+            rel = "prerequisite"
 
             [link.to]
             id = "id2"
@@ -365,6 +467,7 @@ mod tests {
                     to: To::Id("id2".into()),
                     location: Some(Location::Label("addition".into())),
                     alias: Some("matrix addition".into()),
+                    ..Default::default()
                 },
                 Link {
                     from: "id1".into(),
@@ -374,14 +477,41 @@ mod tests {
                         "addition".into(),
                     ])),
                     alias: Some("perform an addition".into()),
+                    ..Default::default()
                 },
                 Link {
                     from: "id1".into(),
                     to: To::Ghost(FilePart::Name("vector".into())),
-                    location: None,
-                    alias: None,
+                    ..Default::default()
                 },
             ]
         )
     }
+
+    #[test]
+    fn test_relations_to() {
+        let link = |from: &str, to: &str, rel: Option<&str>| Link {
+            from: from.into(),
+            to: To::Id(to.into()),
+            rel: rel.map(|r| r.to_string()),
+            ..Default::default()
+        };
+        let db = Db {
+            links: vec![
+                link("id1", "id3", Some("prerequisite")),
+                link("id1", "id3", Some("prerequisite")),
+                link("id2", "id3", Some("prerequisite")),
+                link("id2", "id3", Some("example")),
+                link("id2", "id3", None),
+                link("id3", "id1", Some("cites")),
+            ],
+        };
+
+        let id3 = node::Id::from("id3");
+        assert_eq!(db.backlinks(&id3).count(), 5);
+        assert_eq!(
+            db.relations_to(&id3),
+            BTreeMap::from([("example", 1), ("prerequisite", 2)])
+        );
+    }
 }
@@ -1,38 +1,98 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Display;
 
 use camino::{Utf8Path, Utf8PathBuf};
+use chrono::format::StrftimeItems;
 use compact_str::{CompactString, ToCompactString};
 use miette::Diagnostic;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
-    config::Config,
-    link, node,
+    config::{Config, IdStrategy},
+    link,
     omni_path::{self, OmniPath},
 };
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Hash)]
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
 pub struct Id(pub CompactString);
 
 impl Id {
-    /// YYYYMMDDHHMM
-    /// + C = count as n hex digits
-    pub fn new(db: &UserDb) -> Self {
-        let date = chrono::Local::now();
-        let mut id = date.format("%Y%m%d%H%M").to_string();
-
-        let count = db
-            .files
-            .iter()
-            .filter(|file| file.id.0.starts_with(&id))
-            .count();
+    /// a new id for the file at `path` that isn't taken in `db`,
+    /// made with the strategy chosen in `[ids]` (see `IdStrategy`)
+    pub fn new(db: &UserDb, config: &Config, path: &Utf8Path) -> Result<Self, Error> {
+        let now = chrono::Local::now();
+        let millis = now.timestamp_millis() as u64;
 
-        if count > 0 {
-            id = format!("{}-{:02x}", id, count)
+        // candidates are tried in order until one is free
+        let candidates: Box<dyn Iterator<Item = String>> = match config.ids.strategy {
+            IdStrategy::Timestamp => {
+                let base = now.format("%Y%m%d%H%M").to_string();
+                Box::new(counted(base, |base, n| format!("{base}-{n:02x}")))
+            }
+            IdStrategy::Ulid => Box::new(std::iter::repeat_with(move || {
+                ulid::Ulid::from_parts(millis, fastrand::u128(..)).to_string()
+            })),
+            IdStrategy::UuidV7 => Box::new(std::iter::repeat_with(move || {
+                let mut random = [0; 10];
+                fastrand::fill(&mut random);
+                uuid::Builder::from_unix_timestamp_millis(millis, &random)
+                    .into_uuid()
+                    .to_string()
+            })),
+            IdStrategy::Slug => {
+                let base = match slug::slugify(path.file_stem().unwrap_or_default()) {
+                    base if base.is_empty() => String::from("note"),
+                    base => base,
+                };
+                Box::new(counted(base, |base, n| format!("{base}-{n}")))
+            }
+            IdStrategy::Pattern => {
+                let items = StrftimeItems::new(&config.ids.pattern)
+                    .parse()
+                    .map_err(|_| Error::InvalidIdPattern(config.ids.pattern.clone()))?;
+                let base = now.format_with_items(items.iter()).to_string();
+                let digits = config.ids.random_digits;
+                Box::new(std::iter::repeat_with(move || {
+                    let suffix: String = (0..digits).map(|_| fastrand::digit(16)).collect();
+                    format!("{base}{suffix}")
+                }))
+            }
+        };
+
+        // random strategies collide only if there's not enough randomness
+        for candidate in candidates.take(1000) {
+            let id = Id(candidate.to_compact_string());
+            id.validate()?;
+            if !db.files.iter().any(|file| file.id == id) {
+                return Ok(id);
+            }
         }
+        Err(Error::NoFreeId)
+    }
+
+    /// ids can't contain `:` (used by sub-document nodes), nor start with `/` (used by directories)
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.0.is_empty()
+            || self.0.contains(':')
+            || self.0.starts_with('/')
+            || self.0.contains(char::is_whitespace)
+        {
+            return Err(Error::InvalidId(self.clone()));
+        }
+        Ok(())
+    }
+
+    /// id of the sub-document node with `label` inside the file with this id,
+    /// eg. `202501011200:spectral-theorem`
+    pub fn sub(&self, label: &str) -> Self {
+        Self(format!("{}:{}", self.0, label).to_compact_string())
+    }
 
-        Self(id.to_compact_string())
+    /// id of the directory node at `dir` (relative to the notes directory),
+    /// eg. `/cs/linear-algebra`
+    pub fn dir(dir: &Utf8Path) -> Self {
+        Self(format!("/{dir}").to_compact_string())
     }
 }
 
@@ -51,6 +111,11 @@ impl Display for Id {
     }
 }
 
+/// `base`, then `base` with a counter from 1 onwards
+fn counted(base: String, with_counter: fn(&str, usize) -> String) -> impl Iterator<Item = String> {
+    std::iter::once(base.clone()).chain((1..).map(move |n| with_counter(&base, n)))
+}
+
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub struct File {
     pub id: Id,
@@ -67,17 +132,128 @@ pub struct UserDb {
     pub files: Vec<File>,
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+impl UserDb {
+    /// the ids used by more than one file, eg. after merging notes made on different machines
+    pub fn duplicate_ids(&self) -> BTreeMap<&Id, Vec<&File>> {
+        let mut files: BTreeMap<&Id, Vec<&File>> = BTreeMap::new();
+        for file in &self.files {
+            files.entry(&file.id).or_default().push(file);
+        }
+        files.retain(|_, files| files.len() > 1);
+        files
+    }
+
+    /// fails on the first id used by more than one file
+    pub fn check_ids(&self) -> Result<(), Error> {
+        match self.duplicate_ids().into_iter().next() {
+            Some((id, files)) => Err(Error::DuplicateId(
+                id.clone(),
+                files
+                    .iter()
+                    .map(|f| f.path.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
 pub enum NodeKind {
+    #[default]
     #[serde(rename = "file")]
     File,
+
+    /// a labelled heading inside a typst file.
+    /// org subtrees with an `:ID:` are not nodes yet, as org files are not built,
+    /// see the follow-up in TODO.org.
+    #[serde(rename = "heading")]
+    Heading,
+
+    /// any other labelled element inside a file, eg. a theorem or a definition
+    #[serde(rename = "label")]
+    Label,
+
+    /// a directory, backed by its index file if it has one,
+    /// otherwise by a generated page listing what's inside.
+    #[serde(rename = "directory")]
+    Directory,
+}
+
+/// Where a sub-document node (a heading or a label) lives.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+pub struct Parent {
+    /// id of the file node
+    pub id: Id,
+    /// label of the element inside the file
+    pub label: String,
+}
+
+/// The value of a custom frontmatter field, eg. `status: "draft"` or `year: 2025`.
+#[derive(Debug, Deserialize, Serialize, PartialEq, PartialOrd, Clone)]
+#[serde(untagged)]
+pub enum Value {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    Table(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// parses a value written by hand, eg. in a query,
+    /// falling back to a string if it's not a number or a bool.
+    pub fn parse(raw: &str) -> Self {
+        if let Ok(v) = raw.parse::<i64>() {
+            Value::Integer(v)
+        } else if let Ok(v) = raw.parse::<f64>() {
+            Value::Float(v)
+        } else if let Ok(v) = raw.parse::<bool>() {
+            Value::Bool(v)
+        } else {
+            Value::String(raw.to_string())
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Bool(v) => write!(f, "{v}"),
+            Value::Integer(v) => write!(f, "{v}"),
+            Value::Float(v) => write!(f, "{v}"),
+            Value::String(v) => write!(f, "{v}"),
+            Value::Array(values) => {
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{v}")?;
+                }
+                Ok(())
+            }
+            Value::Table(table) => {
+                for (i, (k, v)) in table.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{k}: {v}")?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
 /// Fully resolved node,
-/// made by taking a `File` or (in future) other kinds of nodes,
+/// made by taking a `File` or a labelled element inside of it,
 /// finding names and tags and putting them in here.
 /// the path is assumed by some functions to be already canonicalized.
+/// sub-document nodes have the same path of their file,
+/// directory nodes the path of their index file or of the directory itself.
 pub struct Node {
     pub id: Id,
     pub path: Utf8PathBuf,
@@ -85,13 +261,23 @@ pub struct Node {
     pub title: String,
     #[serde(default)]
     pub names: Vec<String>,
+    /// how a link shows up depending on the name it uses (eg. `matrices` -> `Matrices`),
+    /// falling back to the title.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub display: BTreeMap<String, String>,
     #[serde(default)]
     pub tags: Vec<String>,
     #[serde(default)]
     pub private: bool,
+    /// only for sub-document nodes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Parent>,
+    /// any other field found in the frontmatter, eg. `date`, `author` or `status`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, Value>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 /// The nodes database found in `build/nodes.toml`.
 /// which will contain everything from `nodes.toml` + additional metadata found from files (eg. tags)
 /// *this is the ultimate source of truth for nodes.*
@@ -120,23 +306,123 @@ pub enum Error {
     #[error("empty path in FilePart::PathAndName")]
     EmptyPath,
 
+    #[error("invalid id `{0}`")]
+    #[diagnostic(help(
+        "ids can't be empty, contain `:` or whitespace, or start with `/`. check `[ids]` in omni.toml"
+    ))]
+    InvalidId(Id),
+
+    #[error("invalid id pattern `{0}`")]
+    #[diagnostic(help("the pattern in `[ids]` should be a strftime pattern, eg. `%Y%m%d`"))]
+    InvalidIdPattern(String),
+
+    #[error("unable to find an id that isn't taken")]
+    #[diagnostic(help("try using more `random_digits` in `[ids]`"))]
+    NoFreeId,
+
+    #[error("id `{0}` is used by more than one file: {1}")]
+    #[diagnostic(help(
+        "this usually happens after merging notes made on different machines. run `omni reid --duplicates` to give them new ids"
+    ))]
+    DuplicateId(Id, String),
+
     #[error(transparent)]
     OmniPathError(#[from] omni_path::Error),
 }
 
+impl Node {
+    /// how a link using `name` shows up
+    pub fn display(&self, name: &str) -> &str {
+        self.display.get(name).unwrap_or(&self.title)
+    }
+
+    /// a field of the node, either a custom one or one of
+    /// `id`, `path`, `kind`, `title`, `names`, `tags` and `private`.
+    /// custom fields with the same name as these are shadowed.
+    pub fn field(&self, name: &str) -> Option<Value> {
+        let strings = |v: &[String]| Value::Array(v.iter().cloned().map(Value::String).collect());
+        match name {
+            "id" => Some(Value::String(self.id.to_string())),
+            "path" => Some(Value::String(self.path.to_string())),
+            "kind" => Some(Value::String(
+                match self.kind {
+                    NodeKind::File => "file",
+                    NodeKind::Heading => "heading",
+                    NodeKind::Label => "label",
+                    NodeKind::Directory => "directory",
+                }
+                .into(),
+            )),
+            "title" => Some(Value::String(self.title.clone())),
+            "names" => Some(strings(&self.names)),
+            "tags" => Some(strings(&self.tags)),
+            "private" => Some(Value::Bool(self.private)),
+            _ => self.fields.get(name).cloned(),
+        }
+    }
+}
+
 impl Db {
-    /// Finds the id of a node from a system path
+    /// Finds the id of a file node from a system path
     pub fn find_abs(&self, path: &Utf8Path, _: &Config) -> Result<&'_ Node, Error> {
         // TODO: consider canonicalizing
-        match self
+        // nodes are not kept sorted by path (shallow builds just push new ones),
+        // so we can't binary search here
+        self.nodes
+            .iter()
+            .find(|node| node.kind == NodeKind::File && node.path == path)
+            .ok_or(Error::UntrackedNode(path.to_path_buf()))
+    }
+
+    /// whether `node` must be left out of public builds,
+    /// as it's private or it's inside a private file
+    pub fn is_private(&self, node: &Node) -> bool {
+        node.private
+            || node
+                .parent
+                .as_ref()
+                .is_some_and(|parent| self.nodes.iter().any(|n| n.id == parent.id && n.private))
+    }
+
+    /// the ids of the nodes that must be left out of public builds,
+    /// like `is_private` but for all of them at once
+    pub fn private_ids(&self) -> HashSet<&Id> {
+        let private_files: HashSet<&Id> = self
             .nodes
-            .binary_search_by_key(&path, |node| node.path.as_path())
-        {
-            Ok(index) => Ok(&self.nodes[index]), // WARNING: this should never crash but you know...
-            Err(_) => Err(Error::UntrackedNode(path.to_path_buf())),
+            .iter()
+            .filter(|n| n.private)
+            .map(|n| &n.id)
+            .collect();
+        self.nodes
+            .iter()
+            .filter(|n| {
+                n.private
+                    || n.parent
+                        .as_ref()
+                        .is_some_and(|parent| private_files.contains(&parent.id))
+            })
+            .map(|n| &n.id)
+            .collect()
+    }
+
+    /// the nodes that can be shown in public builds
+    pub fn public(&self) -> Db {
+        let private = self.private_ids();
+        Db {
+            nodes: self
+                .nodes
+                .iter()
+                .filter(|n| !private.contains(&n.id))
+                .cloned()
+                .collect(),
         }
     }
 
+    /// the nodes by id, to look many of them up
+    pub fn by_id(&self) -> HashMap<&Id, &Node> {
+        self.nodes.iter().map(|n| (&n.id, n)).collect()
+    }
+
     /// Finds a node from an id
     pub fn find_from_id(&self, id: &Id, _: &Config) -> Result<&'_ Node, Error> {
         self.nodes
@@ -152,21 +438,51 @@ impl Db {
         part: &link::FilePart,
         config: &Config,
     ) -> Result<&'_ Node, Error> {
+        let found = self.find_all_from_filepart(root, part, config)?;
+        let name = match part {
+            link::FilePart::Name(name) => name,
+            link::FilePart::PathAndName(_, name) => name,
+        };
+
+        if found.len() > 1 {
+            Err(Error::DuplicateName(name.clone()))
+        } else if found.is_empty() {
+            Err(Error::NameNotFound(name.clone()))
+        } else {
+            Ok(found[0])
+        }
+    }
+
+    /// Finds all nodes matching a FilePart,
+    /// which will be more than one if the FilePart is ambiguous.
+    ///
+    /// a directory is left out if something else matches too,
+    /// eg. `linalg` is `linalg.typ` rather than `linalg/`.
+    pub fn find_all_from_filepart(
+        &self,
+        root: impl AsRef<Utf8Path>,
+        part: &link::FilePart,
+        config: &Config,
+    ) -> Result<Vec<&'_ Node>, Error> {
+        let mut found = self.find_all_matching(root, part, config)?;
+        if found.iter().any(|n| n.kind != NodeKind::Directory) {
+            found.retain(|n| n.kind != NodeKind::Directory);
+        }
+        Ok(found)
+    }
+
+    fn find_all_matching(
+        &self,
+        root: impl AsRef<Utf8Path>,
+        part: &link::FilePart,
+        config: &Config,
+    ) -> Result<Vec<&'_ Node>, Error> {
         match part {
-            link::FilePart::Name(name) => {
-                let found: Vec<&node::Node> = self
-                    .nodes
-                    .iter()
-                    .filter(|node| node.names.contains(name))
-                    .collect();
-                if found.len() > 1 {
-                    Err(Error::DuplicateName(name.clone()))
-                } else if found.is_empty() {
-                    Err(Error::NameNotFound(name.clone()))
-                } else {
-                    Ok(found[0])
-                }
-            }
+            link::FilePart::Name(name) => Ok(self
+                .nodes
+                .iter()
+                .filter(|node| node.names.contains(name))
+                .collect()),
             link::FilePart::PathAndName(fake_path, name) => {
                 let op = OmniPath::new(fake_path.clone(), name.clone());
                 let op = op.unalias(config)?;
@@ -177,7 +493,7 @@ impl Db {
                 let path: Utf8PathBuf = op.try_into()?;
                 let path = path.parent().expect("should always have a parent");
 
-                let found: Vec<&node::Node> = self
+                Ok(self
                     .nodes
                     .iter()
                     .filter(|node| node.names.contains(name))
@@ -187,14 +503,7 @@ impl Db {
                             .expect("node should subbed to root")
                             .starts_with(path)
                     })
-                    .collect();
-                if found.len() > 1 {
-                    Err(Error::DuplicateName(name.clone()))
-                } else if found.is_empty() {
-                    Err(Error::NameNotFound(name.clone()))
-                } else {
-                    Ok(found[0])
-                }
+                    .collect())
             }
         }
     }
@@ -215,20 +524,16 @@ mod tests {
                 Node {
                     id: "id1".into(),
                     path: "linear-algebra/vector.typ".into(),
-                    kind: NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 Node {
                     id: "id2".into(),
                     path: "programming/rust/borrowing.typ".into(),
-                    kind: NodeKind::File,
                     title: "Borrowing".into(),
                     names: vec!["borrow-checker".into(), "borrow".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
             ],
         };
@@ -239,6 +544,8 @@ mod tests {
                 prefix_dir: None,
             },
             typst: config::Typst::default(),
+            ids: config::Ids::default(),
+            storage: Default::default(),
             dir_aliases: HashMap::from([("linalg".into(), "linear-algebra".into())]),
         };
 
@@ -260,20 +567,16 @@ mod tests {
                 Node {
                     id: "id1".into(),
                     path: "linear-algebra".into(),
-                    kind: NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 Node {
                     id: "id2".into(),
                     path: "programming/rust".into(),
-                    kind: NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
             ],
         };
@@ -284,6 +587,8 @@ mod tests {
                 prefix_dir: None,
             },
             typst: config::Typst::default(),
+            ids: config::Ids::default(),
+            storage: Default::default(),
             dir_aliases: HashMap::from([("linalg".into(), "linear-algebra".into())]),
         };
 
@@ -322,20 +627,16 @@ mod tests {
                 Node {
                     id: "id1".into(),
                     path: "/Users/me/src/linear-algebra/vector.typ".into(),
-                    kind: NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 Node {
                     id: "id2".into(),
                     path: "/Users/me/src/programming/rust/vector.typ".into(),
-                    kind: NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
             ],
         };
@@ -346,6 +647,8 @@ mod tests {
                 prefix_dir: Some("src".into()),
             },
             typst: config::Typst::default(),
+            ids: config::Ids::default(),
+            storage: Default::default(),
             dir_aliases: HashMap::from([("linalg".into(), "linear-algebra".into())]),
         };
 
@@ -380,6 +683,153 @@ mod tests {
         assert_eq!(found.id, "id2".into());
     }
 
+    #[test]
+    fn test_find_all_by_name() {
+        let db = Db {
+            nodes: vec![
+                Node {
+                    id: "id1".into(),
+                    path: "linear-algebra/vector.typ".into(),
+                    title: "Vector".into(),
+                    names: vec!["vector".into()],
+                    ..Default::default()
+                },
+                Node {
+                    id: "id2".into(),
+                    path: "programming/rust/vector.typ".into(),
+                    title: "Vector".into(),
+                    names: vec!["vector".into()],
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let config = Config::default();
+
+        let found = db
+            .find_all_from_filepart("", &link::FilePart::Name("vector".into()), &config)
+            .unwrap();
+        assert_eq!(
+            found.iter().map(|n| n.id.clone()).collect::<Vec<_>>(),
+            ["id1".into(), "id2".into()]
+        );
+
+        assert!(matches!(
+            db.find_from_filepart("", &link::FilePart::Name("vector".into()), &config),
+            Err(Error::DuplicateName(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_over_directory() {
+        let node = |id: &str, path: &str, kind| Node {
+            id: id.into(),
+            path: path.into(),
+            kind,
+            title: "Linalg".into(),
+            names: vec!["linalg".into()],
+            ..Default::default()
+        };
+        let db = Db {
+            nodes: vec![
+                node("dir:linalg", "/notes/linalg", NodeKind::Directory),
+                node("id1", "/notes/linalg.typ", NodeKind::File),
+            ],
+        };
+        let config = Config::default();
+
+        let found = db
+            .find_from_filepart("/notes", &link::FilePart::Name("linalg".into()), &config)
+            .unwrap();
+        assert_eq!(found.id, "id1".into());
+
+        // the directory is still there with its path
+        let part = link::FilePart::PathAndName(vec!["linalg".into()], "linalg".into());
+        let found = db.find_from_filepart("/notes", &part, &config).unwrap();
+        assert_eq!(found.id, "dir:linalg".into());
+    }
+
+    #[test]
+    fn test_sub_nodes() {
+        let matrix: Id = "id1".into();
+        let rank = matrix.sub("rank");
+        assert_eq!(rank, "id1:rank".into());
+
+        let db = Db {
+            nodes: vec![
+                Node {
+                    id: matrix.clone(),
+                    path: "/notes/matrix.typ".into(),
+                    title: "Matrix".into(),
+                    names: vec!["matrix".into()],
+                    ..Default::default()
+                },
+                Node {
+                    id: rank.clone(),
+                    path: "/notes/matrix.typ".into(),
+                    kind: NodeKind::Heading,
+                    title: "Rank".into(),
+                    names: vec!["rank".into()],
+                    parent: Some(Parent {
+                        id: matrix.clone(),
+                        label: "rank".into(),
+                    }),
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let config = Config::default();
+
+        // the file is found by its path, not its sub-nodes
+        assert_eq!(
+            db.find_abs("/notes/matrix.typ".into(), &config).unwrap().id,
+            matrix
+        );
+        assert_eq!(
+            db.find_from_filepart("", &link::FilePart::Name("rank".into()), &config)
+                .unwrap()
+                .id,
+            rank
+        );
+    }
+
+    #[test]
+    fn test_private() {
+        let node = |id: &str, private: bool, parent: Option<&str>| Node {
+            id: id.into(),
+            path: "/notes/matrix.typ".into(),
+            title: id.into(),
+            names: vec![id.into()],
+            private,
+            parent: parent.map(|p| Parent {
+                id: p.into(),
+                label: id.into(),
+            }),
+            ..Default::default()
+        };
+
+        let db = Db {
+            nodes: vec![
+                node("matrix", false, None),
+                node("matrix:rank", true, Some("matrix")),
+                node("diary", true, None),
+                node("diary:today", false, Some("diary")),
+            ],
+        };
+
+        assert!(!db.is_private(&db.nodes[0]));
+        assert!(db.is_private(&db.nodes[1]));
+        // inside a private file
+        assert!(db.is_private(&db.nodes[3]));
+
+        assert_eq!(db.public().nodes, vec![db.nodes[0].clone()]);
+        assert_eq!(
+            db.private_ids(),
+            HashSet::from([&db.nodes[1].id, &db.nodes[2].id, &db.nodes[3].id])
+        );
+    }
+
     #[test]
     #[should_panic]
     fn test_find_by_name_fail() {
@@ -388,20 +838,16 @@ mod tests {
                 Node {
                     id: "id1".into(),
                     path: "linear-algebra".into(),
-                    kind: NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 Node {
                     id: "id2".into(),
                     path: "programming/rust".into(),
-                    kind: NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
             ],
         };
@@ -412,6 +858,8 @@ mod tests {
                 prefix_dir: None,
             },
             typst: config::Typst::default(),
+            ids: config::Ids::default(),
+            storage: Default::default(),
             dir_aliases: HashMap::new(),
         };
 
@@ -419,6 +867,108 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_find_abs_unsorted() {
+        let db = Db {
+            nodes: vec![
+                Node {
+                    id: "id1".into(),
+                    path: "/vault/zeta.typ".into(),
+                    title: "Zeta".into(),
+                    names: vec!["zeta".into()],
+                    ..Default::default()
+                },
+                Node {
+                    id: "id2".into(),
+                    path: "/vault/alpha.typ".into(),
+                    title: "Alpha".into(),
+                    names: vec!["alpha".into()],
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let config = Config::default();
+        let found = db.find_abs("/vault/alpha.typ".into(), &config).unwrap();
+        assert_eq!(found.id, "id2".into());
+        assert!(db.find_abs("/vault/beta.typ".into(), &config).is_err());
+    }
+
+    #[test]
+    fn test_id_strategies() {
+        let config = |strategy: config::IdStrategy, pattern: &str| Config {
+            ids: config::Ids {
+                strategy,
+                pattern: pattern.into(),
+                random_digits: 4,
+            },
+            ..Default::default()
+        };
+        let path = Utf8Path::new("linalg/Spectral Theorem.typ");
+        let mut db = UserDb { files: vec![] };
+
+        let id = Id::new(&db, &config(config::IdStrategy::Timestamp, ""), path).unwrap();
+        assert_eq!(id.0.len(), "YYYYMMDDHHMM".len());
+        db.files.push(File {
+            id: id.clone(),
+            path: path.into(),
+        });
+        // a counter is added now that the minute is taken
+        let next = Id::new(&db, &config(config::IdStrategy::Timestamp, ""), path).unwrap();
+        assert_ne!(next, id);
+
+        let slug = |db: &UserDb| Id::new(db, &config(config::IdStrategy::Slug, ""), path).unwrap();
+        assert_eq!(slug(&db), "spectral-theorem".into());
+        db.files.push(File {
+            id: "spectral-theorem".into(),
+            path: path.into(),
+        });
+        assert_eq!(slug(&db), "spectral-theorem-1".into());
+
+        let ulid = Id::new(&db, &config(config::IdStrategy::Ulid, ""), path).unwrap();
+        assert_eq!(ulid.0.len(), 26);
+
+        let uuid = Id::new(&db, &config(config::IdStrategy::UuidV7, ""), path).unwrap();
+        assert_eq!(uuid.0.len(), 36);
+        assert_eq!(uuid.0.chars().nth(14), Some('7'));
+
+        let pattern = Id::new(&db, &config(config::IdStrategy::Pattern, "n%Y-"), path).unwrap();
+        assert!(pattern.0.starts_with('n'));
+        assert_eq!(pattern.0.len(), "nYYYY-".len() + 4);
+
+        assert!(matches!(
+            Id::new(&db, &config(config::IdStrategy::Pattern, "%Q"), path),
+            Err(Error::InvalidIdPattern(_))
+        ));
+        assert!(matches!(
+            Id::new(&db, &config(config::IdStrategy::Pattern, "%H:%M"), path),
+            Err(Error::InvalidId(_))
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_ids() {
+        let raw = r#"
+        [[file]]
+        id = "202601011200"
+        path = "src/matrix.typ"
+
+        [[file]]
+        id = "202601011200"
+        path = "src/vector.typ"
+
+        [[file]]
+        id = "202601011201"
+        path = "src/tensor.typ"
+        "#;
+        let db: UserDb = toml::from_str(raw).unwrap();
+
+        let duplicates = db.duplicate_ids();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[&Id::from("202601011200")].len(), 2);
+        assert!(matches!(db.check_ids(), Err(Error::DuplicateId(..))));
+    }
+
     #[test]
     fn test_user_nodes_db_parsing() {
         let raw = r#"
@@ -455,7 +1005,8 @@ mod tests {
         path = "cs/c/matrix.md"
         kind = "file"
         title = "Matrix"
-        names = ["matrix"]
+        names = ["matrix", "matrices"]
+        display = { matrices = "Matrices" }
         tags = ["programming"]
 
         [[node]]
@@ -473,22 +1024,60 @@ mod tests {
                 Node {
                     id: Id("id1".into()),
                     path: "cs/c/matrix.md".into(),
-                    kind: NodeKind::File,
                     title: "Matrix".into(),
-                    names: vec!["matrix".into()],
+                    names: vec!["matrix".into(), "matrices".into()],
+                    display: BTreeMap::from([("matrices".into(), "Matrices".into())]),
                     tags: vec!["programming".into()],
-                    private: false,
+                    ..Default::default()
                 },
                 Node {
                     id: Id("id2".into()),
                     path: "cs/discrete-math/proofs/proof-by-induction.typ".into(),
-                    kind: NodeKind::File,
                     title: "Proof by induction".into(),
                     names: vec!["proof-by-induction".into(), "induction".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 }
             ]
-        )
+        );
+
+        assert_eq!(db.nodes[0].display("matrix"), "Matrix");
+        assert_eq!(db.nodes[0].display("matrices"), "Matrices");
+    }
+
+    #[test]
+    fn test_nodes_db_fields_roundtrip() {
+        let db = Db {
+            nodes: vec![Node {
+                id: Id("id1".into()),
+                path: "cs/c/matrix.typ".into(),
+                title: "Matrix".into(),
+                names: vec!["matrix".into()],
+                fields: BTreeMap::from([
+                    ("status".into(), Value::String("draft".into())),
+                    ("year".into(), Value::Integer(2025)),
+                    ("rating".into(), Value::Float(4.5)),
+                    (
+                        "source".into(),
+                        Value::Table(BTreeMap::from([(
+                            "title".into(),
+                            Value::String("Linear Algebra Done Right".into()),
+                        )])),
+                    ),
+                ]),
+                ..Default::default()
+            }],
+        };
+
+        let raw = toml::to_string(&db).unwrap();
+        let parsed: Db = toml::from_str(&raw).unwrap();
+        assert_eq!(parsed.nodes, db.nodes);
+        assert_eq!(
+            parsed.nodes[0].field("status"),
+            Some(Value::String("draft".into()))
+        );
+        assert_eq!(
+            parsed.nodes[0].field("kind"),
+            Some(Value::String("file".into()))
+        );
     }
 }
@@ -174,6 +174,15 @@ impl OmniPath {
             format!("omni.{}.{}", path_part, self.name)
         }
     }
+
+    pub fn as_wiki_style(&self) -> String {
+        if self.path.is_empty() {
+            format!("omni:{}", self.name)
+        } else {
+            let path_part = self.path.join("/");
+            format!("omni:{}/{}", path_part, self.name)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -192,6 +201,8 @@ mod tests {
                 prefix_dir: None,
             },
             typst: config::Typst::default(),
+            ids: config::Ids::default(),
+            storage: Default::default(),
             dir_aliases: HashMap::from([("linalg".into(), "cs/linear-algebra".into())]),
         };
 
@@ -219,6 +230,8 @@ mod tests {
                 prefix_dir: Some("src".into()),
             },
             typst: config::Typst::default(),
+            ids: config::Ids::default(),
+            storage: Default::default(),
             dir_aliases: HashMap::from([("linalg".into(), "cs/linear-algebra".into())]),
         };
 
@@ -252,6 +265,8 @@ mod tests {
                 prefix_dir: None,
             },
             typst: config::Typst::default(),
+            ids: config::Ids::default(),
+            storage: Default::default(),
             dir_aliases: HashMap::from([("linalg".into(), "cs/linear-algebra".into())]),
         };
 
@@ -274,6 +289,8 @@ mod tests {
                 prefix_dir: None,
             },
             typst: config::Typst::default(),
+            ids: config::Ids::default(),
+            storage: Default::default(),
             dir_aliases: HashMap::from([("linalg".into(), "cs/linear-algebra".into())]),
         };
 
@@ -0,0 +1,250 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::node::{self, Node, Value};
+
+#[derive(Error, Debug, Diagnostic)]
+pub enum Error {
+    #[error("invalid filter `{0}`")]
+    #[diagnostic(help(
+        "filters look like `status=draft`, `year>=2024`, `tags=math` or just `source` to check that it's there"
+    ))]
+    InvalidFilter(String),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A condition on a field of a node, either a custom or a builtin one (see `Node::field`).
+#[derive(Debug, PartialEq, Clone)]
+pub enum Filter {
+    /// `field`: the field is there
+    Has(String),
+    /// `field<op>value`, eg. `status=draft` or `year>=2024`.
+    /// on lists `=` means "contains" and `!=` "doesn't contain",
+    /// and `tags=math` also matches the tags nested inside `math`.
+    Compare { field: String, op: Op, value: Value },
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidFilter(raw.to_string());
+
+        let Some(start) = raw.find(['=', '!', '<', '>']) else {
+            let field = raw.trim();
+            if field.is_empty() || field.contains(char::is_whitespace) {
+                return Err(invalid());
+            }
+            return Ok(Filter::Has(field.to_string()));
+        };
+
+        let field = raw[..start].trim();
+        let rest = &raw[start..];
+        let (op, len) = if rest.starts_with("!=") {
+            (Op::Ne, 2)
+        } else if rest.starts_with("<=") {
+            (Op::Le, 2)
+        } else if rest.starts_with(">=") {
+            (Op::Ge, 2)
+        } else if rest.starts_with('=') {
+            (Op::Eq, 1)
+        } else if rest.starts_with('<') {
+            (Op::Lt, 1)
+        } else if rest.starts_with('>') {
+            (Op::Gt, 1)
+        } else {
+            return Err(invalid());
+        };
+
+        if field.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Filter::Compare {
+            field: field.to_string(),
+            op,
+            value: Value::parse(rest[len..].trim()),
+        })
+    }
+}
+
+/// compares two values, as long as it makes sense to.
+/// strings are compared to anything by their text, eg. so that `date>=2024` works
+fn compare(actual: &Value, expected: &Value) -> Option<Ordering> {
+    match (actual, expected) {
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+        (Value::Integer(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+        (Value::Float(a), Value::Integer(b)) => a.partial_cmp(&(*b as f64)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::String(a), b) => Some(a.as_str().cmp(b.to_string().as_str())),
+        _ => None,
+    }
+}
+
+/// orders two values the way the filters compare them, eg. to sort by a field.
+/// values that can't be compared are equal.
+pub fn order(a: &Value, b: &Value) -> Ordering {
+    compare(a, b)
+        .or_else(|| compare(b, a).map(Ordering::reverse))
+        .unwrap_or(Ordering::Equal)
+}
+
+impl Filter {
+    pub fn matches(&self, node: &Node) -> bool {
+        let (field, op, expected) = match self {
+            Filter::Has(field) => return node.field(field).is_some(),
+            Filter::Compare { field, op, value } => (field, *op, value),
+        };
+
+        let Some(actual) = node.field(field) else {
+            return op == Op::Ne;
+        };
+
+        let is = |actual: &Value| {
+            if field == "tags"
+                && let (Value::String(tag), Value::String(expected)) = (actual, expected)
+            {
+                return tag == expected || tag.starts_with(&format!("{expected}/"));
+            }
+            compare(actual, expected) == Some(Ordering::Equal)
+        };
+
+        match (&actual, op) {
+            (Value::Array(values), Op::Eq) => values.iter().any(is),
+            (Value::Array(values), Op::Ne) => !values.iter().any(is),
+            (_, Op::Eq) => is(&actual),
+            (_, Op::Ne) => !is(&actual),
+            (_, op) => compare(&actual, expected).is_some_and(|ordering| match op {
+                Op::Lt => ordering.is_lt(),
+                Op::Le => ordering.is_le(),
+                Op::Gt => ordering.is_gt(),
+                Op::Ge => ordering.is_ge(),
+                Op::Eq | Op::Ne => unreachable!(),
+            }),
+        }
+    }
+}
+
+/// the nodes matching all the filters
+pub fn query<'a>(nodes: &'a node::Db, filters: &'a [Filter]) -> impl Iterator<Item = &'a Node> {
+    nodes
+        .nodes
+        .iter()
+        .filter(|node| filters.iter().all(|f| f.matches(node)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(
+            "status=draft".parse::<Filter>().unwrap(),
+            Filter::Compare {
+                field: "status".into(),
+                op: Op::Eq,
+                value: Value::String("draft".into())
+            }
+        );
+        assert_eq!(
+            "year >= 2024".parse::<Filter>().unwrap(),
+            Filter::Compare {
+                field: "year".into(),
+                op: Op::Ge,
+                value: Value::Integer(2024)
+            }
+        );
+        assert_eq!(
+            "source".parse::<Filter>().unwrap(),
+            Filter::Has("source".into())
+        );
+        assert!("=draft".parse::<Filter>().is_err());
+        assert!("status!draft".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn test_order() {
+        assert_eq!(
+            order(&Value::Integer(20), &Value::Float(1.5)),
+            Ordering::Greater
+        );
+        assert_eq!(
+            order(&Value::Integer(2024), &Value::String("2025-01".into())),
+            Ordering::Less
+        );
+        assert_eq!(
+            order(&Value::Bool(true), &Value::Integer(1)),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn test_query() {
+        let node = |id: &str, tags: &[&str], fields: &[(&str, Value)]| Node {
+            id: id.into(),
+            path: format!("/vault/{id}.typ").into(),
+            title: id.into(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect::<BTreeMap<_, _>>(),
+            ..Default::default()
+        };
+
+        let nodes = node::Db {
+            nodes: vec![
+                node(
+                    "id1",
+                    &["math/linalg"],
+                    &[
+                        ("status", Value::String("draft".into())),
+                        ("year", Value::Integer(2023)),
+                        ("date", Value::String("2023-05-01".into())),
+                    ],
+                ),
+                node(
+                    "id2",
+                    &["physics"],
+                    &[
+                        ("status", Value::String("done".into())),
+                        ("year", Value::Integer(2025)),
+                        ("source", Value::String("a book".into())),
+                    ],
+                ),
+            ],
+        };
+
+        let ids = |filters: &[&str]| {
+            let filters: Vec<Filter> = filters.iter().map(|f| f.parse().unwrap()).collect();
+            query(&nodes, &filters)
+                .map(|n| n.id.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(&["status=draft"]), ["id1"]);
+        assert_eq!(ids(&["status!=draft"]), ["id2"]);
+        assert_eq!(ids(&["year>2023"]), ["id2"]);
+        assert_eq!(ids(&["year>=2023", "status=done"]), ["id2"]);
+        assert_eq!(ids(&["date<2024"]), ["id1"]);
+        assert_eq!(ids(&["tags=math"]), ["id1"]);
+        assert_eq!(ids(&["tags!=math"]), ["id2"]);
+        assert_eq!(ids(&["source"]), ["id2"]);
+        assert_eq!(ids(&["kind=file"]), ["id1", "id2"]);
+    }
+}
@@ -0,0 +1,290 @@
+//! gives files new ids, eg. when they collide after merging notes made on different machines.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{
+    build::lock::{BuildLock, LockError},
+    config::Config,
+    link, node,
+    storage::{self, StorageError},
+    tag,
+    transaction::{self, Transaction, TransactionError},
+};
+
+#[derive(thiserror::Error, miette::Diagnostic, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    TransactionError(#[from] TransactionError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    NodeError(#[from] node::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    LockError(#[from] LockError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    StorageError(#[from] StorageError),
+}
+
+/// which files get new ids
+#[derive(Debug, Clone)]
+pub enum Which {
+    /// the files at these paths
+    Paths(Vec<Utf8PathBuf>),
+    /// all but the first file of each id used more than once
+    Duplicates,
+    /// every file, eg. after switching id strategy
+    All,
+}
+
+/// a file that got a new id
+#[derive(Debug, PartialEq, Clone)]
+pub struct Reid {
+    pub path: Utf8PathBuf,
+    pub old: node::Id,
+    pub new: node::Id,
+}
+
+fn canonical(root: &Utf8Path, path: &Utf8Path) -> Utf8PathBuf {
+    let path = root.join(path);
+    path.canonicalize_utf8().unwrap_or(path)
+}
+
+/// the paths (as found in `nodes.toml`) of the files that get new ids
+fn paths(root: &Utf8Path, user_db: &node::UserDb, which: Which) -> Result<Vec<Utf8PathBuf>, Error> {
+    Ok(match which {
+        Which::Paths(paths) => paths
+            .into_iter()
+            .map(|path| {
+                let target = path.canonicalize_utf8().unwrap_or(path.clone());
+                user_db
+                    .files
+                    .iter()
+                    .find(|f| canonical(root, &f.path) == target)
+                    .map(|f| f.path.clone())
+                    .ok_or(node::Error::UntrackedNode(path))
+            })
+            .collect::<Result<_, _>>()?,
+        Which::Duplicates => user_db
+            .duplicate_ids()
+            .into_values()
+            .flat_map(|files| files.into_iter().skip(1))
+            .map(|f| f.path.clone())
+            .collect(),
+        Which::All => user_db.files.iter().map(|f| f.path.clone()).collect(),
+    })
+}
+
+/// gives new ids to the files at `paths` (as found in `nodes.toml`),
+/// renaming them in the user database and in the build databases.
+///
+/// when an id was used by more than one file, the links from it can't be told apart,
+/// so they are dropped until the next build.
+pub fn rename(
+    root: impl AsRef<Utf8Path>,
+    config: &Config,
+    user_db: &mut node::UserDb,
+    nodes: &mut node::Db,
+    links: &mut link::Db,
+    paths: &[Utf8PathBuf],
+) -> Result<Vec<Reid>, node::Error> {
+    let root = root.as_ref();
+    let mut renamed = vec![];
+
+    for path in paths {
+        let new = node::Id::new(user_db, config, path)?;
+        let i = user_db
+            .files
+            .iter()
+            .position(|f| &f.path == path)
+            .ok_or(node::Error::UntrackedNode(path.clone()))?;
+        let old = std::mem::replace(&mut user_db.files[i].id, new.clone());
+        let was_duplicate = user_db.files.iter().any(|f| f.id == old);
+
+        // the nodes of the file, and of what's inside it
+        let abs = canonical(root, path);
+        let mut node_renames: HashMap<node::Id, node::Id> = HashMap::new();
+        for node in nodes.nodes.iter_mut().filter(|n| n.path == abs) {
+            let new_id = match &mut node.parent {
+                Some(parent) if parent.id == old => {
+                    parent.id = new.clone();
+                    new.sub(&parent.label)
+                }
+                None if node.kind == node::NodeKind::File && node.id == old => new.clone(),
+                _ => continue,
+            };
+            node_renames.insert(std::mem::replace(&mut node.id, new_id.clone()), new_id);
+        }
+
+        links.links.retain_mut(|l| {
+            if let link::To::Id(to) = &mut l.to
+                && let Some(new_to) = node_renames.get(to)
+                // another file still has the node with this id
+                && !nodes.nodes.iter().any(|n| &n.id == to)
+            {
+                *to = new_to.clone();
+            }
+            if l.from == old {
+                if was_duplicate {
+                    return false;
+                }
+                l.from = new.clone();
+            }
+            true
+        });
+
+        renamed.push(Reid {
+            path: path.clone(),
+            old,
+            new,
+        });
+    }
+
+    Ok(renamed)
+}
+
+/// gives new ids to some files and saves all the databases, see `rename`
+pub fn reid(root: impl AsRef<Utf8Path>, config: &Config, which: Which) -> Result<Vec<Reid>, Error> {
+    let root = root.as_ref();
+    // builds rewrite the build databases too
+    let lock = BuildLock::acquire(root, Duration::from_secs(30))?;
+
+    let mut user_db: node::UserDb = transaction::read_toml(root.join("nodes.toml"))?;
+    let mut storage = storage::open(root, config)?;
+    let mut nodes = storage.load_nodes()?;
+    let mut links = storage.load_links()?;
+
+    let paths = paths(root, &user_db, which)?;
+    let renamed = rename(root, config, &mut user_db, &mut nodes, &mut links, &paths)?;
+    if renamed.is_empty() {
+        return Ok(renamed);
+    }
+
+    // SAVEPOINT(user_db, nodes, links, tags):
+    // the build databases can be rebuilt from nodes.toml, so they go last
+    Transaction::new(&lock)
+        .write_db("nodes.toml", &user_db)?
+        .commit()?;
+    storage.save(&lock, &nodes, &links)?;
+    Transaction::new(&lock)
+        .write_db("build/tags.toml", &tag::Db::from_nodes(&nodes))?
+        .commit()?;
+
+    Ok(renamed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rename() {
+        let config = Config {
+            ids: crate::config::Ids {
+                strategy: crate::config::IdStrategy::Slug,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let file = |id: &str, path: &str| node::File {
+            id: id.into(),
+            path: path.into(),
+        };
+        // merged from two machines
+        let mut user_db = node::UserDb {
+            files: vec![
+                file("id1", "matrix.typ"),
+                file("id2", "vector.typ"),
+                file("id2", "tensor.typ"),
+            ],
+        };
+
+        let node = |id: &str, path: &str, parent: Option<(&str, &str)>| node::Node {
+            id: id.into(),
+            path: format!("/vault/{path}").into(),
+            kind: match parent {
+                Some(_) => node::NodeKind::Label,
+                None => node::NodeKind::File,
+            },
+            title: id.into(),
+            parent: parent.map(|(id, label)| node::Parent {
+                id: id.into(),
+                label: label.into(),
+            }),
+            ..Default::default()
+        };
+        let mut nodes = node::Db {
+            nodes: vec![
+                node("id1", "matrix.typ", None),
+                node("id2", "vector.typ", None),
+                node("id2", "tensor.typ", None),
+                node("id2:rank", "tensor.typ", Some(("id2", "rank"))),
+            ],
+        };
+
+        let link = |from: &str, to: &str| link::Link {
+            from: from.into(),
+            to: link::To::Id(to.into()),
+            ..Default::default()
+        };
+        let mut links = link::Db {
+            links: vec![
+                link("id1", "id2:rank"),
+                link("id1", "id2"),
+                link("id2", "id1"),
+            ],
+        };
+
+        let paths = paths("/vault".into(), &user_db, Which::Duplicates).unwrap();
+        assert_eq!(paths, [Utf8PathBuf::from("tensor.typ")]);
+
+        let renamed = rename(
+            "/vault",
+            &config,
+            &mut user_db,
+            &mut nodes,
+            &mut links,
+            &paths,
+        )
+        .unwrap();
+        assert_eq!(
+            renamed,
+            [Reid {
+                path: "tensor.typ".into(),
+                old: "id2".into(),
+                new: "tensor".into(),
+            }]
+        );
+
+        assert!(user_db.duplicate_ids().is_empty());
+        assert_eq!(
+            nodes
+                .nodes
+                .iter()
+                .map(|n| n.id.0.as_str())
+                .collect::<Vec<_>>(),
+            ["id1", "id2", "tensor", "tensor:rank"]
+        );
+        assert_eq!(
+            nodes.nodes[3].parent.as_ref().map(|p| p.id.clone()),
+            Some("tensor".into())
+        );
+        // the link to `id2` could be to either file, so it's left alone,
+        // and the one from `id2` is dropped until the next build
+        assert_eq!(
+            links.links,
+            [link("id1", "tensor:rank"), link("id1", "id2")]
+        );
+    }
+}
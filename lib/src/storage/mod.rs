@@ -0,0 +1,105 @@
+//! where the build databases (nodes and links) are kept, chosen with `[storage]` in omni.toml.
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite_storage;
+pub mod toml_storage;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::{
+    build::lock::BuildLock,
+    config::{Config, StorageBackend},
+    link, node,
+    transaction::TransactionError,
+};
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum StorageError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    TomlDeserializeError(#[from] toml::de::Error),
+
+    #[error(transparent)]
+    TomlSerializeError(#[from] toml::ser::Error),
+
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    TransactionError(#[from] TransactionError),
+
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    SqliteError(#[from] rusqlite::Error),
+
+    #[error("this omni was built without sqlite support")]
+    #[diagnostic(help(
+        "build omni with `--features sqlite`, or set `backend = \"toml\"` in `[storage]`"
+    ))]
+    SqliteNotEnabled,
+}
+
+/// A place to load and save the nodes and links databases.
+pub trait Storage: Send + std::fmt::Debug {
+    fn load_nodes(&self) -> Result<node::Db, StorageError>;
+
+    fn load_links(&self) -> Result<link::Db, StorageError>;
+
+    /// saves both databases in one go while holding the build lock,
+    /// only rewriting what changed if the backend allows it.
+    fn save(
+        &mut self,
+        lock: &BuildLock,
+        nodes: &node::Db,
+        links: &link::Db,
+    ) -> Result<(), StorageError>;
+
+    fn node_by_id(&self, id: &node::Id) -> Result<Option<node::Node>, StorageError>;
+
+    /// the nodes having `name` among their names
+    fn nodes_by_name(&self, name: &str) -> Result<Vec<node::Node>, StorageError>;
+
+    /// the nodes at `path` (canonical), ie. a file and what's inside it
+    fn nodes_by_path(&self, path: &Utf8Path) -> Result<Vec<node::Node>, StorageError>;
+}
+
+/// opens the storage chosen in `[storage]`
+pub fn open(root: impl AsRef<Utf8Path>, config: &Config) -> Result<Box<dyn Storage>, StorageError> {
+    let root = root.as_ref();
+    match config.storage.backend {
+        StorageBackend::Toml => Ok(Box::new(toml_storage::TomlStorage::new(root))),
+        #[cfg(feature = "sqlite")]
+        StorageBackend::Sqlite => Ok(Box::new(sqlite_storage::SqliteStorage::open(root)?)),
+        #[cfg(not(feature = "sqlite"))]
+        StorageBackend::Sqlite => Err(StorageError::SqliteNotEnabled),
+    }
+}
+
+/// the file that changes whenever the links are saved
+pub fn links_path(root: impl AsRef<Utf8Path>, config: &Config) -> Utf8PathBuf {
+    match config.storage.backend {
+        StorageBackend::Toml => root.as_ref().join(toml_storage::LINKS_FILE),
+        StorageBackend::Sqlite => root.as_ref().join(DB_FILE),
+    }
+}
+
+/// where the sqlite database is, relative to the root
+pub const DB_FILE: &str = "build/omni.db";
+
+/// writes the databases in `storage` to `build/nodes.toml` and `build/links.toml`, eg. to diff them
+pub fn export_toml(lock: &BuildLock, storage: &dyn Storage) -> Result<(), StorageError> {
+    let nodes = storage.load_nodes()?;
+    let links = storage.load_links()?;
+    toml_storage::TomlStorage::new(lock.root()).save(lock, &nodes, &links)
+}
+
+/// replaces the databases in `storage` with the ones in `build/nodes.toml` and `build/links.toml`
+pub fn import_toml(lock: &BuildLock, storage: &mut dyn Storage) -> Result<(), StorageError> {
+    let files = toml_storage::TomlStorage::new(lock.root());
+    storage.save(lock, &files.load_nodes()?, &files.load_links()?)
+}
@@ -0,0 +1,416 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use camino::Utf8Path;
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::{
+    build::lock::BuildLock,
+    link, node,
+    storage::{DB_FILE, Storage, StorageError, toml_storage},
+    transaction::{SCHEMA_VERSION, TransactionError},
+};
+
+/// nodes and links are kept whole as json in `data`,
+/// the other columns are only there to look them up.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS nodes (
+    id TEXT PRIMARY KEY,
+    seq INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS nodes_path ON nodes(path);
+
+CREATE TABLE IF NOT EXISTS names (
+    name TEXT NOT NULL,
+    node TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS names_name ON names(name);
+CREATE INDEX IF NOT EXISTS names_node ON names(node);
+
+CREATE TABLE IF NOT EXISTS links (
+    from_id TEXT NOT NULL,
+    to_id TEXT,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS links_from ON links(from_id);
+CREATE INDEX IF NOT EXISTS links_to ON links(to_id);
+";
+
+/// what the database holds as of the last load or save,
+/// so that saving only has to write what changed.
+#[derive(Debug, Default)]
+struct Saved {
+    /// None until the nodes are loaded or saved, then a save replaces all of them
+    nodes: Option<HashMap<node::Id, (usize, node::Node)>>,
+    /// the links of each source node, None like `nodes`
+    links: Option<HashMap<node::Id, Vec<link::Link>>>,
+}
+
+/// The databases in `build/omni.db`.
+/// saving only touches the nodes that changed and the links of the nodes whose links changed,
+/// all in one transaction.
+/// `build/nodes.toml` is still written on save when the nodes changed,
+/// since the typst plugin reads it.
+/// the schema version is kept in `PRAGMA user_version`.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Connection,
+    saved: RefCell<Saved>,
+}
+
+impl SqliteStorage {
+    pub fn open(root: impl AsRef<Utf8Path>) -> Result<Self, StorageError> {
+        let path = root.as_ref().join(DB_FILE);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(&path)?;
+
+        // like the toml databases, a database without a version is from before versions
+        let found: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if found > SCHEMA_VERSION {
+            return Err(TransactionError::NewerSchema { path, found }.into());
+        }
+        conn.execute_batch(SCHEMA)?;
+        if found == 0 {
+            conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+        Ok(Self {
+            conn,
+            saved: RefCell::default(),
+        })
+    }
+
+    fn query_nodes(
+        &self,
+        sql: &str,
+        param: impl rusqlite::ToSql,
+    ) -> Result<Vec<node::Node>, StorageError> {
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let rows = stmt.query_map([param], |row| row.get::<_, String>(0))?;
+        let mut nodes = vec![];
+        for data in rows {
+            nodes.push(serde_json::from_str(&data?)?);
+        }
+        Ok(nodes)
+    }
+}
+
+fn nodes_snapshot(nodes: &[node::Node]) -> HashMap<node::Id, (usize, node::Node)> {
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(seq, node)| (node.id.clone(), (seq, node.clone())))
+        .collect()
+}
+
+fn links_snapshot(links: &[link::Link]) -> HashMap<node::Id, Vec<link::Link>> {
+    let mut by_source: HashMap<node::Id, Vec<link::Link>> = HashMap::new();
+    for link in links {
+        by_source
+            .entry(link.from.clone())
+            .or_default()
+            .push(link.clone());
+    }
+    by_source
+}
+
+fn insert_names(tx: &rusqlite::Transaction, node: &node::Node) -> Result<(), StorageError> {
+    let mut stmt = tx.prepare_cached("INSERT INTO names (name, node) VALUES (?1, ?2)")?;
+    for name in &node.names {
+        stmt.execute(params![name, node.id.0.as_str()])?;
+    }
+    Ok(())
+}
+
+fn delete_node(tx: &rusqlite::Transaction, id: &str) -> Result<(), StorageError> {
+    tx.prepare_cached("DELETE FROM nodes WHERE id = ?1")?
+        .execute([id])?;
+    tx.prepare_cached("DELETE FROM names WHERE node = ?1")?
+        .execute([id])?;
+    Ok(())
+}
+
+/// writes the nodes that differ from `saved` (everything if it's None),
+/// returning whether anything changed
+fn save_nodes(
+    tx: &rusqlite::Transaction,
+    saved: Option<&HashMap<node::Id, (usize, node::Node)>>,
+    nodes: &node::Db,
+) -> Result<bool, StorageError> {
+    let mut changed = false;
+    if saved.is_none() {
+        tx.execute_batch("DELETE FROM nodes; DELETE FROM names;")?;
+        changed = true;
+    }
+
+    let mut seen = HashSet::new();
+    for (seq, node) in nodes.nodes.iter().enumerate() {
+        // ids are unique once checked, if not the last one wins
+        seen.insert(&node.id);
+        match saved.and_then(|saved| saved.get(&node.id)) {
+            Some((old_seq, old)) if old == node => {
+                if *old_seq != seq {
+                    tx.prepare_cached("UPDATE nodes SET seq = ?2 WHERE id = ?1")?
+                        .execute(params![node.id.0.as_str(), seq])?;
+                    changed = true;
+                }
+            }
+            _ => {
+                tx.prepare_cached(
+                    "INSERT OR REPLACE INTO nodes (id, seq, path, data) VALUES (?1, ?2, ?3, ?4)",
+                )?
+                .execute(params![
+                    node.id.0.as_str(),
+                    seq,
+                    node.path.as_str(),
+                    serde_json::to_string(node)?
+                ])?;
+                tx.prepare_cached("DELETE FROM names WHERE node = ?1")?
+                    .execute([node.id.0.as_str()])?;
+                insert_names(tx, node)?;
+                changed = true;
+            }
+        }
+    }
+    for id in saved.into_iter().flat_map(|saved| saved.keys()) {
+        if !seen.contains(id) {
+            delete_node(tx, id.0.as_str())?;
+            changed = true;
+        }
+    }
+
+    Ok(changed)
+}
+
+/// replaces the links of the source nodes whose links differ from `saved`
+/// (everything if it's None), like builds do
+fn save_links(
+    tx: &rusqlite::Transaction,
+    saved: Option<&HashMap<node::Id, Vec<link::Link>>>,
+    links: &link::Db,
+) -> Result<(), StorageError> {
+    if saved.is_none() {
+        tx.execute("DELETE FROM links", [])?;
+    }
+
+    let mut new_links: BTreeMap<&node::Id, Vec<&link::Link>> = BTreeMap::new();
+    for link in &links.links {
+        new_links.entry(&link.from).or_default().push(link);
+    }
+
+    for (from, group) in &new_links {
+        let unchanged = saved.and_then(|saved| saved.get(*from)).is_some_and(|old| {
+            old.len() == group.len() && old.iter().zip(group).all(|(o, n)| o == *n)
+        });
+        if unchanged {
+            continue;
+        }
+        tx.prepare_cached("DELETE FROM links WHERE from_id = ?1")?
+            .execute([from.0.as_str()])?;
+        let mut stmt =
+            tx.prepare_cached("INSERT INTO links (from_id, to_id, data) VALUES (?1, ?2, ?3)")?;
+        for link in group {
+            let to = match &link.to {
+                link::To::Id(id) => Some(id.0.as_str()),
+                link::To::Ghost(_) => None,
+            };
+            stmt.execute(params![from.0.as_str(), to, serde_json::to_string(link)?])?;
+        }
+    }
+    for from in saved.into_iter().flat_map(|saved| saved.keys()) {
+        if !new_links.contains_key(from) {
+            tx.prepare_cached("DELETE FROM links WHERE from_id = ?1")?
+                .execute([from.0.as_str()])?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn load_nodes(&self) -> Result<node::Db, StorageError> {
+        let mut stmt = self.conn.prepare("SELECT data FROM nodes ORDER BY seq")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut nodes: Vec<node::Node> = vec![];
+        for data in rows {
+            nodes.push(serde_json::from_str(&data?)?);
+        }
+
+        self.saved.borrow_mut().nodes = Some(nodes_snapshot(&nodes));
+        Ok(node::Db { nodes })
+    }
+
+    fn load_links(&self) -> Result<link::Db, StorageError> {
+        let mut stmt = self.conn.prepare("SELECT data FROM links ORDER BY rowid")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut links: Vec<link::Link> = vec![];
+        for data in rows {
+            links.push(serde_json::from_str(&data?)?);
+        }
+
+        self.saved.borrow_mut().links = Some(links_snapshot(&links));
+        Ok(link::Db { links })
+    }
+
+    fn save(
+        &mut self,
+        lock: &BuildLock,
+        nodes: &node::Db,
+        links: &link::Db,
+    ) -> Result<(), StorageError> {
+        let saved = self.saved.get_mut();
+        let tx = self.conn.transaction()?;
+        let nodes_changed = save_nodes(&tx, saved.nodes.as_ref(), nodes)?;
+        save_links(&tx, saved.links.as_ref(), links)?;
+        tx.commit()?;
+
+        saved.nodes = Some(nodes_snapshot(&nodes.nodes));
+        saved.links = Some(links_snapshot(&links.links));
+
+        if nodes_changed || !lock.root().join(toml_storage::NODES_FILE).exists() {
+            toml_storage::save_nodes(lock, nodes)?;
+        }
+        Ok(())
+    }
+
+    fn node_by_id(&self, id: &node::Id) -> Result<Option<node::Node>, StorageError> {
+        let data: Option<String> = self
+            .conn
+            .prepare_cached("SELECT data FROM nodes WHERE id = ?1")?
+            .query_row([id.0.as_str()], |row| row.get(0))
+            .optional()?;
+        Ok(data.map(|d| serde_json::from_str(&d)).transpose()?)
+    }
+
+    fn nodes_by_name(&self, name: &str) -> Result<Vec<node::Node>, StorageError> {
+        self.query_nodes(
+            "SELECT nodes.data FROM names JOIN nodes ON nodes.id = names.node
+            WHERE names.name = ?1 ORDER BY nodes.seq",
+            name,
+        )
+    }
+
+    fn nodes_by_path(&self, path: &Utf8Path) -> Result<Vec<node::Node>, StorageError> {
+        self.query_nodes(
+            "SELECT data FROM nodes WHERE path = ?1 ORDER BY seq",
+            path.as_str(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, path: &str, names: &[&str]) -> node::Node {
+        node::Node {
+            id: id.into(),
+            path: path.into(),
+            title: id.into(),
+            names: names.iter().map(|n| n.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn link(from: &str, to: &str) -> link::Link {
+        link::Link {
+            from: from.into(),
+            to: link::To::Id(to.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_schema_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+
+        let storage = SqliteStorage::open(root).unwrap();
+        let version = |storage: &SqliteStorage| -> u32 {
+            storage
+                .conn
+                .pragma_query_value(None, "user_version", |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(version(&storage), SCHEMA_VERSION);
+
+        storage
+            .conn
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        drop(storage);
+        assert!(matches!(
+            SqliteStorage::open(root),
+            Err(StorageError::TransactionError(
+                TransactionError::NewerSchema { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_sqlite_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+        let mut storage = SqliteStorage::open(root).unwrap();
+        let lock = BuildLock::try_acquire(root).unwrap();
+
+        let mut nodes = node::Db {
+            nodes: vec![
+                node("id1", "/vault/vector.typ", &["vector", "vectors"]),
+                node("id2", "/vault/matrix.typ", &["matrix"]),
+            ],
+        };
+        let mut links = link::Db {
+            links: vec![link("id1", "id2"), link("id2", "id1")],
+        };
+        storage.save(&lock, &nodes, &links).unwrap();
+
+        assert_eq!(storage.load_nodes().unwrap(), nodes);
+        assert_eq!(storage.load_links().unwrap().links, links.links);
+        assert_eq!(
+            storage.node_by_id(&"id2".into()).unwrap(),
+            Some(nodes.nodes[1].clone())
+        );
+        assert_eq!(
+            storage.nodes_by_name("vectors").unwrap(),
+            [nodes.nodes[0].clone()]
+        );
+        assert_eq!(
+            storage.nodes_by_path("/vault/matrix.typ".into()).unwrap(),
+            [nodes.nodes[1].clone()]
+        );
+        // for the typst plugin
+        assert!(root.join("build/nodes.toml").exists());
+
+        // a rebuild of vector.typ
+        nodes.nodes[0].names = vec!["vector".to_string()];
+        links.links.retain(|l| l.from != "id1".into());
+        links.links.push(link("id1", "id3"));
+        nodes.nodes.push(node("id3", "/vault/tensor.typ", &[]));
+        storage.save(&lock, &nodes, &links).unwrap();
+
+        assert_eq!(storage.load_nodes().unwrap(), nodes);
+        assert_eq!(storage.load_links().unwrap().links, links.links);
+        assert!(storage.nodes_by_name("vectors").unwrap().is_empty());
+
+        // nothing changed, so nothing is written
+        std::fs::write(root.join("build/nodes.toml"), "untouched").unwrap();
+        storage.save(&lock, &nodes, &links).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("build/nodes.toml")).unwrap(),
+            "untouched"
+        );
+
+        // reopening
+        nodes.nodes.remove(1);
+        links.links.retain(|l| l.from != "id2".into());
+        storage.save(&lock, &nodes, &links).unwrap();
+        let storage = SqliteStorage::open(root).unwrap();
+        assert_eq!(storage.load_nodes().unwrap(), nodes);
+        assert_eq!(storage.load_links().unwrap().links, links.links);
+        assert_eq!(storage.node_by_id(&"id2".into()).unwrap(), None);
+    }
+}
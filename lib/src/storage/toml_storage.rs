@@ -0,0 +1,148 @@
+use std::cell::RefCell;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{
+    build::lock::BuildLock,
+    link, node,
+    storage::{Storage, StorageError},
+    transaction::{self, Transaction},
+};
+
+/// relative to the root
+pub const NODES_FILE: &str = "build/nodes.toml";
+pub const LINKS_FILE: &str = "build/links.toml";
+
+/// The databases as `build/nodes.toml` and `build/links.toml`.
+/// everything is read and written as a whole, so lookups are linear,
+/// over the nodes read by the first lookup (or written by the last save).
+#[derive(Debug)]
+pub struct TomlStorage {
+    root: Utf8PathBuf,
+    nodes: RefCell<Option<node::Db>>,
+}
+
+impl TomlStorage {
+    pub fn new(root: impl AsRef<Utf8Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            nodes: RefCell::default(),
+        }
+    }
+
+    /// the nodes matching `f`
+    fn find(&self, f: impl Fn(&node::Node) -> bool) -> Result<Vec<node::Node>, StorageError> {
+        let mut nodes = self.nodes.borrow_mut();
+        if nodes.is_none() {
+            *nodes = Some(self.read(NODES_FILE)?);
+        }
+        Ok(nodes
+            .iter()
+            .flat_map(|db| &db.nodes)
+            .filter(|n| f(n))
+            .cloned()
+            .collect())
+    }
+
+    /// a missing file is an empty database, eg. before the first build
+    fn read<T: serde::de::DeserializeOwned>(&self, file: &str) -> Result<T, StorageError> {
+        let path = self.root.join(file);
+        let content = match std::fs::read(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err.into()),
+        };
+        Ok(transaction::from_toml(&content, path)?)
+    }
+}
+
+/// writes only `build/nodes.toml`, which the typst plugin reads whatever the storage
+pub fn save_nodes(lock: &BuildLock, nodes: &node::Db) -> Result<(), StorageError> {
+    Ok(Transaction::new(lock)
+        .write_db(NODES_FILE, nodes)?
+        .commit()?)
+}
+
+impl Storage for TomlStorage {
+    fn load_nodes(&self) -> Result<node::Db, StorageError> {
+        self.read(NODES_FILE)
+    }
+
+    fn load_links(&self) -> Result<link::Db, StorageError> {
+        self.read(LINKS_FILE)
+    }
+
+    fn save(
+        &mut self,
+        lock: &BuildLock,
+        nodes: &node::Db,
+        links: &link::Db,
+    ) -> Result<(), StorageError> {
+        Transaction::new(lock)
+            .write_db(NODES_FILE, nodes)?
+            .write_db(LINKS_FILE, links)?
+            .commit()?;
+        *self.nodes.get_mut() = Some(nodes.clone());
+        Ok(())
+    }
+
+    fn node_by_id(&self, id: &node::Id) -> Result<Option<node::Node>, StorageError> {
+        Ok(self.find(|n| &n.id == id)?.into_iter().next())
+    }
+
+    fn nodes_by_name(&self, name: &str) -> Result<Vec<node::Node>, StorageError> {
+        self.find(|n| n.names.iter().any(|n| n == name))
+    }
+
+    fn nodes_by_path(&self, path: &Utf8Path) -> Result<Vec<node::Node>, StorageError> {
+        self.find(|n| n.path == path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+        let mut storage = TomlStorage::new(root);
+        let lock = BuildLock::try_acquire(root).unwrap();
+
+        // before the first build
+        assert!(storage.load_nodes().unwrap().nodes.is_empty());
+        assert!(storage.load_links().unwrap().links.is_empty());
+
+        let node = node::Node {
+            id: "id1".into(),
+            path: "/vault/vector.typ".into(),
+            title: "Vector".into(),
+            names: vec!["vectors".into()],
+            ..Default::default()
+        };
+        let nodes = node::Db {
+            nodes: vec![node.clone()],
+        };
+        let links = link::Db {
+            links: vec![link::Link {
+                from: "id1".into(),
+                to: link::To::Id("id1".into()),
+                ..Default::default()
+            }],
+        };
+        storage.save(&lock, &nodes, &links).unwrap();
+
+        assert_eq!(storage.load_nodes().unwrap(), nodes);
+        assert_eq!(storage.load_links().unwrap().links, links.links);
+        assert_eq!(
+            storage.nodes_by_name("vectors").unwrap(),
+            vec![node.clone()]
+        );
+        assert_eq!(
+            storage.nodes_by_path("/vault/vector.typ".into()).unwrap(),
+            [node]
+        );
+        assert_eq!(storage.node_by_id(&"id2".into()).unwrap(), None);
+    }
+}
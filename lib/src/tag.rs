@@ -0,0 +1,145 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::node;
+
+/// a tag, like `math/linalg/spectral`, with the nodes tagged with it
+/// or with any tag nested inside of it.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Tag {
+    pub name: String,
+    #[serde(default)]
+    pub nodes: Vec<node::Id>,
+}
+
+/// The tags database found in `build/tags.toml`,
+/// made from the tags of the nodes in `build/nodes.toml`.
+/// sorted by name, so parents always come before their children.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Default, Clone)]
+pub struct Db {
+    #[serde(rename = "tag")]
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+}
+
+/// removes empty components, eg. `/math//linalg/` -> `math/linalg`
+pub fn normalize(tag: &str) -> String {
+    tag.split('/')
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// the tag containing this one, eg. `math/linalg` -> `math`
+pub fn parent(tag: &str) -> Option<&str> {
+    tag.rsplit_once('/').map(|(parent, _)| parent)
+}
+
+/// the tag itself and all the ones containing it,
+/// eg. `math/linalg` -> `math`, `math/linalg`
+pub fn ancestors(tag: &str) -> impl Iterator<Item = &str> {
+    tag.match_indices('/')
+        .map(|(i, _)| &tag[..i])
+        .chain(std::iter::once(tag))
+}
+
+/// the last component of a tag, eg. `math/linalg` -> `linalg`
+pub fn basename(tag: &str) -> &str {
+    tag.rsplit('/').next().unwrap_or(tag)
+}
+
+impl Db {
+    pub fn from_nodes(nodes: &node::Db) -> Self {
+        let mut tags: BTreeMap<String, BTreeSet<&node::Id>> = BTreeMap::new();
+
+        for node in &nodes.nodes {
+            for tag in &node.tags {
+                let tag = normalize(tag);
+                for ancestor in ancestors(&tag) {
+                    tags.entry(ancestor.to_string())
+                        .or_default()
+                        .insert(&node.id);
+                }
+            }
+        }
+
+        Self {
+            tags: tags
+                .into_iter()
+                .filter(|(name, _)| !name.is_empty())
+                .map(|(name, ids)| Tag {
+                    name,
+                    nodes: ids.into_iter().cloned().collect(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Tag> {
+        self.tags
+            .binary_search_by(|t| t.name.as_str().cmp(name))
+            .ok()
+            .map(|i| &self.tags[i])
+    }
+
+    /// the tags directly inside `parent`, or the top level ones if it's None
+    pub fn children<'a>(&'a self, of: Option<&'a str>) -> impl Iterator<Item = &'a Tag> {
+        self.tags.iter().filter(move |t| parent(&t.name) == of)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tags_from_nodes() {
+        let node = |id: &str, tags: &[&str]| node::Node {
+            id: id.into(),
+            path: format!("/vault/{id}.typ").into(),
+            title: id.into(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        };
+
+        let nodes = node::Db {
+            nodes: vec![
+                node("id1", &["math/linalg/spectral", "math/linalg"]),
+                node("id2", &["math/linalg/"]),
+                node("id3", &["physics", "/math"]),
+            ],
+        };
+
+        let db = Db::from_nodes(&nodes);
+
+        let tag = |name: &str, ids: &[&str]| Tag {
+            name: name.into(),
+            nodes: ids.iter().map(|&id| id.into()).collect(),
+        };
+        assert_eq!(
+            db.tags,
+            [
+                tag("math", &["id1", "id2", "id3"]),
+                tag("math/linalg", &["id1", "id2"]),
+                tag("math/linalg/spectral", &["id1"]),
+                tag("physics", &["id3"]),
+            ]
+        );
+
+        assert_eq!(db.find("math/linalg").map(|t| t.nodes.len()), Some(2));
+        assert_eq!(
+            db.children(None)
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>(),
+            ["math", "physics"]
+        );
+        assert_eq!(
+            db.children(Some("math"))
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>(),
+            ["math/linalg"]
+        );
+    }
+}
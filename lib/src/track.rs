@@ -1,6 +1,13 @@
+use std::time::Duration;
+
 use camino::{Utf8Path, Utf8PathBuf};
 
-use crate::node;
+use crate::{
+    build::lock::{BuildLock, LockError},
+    config::Config,
+    node,
+    transaction::{self, Transaction, TransactionError},
+};
 
 #[derive(thiserror::Error, miette::Diagnostic, Debug)]
 pub enum Error {
@@ -8,16 +15,22 @@ pub enum Error {
     IoError(#[from] std::io::Error),
 
     #[error(transparent)]
-    TomlDeserializeError(#[from] toml::de::Error),
+    #[diagnostic(transparent)]
+    TransactionError(#[from] TransactionError),
 
     #[error(transparent)]
-    TomlSerializeError(#[from] toml::ser::Error),
+    #[diagnostic(transparent)]
+    LockError(#[from] LockError),
 
     #[error("path given is outside project root")]
     OutsideRoot,
 
     #[error("{0} is already tracked")]
     AlreadyTracked(Utf8PathBuf),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    NodeError(#[from] node::Error),
 }
 
 fn is_already_tracked(db: &node::UserDb, target: impl AsRef<Utf8Path>) -> Result<bool, Error> {
@@ -37,33 +50,34 @@ fn is_already_tracked(db: &node::UserDb, target: impl AsRef<Utf8Path>) -> Result
 /// only check that it does is checking if the file is already tracked
 pub fn track(
     root: impl AsRef<Utf8Path>,
+    config: &Config,
     target: impl AsRef<Utf8Path>,
 ) -> Result<node::File, Error> {
     let target = target.as_ref().to_path_buf();
 
-    let db_path = root.as_ref().join("nodes.toml");
-    let db_file = std::fs::read(&db_path)?;
-
-    let mut db: node::UserDb = toml::from_slice(&db_file)?;
+    // builds and other tracks might be writing nodes.toml too
+    let lock = BuildLock::acquire(&root, Duration::from_secs(30))?;
+    let mut db: node::UserDb = transaction::read_toml(root.as_ref().join("nodes.toml"))?;
 
     // Check that target it not already tracked
     if is_already_tracked(&db, &target)? {
         return Err(Error::AlreadyTracked(target));
     };
 
+    let path: Utf8PathBuf = target
+        .strip_prefix(root.as_ref())
+        .map_err(|_| Error::OutsideRoot)?
+        .into();
     let file_node = node::File {
-        id: node::Id::new(&db),
-        path: target
-            .strip_prefix(root.as_ref())
-            .map_err(|_| Error::OutsideRoot)?
-            .into(),
+        id: node::Id::new(&db, config, &path)?,
+        path,
     };
     db.files.push(file_node.clone());
 
     // SAVEPOINT(user_db)
-
-    let new_toml = toml::to_string(&db)?;
-    std::fs::write(db_path, new_toml)?;
+    Transaction::new(&lock)
+        .write_db("nodes.toml", &db)?
+        .commit()?;
 
     Ok(file_node)
 }
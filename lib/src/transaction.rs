@@ -0,0 +1,206 @@
+//! writes the database files all together while holding the build lock,
+//! each one through a temporary file so that a crash never leaves it half written.
+
+use std::io::Write;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use miette::Diagnostic;
+use serde::{Serialize, de::DeserializeOwned};
+use thiserror::Error;
+
+use crate::build::lock::BuildLock;
+
+/// version of the layout of the database files, written at the top of each of them.
+/// files without it are from before it existed, and are read as version 1.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum TransactionError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    TomlDeserializeError(#[from] toml::de::Error),
+
+    #[error(transparent)]
+    TomlSerializeError(#[from] toml::ser::Error),
+
+    #[error("{path} has schema version {found}, but this omni only knows up to {SCHEMA_VERSION}")]
+    #[diagnostic(help("it was written by a newer omni, update this one"))]
+    NewerSchema { path: Utf8PathBuf, found: u32 },
+}
+
+/// serializes a database, with the schema version on top
+pub fn to_toml<T: Serialize>(db: &T) -> Result<String, toml::ser::Error> {
+    Ok(format!(
+        "schema_version = {SCHEMA_VERSION}\n\n{}",
+        toml::to_string(db)?
+    ))
+}
+
+/// the schema version at the top of a database, if it's there
+fn schema_version(content: &[u8]) -> Option<u32> {
+    let content = std::str::from_utf8(content).ok()?;
+    let line = content
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))?;
+    let (key, value) = line.split_once('=')?;
+    if key.trim() != "schema_version" {
+        return None;
+    }
+    value.trim().parse().ok()
+}
+
+/// deserializes a database read from `path`, refusing ones written by a newer omni
+pub fn from_toml<T: DeserializeOwned>(
+    content: &[u8],
+    path: impl AsRef<Utf8Path>,
+) -> Result<T, TransactionError> {
+    match schema_version(content) {
+        Some(found) if found > SCHEMA_VERSION => Err(TransactionError::NewerSchema {
+            path: path.as_ref().to_path_buf(),
+            found,
+        }),
+        _ => Ok(toml::from_slice(content)?),
+    }
+}
+
+/// reads and deserializes the database at `path`, see `from_toml`
+pub fn read_toml<T: DeserializeOwned>(path: impl AsRef<Utf8Path>) -> Result<T, TransactionError> {
+    let path = path.as_ref();
+    from_toml(&std::fs::read(path)?, path)
+}
+
+/// the temporary file `path` is written to before being renamed
+fn temp_path(path: &Utf8Path) -> Utf8PathBuf {
+    path.with_file_name(format!(".{}.tmp", path.file_name().unwrap_or_default()))
+}
+
+fn write_temp(path: &Utf8Path, content: &[u8]) -> std::io::Result<Utf8PathBuf> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp = temp_path(path);
+    let mut file = std::fs::File::create(&temp)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    Ok(temp)
+}
+
+/// replaces the file at `path` with `content` in one step,
+/// for files outside of a transaction, like the ones the typst plugin reads mid build.
+pub fn write_atomic(path: impl AsRef<Utf8Path>, content: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let temp = write_temp(path, content.as_ref())?;
+    std::fs::rename(temp, path)
+}
+
+/// Files to write together, staged and then committed while holding the build lock.
+///
+/// On commit every file is first written next to its destination,
+/// and only once they're all there are they renamed over the old ones.
+#[derive(Debug)]
+#[must_use = "nothing is written until the transaction is committed"]
+pub struct Transaction<'a> {
+    lock: &'a BuildLock,
+    staged: Vec<(Utf8PathBuf, Vec<u8>)>,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new(lock: &'a BuildLock) -> Self {
+        Self {
+            lock,
+            staged: vec![],
+        }
+    }
+
+    /// stages `content` for `path`, relative to the root
+    pub fn write(mut self, path: impl AsRef<Utf8Path>, content: impl Into<Vec<u8>>) -> Self {
+        let path = self.lock.root().join(path);
+        self.staged.retain(|(p, _)| p != &path);
+        self.staged.push((path, content.into()));
+        self
+    }
+
+    /// stages a database for `path`, relative to the root, see `to_toml`
+    pub fn write_db<T: Serialize>(
+        self,
+        path: impl AsRef<Utf8Path>,
+        db: &T,
+    ) -> Result<Self, TransactionError> {
+        Ok(self.write(path, to_toml(db)?))
+    }
+
+    pub fn commit(self) -> Result<(), TransactionError> {
+        let mut temps = vec![];
+        for (path, content) in &self.staged {
+            match write_temp(path, content) {
+                Ok(temp) => temps.push((temp, path)),
+                Err(err) => {
+                    for (temp, _) in temps {
+                        let _ = std::fs::remove_file(temp);
+                    }
+                    return Err(err.into());
+                }
+            }
+        }
+        for (temp, path) in temps {
+            std::fs::rename(temp, path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::node;
+
+    #[test]
+    fn test_transaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+        let lock = BuildLock::acquire(root, Duration::from_secs(1)).unwrap();
+
+        let db = node::UserDb {
+            files: vec![node::File {
+                id: "id1".into(),
+                path: "vector.typ".into(),
+            }],
+        };
+        Transaction::new(&lock)
+            .write_db("nodes.toml", &db)
+            .unwrap()
+            .write("build/root", root.as_str())
+            .commit()
+            .unwrap();
+
+        let content = std::fs::read_to_string(root.join("nodes.toml")).unwrap();
+        assert!(content.starts_with("schema_version = 1\n"));
+        let read: node::UserDb = read_toml(root.join("nodes.toml")).unwrap();
+        assert_eq!(read.files, db.files);
+        assert_eq!(
+            std::fs::read_to_string(root.join("build/root")).unwrap(),
+            root.as_str()
+        );
+        // no temporary files are left behind
+        assert!(!root.join(".nodes.toml.tmp").exists());
+
+        // from before the header
+        let old: node::UserDb = from_toml(
+            b"[[file]]\nid = \"id1\"\npath = \"vector.typ\"\n",
+            "nodes.toml",
+        )
+        .unwrap();
+        assert_eq!(old.files, db.files);
+
+        let newer = from_toml::<node::UserDb>(b"# a comment\nschema_version = 2\n", "nodes.toml");
+        assert!(matches!(
+            newer,
+            Err(TransactionError::NewerSchema { found: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use dashmap::DashMap;
+use omni::build::cache::{Cache, CacheError};
+use omni::build::directory::{self, DirectoryError};
+use omni::build::full::{self, FullError, Phase};
+use omni::build::lock::{BuildLock, LockError};
+use omni::build::partial::PartialError;
+use omni::build::tags::{self, TagsError};
+use omni::storage::{self, StorageError};
+use omni::transaction::{self, Transaction, TransactionError};
+use omni::{config::Config, node, tag};
+use thiserror::Error;
+use tower_lsp_server::Client;
+use tower_lsp_server::ls_types::*;
+
+use crate::backend::Backend;
+use crate::err_log_ext::ErrLogExt;
+use crate::project::Project;
+
+/// saves closer than this to each other are built together
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// how long to wait for another build (eg. from the CLI) to release the lock
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug)]
+pub enum BuildError {
+    #[error(transparent)]
+    PartialError(#[from] PartialError),
+
+    #[error(transparent)]
+    FullError(#[from] FullError),
+
+    #[error(transparent)]
+    LockError(#[from] LockError),
+
+    #[error(transparent)]
+    DirectoryError(#[from] DirectoryError),
+
+    #[error(transparent)]
+    TagsError(#[from] TagsError),
+
+    #[error(transparent)]
+    NodeError(#[from] node::Error),
+
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
+
+    #[error(transparent)]
+    TransactionError(#[from] TransactionError),
+
+    #[error(transparent)]
+    CacheError(#[from] CacheError),
+
+    #[error("the build task panicked")]
+    Panicked,
+}
+
+/// State of the builds triggered by saving the documents of a project.
+#[derive(Debug, Default)]
+pub struct SaveBuilds {
+    /// bumped on every save, a debounced build only starts if no save came after it
+    generation: u64,
+
+    /// paths of the files saved but not built yet
+    pending: HashSet<Utf8PathBuf>,
+
+    /// progress token and cancel flag of the running build, if any
+    running: Option<(NumberOrString, Arc<AtomicBool>)>,
+}
+
+/// runs a partial build of each file and saves the build databases after each one.
+/// a cancelled build stops between files, keeping what it built so far.
+///
+/// returns whether all files were built.
+fn build_files(
+    root: &Utf8Path,
+    config: &Config,
+    files: &[node::File],
+    cancel: &AtomicBool,
+) -> Result<bool, BuildError> {
+    // the CLI might be building too
+    let lock = BuildLock::acquire(root, LOCK_TIMEOUT)?;
+
+    // ids might collide after merging notes made on different machines
+    let user_db: node::UserDb = transaction::read_toml(root.join("nodes.toml"))?;
+    user_db.check_ids()?;
+
+    // we have to get new nodes and links because we cannot mutate project.nodes and links
+    let mut storage = storage::open(root, config)?;
+    let mut nodes = storage.load_nodes()?;
+    let mut links = storage.load_links()?;
+
+    let mut built = 0;
+    for file in files {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        omni::build::partial::partial(
+            &lock,
+            config,
+            storage.as_mut(),
+            &mut nodes,
+            &mut links,
+            file,
+            true,
+        )?;
+        // SAVEPOINT(nodes, links): the next files look up the nodes of this one in the storage
+        storage.save(&lock, &nodes, &links)?;
+        built += 1;
+    }
+    // nothing to save
+    if built == 0 && !files.is_empty() {
+        return Ok(false);
+    }
+
+    // SAVEPOINT(tags, root)
+    let tags = tag::Db::from_nodes(&nodes);
+    let transaction = Transaction::new(&lock)
+        .write_db("build/tags.toml", &tags)?
+        .write("build/root", root.as_str());
+    Cache::forget_public(root, transaction)?.commit()?;
+
+    directory::write_pages(root, config, &nodes)?;
+    tags::write_pages(root, config, &nodes, &tags)?;
+
+    Ok(built == files.len())
+}
+
+/// runs a partial build of `file` and saves the build databases
+pub async fn build_and_save(
+    root: &Utf8Path,
+    config: &Config,
+    file: &node::File,
+) -> Result<(), BuildError> {
+    let root = root.to_path_buf();
+    let config = config.clone();
+    let files = [file.clone()];
+
+    tokio::task::spawn_blocking(move || {
+        build_files(&root, &config, &files, &AtomicBool::new(false)).map(|_| ())
+    })
+    .await
+    .map_err(|_| BuildError::Panicked)?
+}
+
+/// runs a full build of the project, reporting its phases with `on_phase`.
+/// files that fail don't stop it, they're in the report.
+pub async fn build_all(
+    root: &Utf8Path,
+    config: &Config,
+    on_phase: impl Fn(Phase) + Send + 'static,
+) -> Result<full::Report, BuildError> {
+    let root = root.to_path_buf();
+    let config = config.clone();
+
+    tokio::task::spawn_blocking(move || {
+        // the CLI might be building too
+        let lock = BuildLock::acquire(&root, LOCK_TIMEOUT)?;
+        Ok(full::full(
+            &lock,
+            &config,
+            &full::Options::default(),
+            on_phase,
+        )?)
+    })
+    .await
+    .map_err(|_| BuildError::Panicked)?
+}
+
+impl Backend {
+    /// schedules a build of the saved document,
+    /// which will be done together with the ones saved right after it.
+    pub async fn build_on_save(&self, uri: &Uri) {
+        let (root, path) = {
+            let Some(document) = self.documents.get(uri) else {
+                return;
+            };
+            let Some(root) = document.project_root.clone() else {
+                return;
+            };
+            (root, document.path.clone())
+        };
+
+        // only tracked files can be built
+        match self.projects.get(&root) {
+            Some(project) if project.tracked_file(&path).is_ok() => {}
+            _ => return,
+        }
+
+        let generation = {
+            let mut builds = self.save_builds.entry(root.clone()).or_default();
+            builds.generation += 1;
+            builds.pending.insert(path);
+
+            // the running build is stale now, and its files will be built again
+            if let Some((_, cancel)) = &builds.running {
+                cancel.store(true, Ordering::Relaxed);
+            }
+
+            builds.generation
+        };
+
+        let save_builds = Arc::clone(&self.save_builds);
+        let projects = Arc::clone(&self.projects);
+        let client = self.client.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(DEBOUNCE).await;
+            run_save_builds(root, generation, save_builds, projects, client).await;
+        });
+    }
+
+    /// cancels the build on save reporting progress with `token`
+    pub fn cancel_build(&self, token: &NumberOrString) {
+        for builds in self.save_builds.iter() {
+            if let Some((running_token, cancel)) = &builds.running
+                && running_token == token
+            {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+async fn run_save_builds(
+    root: Utf8PathBuf,
+    mut generation: u64,
+    save_builds: Arc<DashMap<Utf8PathBuf, SaveBuilds>>,
+    projects: Arc<DashMap<Utf8PathBuf, Project>>,
+    client: Client,
+) {
+    loop {
+        let token = NumberOrString::String(format!("omni-build-on-save-{generation}"));
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let paths: Vec<Utf8PathBuf> = {
+            let Some(mut builds) = save_builds.get_mut(&root) else {
+                return;
+            };
+            // a later save (or the running build) will take care of it
+            if builds.generation != generation
+                || builds.running.is_some()
+                || builds.pending.is_empty()
+            {
+                return;
+            }
+            builds.running = Some((token.clone(), Arc::clone(&cancel)));
+            builds.pending.drain().collect()
+        };
+
+        let (config, files) = {
+            let Some(project) = projects.get(&root) else {
+                if let Some(mut builds) = save_builds.get_mut(&root) {
+                    builds.running = None;
+                }
+                return;
+            };
+            let files: Vec<node::File> = paths
+                .iter()
+                .filter_map(|path| project.tracked_file(path).ok().cloned())
+                .collect();
+            (project.config.clone(), files)
+        };
+
+        let progress = client
+            .progress(token, "Building")
+            .with_message(match files.len() {
+                1 => "1 file".to_string(),
+                n => format!("{n} files"),
+            })
+            .with_cancel_button()
+            .begin()
+            .await;
+
+        let root_clone = root.clone();
+        let cancel_clone = Arc::clone(&cancel);
+        let result = tokio::task::spawn_blocking(move || {
+            build_files(&root_clone, &config, &files, &cancel_clone)
+        })
+        .await
+        .map_err(|_| BuildError::Panicked)
+        .and_then(|res| res);
+
+        match result
+            .log_err("build on save failed")
+            .show_err_client("build err", &client)
+            .await
+        {
+            Ok(true) => progress.finish_with_message("Done").await,
+            Ok(false) => progress.finish_with_message("Cancelled").await,
+            Err(_) => progress.finish_with_message("Failed").await,
+        }
+
+        let Some(mut builds) = save_builds.get_mut(&root) else {
+            return;
+        };
+        builds.running = None;
+
+        // cancelled files still have to be built, unless they were cancelled by the user
+        let superseded = builds.generation != generation;
+        if cancel.load(Ordering::Relaxed) && superseded {
+            builds.pending.extend(paths);
+        }
+
+        // saves that came in while we were building found us running and gave up,
+        // so we have to build them ourselves
+        if !superseded || builds.pending.is_empty() {
+            return;
+        }
+        generation = builds.generation;
+    }
+}
@@ -0,0 +1,174 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use itertools::Itertools;
+use omni::{link, node};
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::jsonrpc::Result;
+use tower_lsp_server::ls_types::*;
+
+use crate::backend::Backend;
+
+/// what we keep inside `CallHierarchyItem::data` to find the node again
+#[derive(Debug, Serialize, Deserialize)]
+struct ItemData {
+    root: Utf8PathBuf,
+    id: node::Id,
+}
+
+fn node_item(root: &Utf8Path, node: &node::Node) -> Option<CallHierarchyItem> {
+    let uri = Uri::from_file_path(&node.path)?;
+    let range = Range::default();
+
+    Some(CallHierarchyItem {
+        name: node.title.clone(),
+        kind: SymbolKind::FILE,
+        tags: None,
+        detail: Some(
+            node.path
+                .strip_prefix(root)
+                .unwrap_or(&node.path)
+                .to_string(),
+        ),
+        uri,
+        range,
+        selection_range: range,
+        data: serde_json::to_value(ItemData {
+            root: root.to_path_buf(),
+            id: node.id.clone(),
+        })
+        .ok(),
+    })
+}
+
+fn item_data(item: &CallHierarchyItem) -> Option<ItemData> {
+    serde_json::from_value(item.data.clone()?).ok()
+}
+
+/// where a link is in the file it comes from, as found by the last build
+pub fn link_range(link: &link::Link) -> Option<Range> {
+    let span = link.span?;
+    Some(Range {
+        start: Position::new(span.line as u32, span.column as u32),
+        end: Position::new(span.line as u32, span.end_column.max(span.column) as u32),
+    })
+}
+
+/// the hierarchy starts from the link under the cursor, or from the document itself
+pub async fn prepare_call_hierarchy(
+    backend: &Backend,
+    params: CallHierarchyPrepareParams,
+) -> Result<Option<Vec<CallHierarchyItem>>> {
+    let uri = params.text_document_position_params.text_document.uri;
+    let Some(document) = backend.documents.get(&uri) else {
+        return Ok(None);
+    };
+    let Some(root) = &document.project_root else {
+        return Ok(None);
+    };
+    let Some(project) = backend.projects.get(root) else {
+        return Ok(None);
+    };
+
+    let under_cursor = document.link_under_cursor(params.text_document_position_params.position);
+
+    let node = match under_cursor {
+        Some(unresolved) => match project.find_from_filepart(root, &unresolved.file_part) {
+            Ok(node) => node,
+            Err(_) => return Ok(None),
+        },
+        None => {
+            let Ok(path) = document.path.canonicalize_utf8() else {
+                return Ok(None);
+            };
+            match project.find_abs(&path) {
+                Ok(node) => node,
+                Err(_) => return Ok(None),
+            }
+        }
+    };
+
+    Ok(node_item(root, &node).map(|item| vec![item]))
+}
+
+/// incoming calls are backlinks
+pub async fn incoming_calls(
+    backend: &Backend,
+    params: CallHierarchyIncomingCallsParams,
+) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+    let Some(data) = item_data(&params.item) else {
+        return Ok(None);
+    };
+
+    let Some(project) = backend.projects.get(&data.root) else {
+        return Ok(None);
+    };
+
+    let by_id = project.nodes.by_id();
+    let calls = project
+        .links
+        .links
+        .iter()
+        .filter(|l| l.to == link::To::Id(data.id.clone()))
+        .map(|l| &l.from)
+        .unique()
+        .filter_map(|from| by_id.get(from))
+        .filter_map(|node| {
+            let mut item = node_item(&data.root, node)?;
+            let backlinks = || {
+                project
+                    .links
+                    .backlinks(&data.id)
+                    .filter(|l| l.from == node.id)
+            };
+            // how the caller relates to the node, eg. `prerequisite`
+            let relations = backlinks()
+                .filter_map(|l| l.rel.as_deref())
+                .unique()
+                .join(", ");
+            if !relations.is_empty() {
+                item.detail = item.detail.map(|detail| format!("{detail} · {relations}"));
+            }
+            Some(CallHierarchyIncomingCall {
+                from: item,
+                from_ranges: backlinks().filter_map(link_range).collect(),
+            })
+        })
+        .collect();
+
+    Ok(Some(calls))
+}
+
+/// outgoing calls are forward links
+pub async fn outgoing_calls(
+    backend: &Backend,
+    params: CallHierarchyOutgoingCallsParams,
+) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+    let Some(data) = item_data(&params.item) else {
+        return Ok(None);
+    };
+
+    let Some(project) = backend.projects.get(&data.root) else {
+        return Ok(None);
+    };
+    let my_links = || project.links.links.iter().filter(|l| l.from == data.id);
+    let by_id = project.nodes.by_id();
+
+    let calls = my_links()
+        .filter_map(|l| match &l.to {
+            link::To::Id(id) => Some(id),
+            link::To::Ghost(_) => None,
+        })
+        .unique()
+        .filter_map(|id| by_id.get(id))
+        .filter_map(|node| {
+            Some(CallHierarchyOutgoingCall {
+                to: node_item(&data.root, node)?,
+                from_ranges: my_links()
+                    .filter(|l| l.to == link::To::Id(node.id.clone()))
+                    .filter_map(link_range)
+                    .collect(),
+            })
+        })
+        .collect();
+
+    Ok(Some(calls))
+}
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 
 use camino::{Utf8Path, Utf8PathBuf};
+use omni::node;
+use omni::omni_path::OmniPath;
 use tera::Tera;
 use tower_lsp_server::jsonrpc::Result;
 use tower_lsp_server::ls_types::*;
@@ -20,28 +22,259 @@ pub async fn code_action(
     };
 
     let mut commands = vec![];
+    commands.extend(get_materialize_actions(backend, &uri, &root, params.range).await?);
+    commands.extend(get_disambiguate_actions(
+        backend,
+        &uri,
+        &root,
+        params.range,
+        &params.context.diagnostics,
+    )?);
+    commands.extend(get_extract_actions(backend, &uri, &root, params.range).await?);
     commands.extend(get_template_actions(backend, &uri, &root).await?);
     commands.extend(get_build_actions(backend, &uri, &root).await?);
 
     Ok(Some(commands))
 }
 
+/// lists all template names in `resources/templates`
+async fn list_templates(backend: &Backend, root: impl AsRef<Utf8Path>) -> Result<Vec<String>> {
+    let mut templates = tokio::fs::read_dir(root.as_ref().join("resources/templates"))
+        .await
+        .log_err_client("cannot read resources/templates", &backend.client)
+        .await
+        .rpc()?;
+
+    let mut names = vec![];
+    while let Ok(Some(entry)) = templates.next_entry().await {
+        if let Some(stem) = entry.path().file_stem() {
+            names.push(stem.to_string_lossy().to_string());
+        }
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+/// on a ghost link, offers to create the node it points to from each template
+async fn get_materialize_actions(
+    backend: &Backend,
+    uri: &Uri,
+    root: impl AsRef<Utf8Path>,
+    range: Range,
+) -> Result<Vec<CodeActionOrCommand>> {
+    let file_part = {
+        let Some(document) = backend.documents.get(uri) else {
+            return Ok(vec![]);
+        };
+        let Some(unresolved) = document.link_under_cursor(range.start) else {
+            return Ok(vec![]);
+        };
+        let Some(project) = backend.projects.get(root.as_ref()) else {
+            return Ok(vec![]);
+        };
+
+        match project.find_from_filepart(root.as_ref(), &unresolved.file_part) {
+            Err(node::Error::NameNotFound(_)) => unresolved.file_part,
+            _ => return Ok(vec![]),
+        }
+    };
+
+    let omni_path = OmniPath::from(file_part.clone());
+    let file_part_arg = serde_json::to_value(&file_part).rpc()?;
+
+    let actions = list_templates(backend, &root)
+        .await?
+        .into_iter()
+        .map(|template| {
+            CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!(
+                    "Create `{}` from template \"{}\"",
+                    omni_path.as_typst_style(),
+                    template
+                ),
+                kind: Some(CodeActionKind::QUICKFIX),
+                command: Some(Command {
+                    title: "Create node".into(),
+                    command: "code_action_materialize".into(),
+                    arguments: Some(vec![
+                        serde_json::Value::String(uri.to_string()),
+                        serde_json::Value::String(template),
+                        file_part_arg.clone(),
+                    ]),
+                }),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    Ok(actions)
+}
+
+/// on an ambiguous link, offers to rewrite it to the shortest unambiguous form
+/// of each candidate, as given by [`crate::links::get_possible_links`]
+fn get_disambiguate_actions(
+    backend: &Backend,
+    uri: &Uri,
+    root: impl AsRef<Utf8Path>,
+    range: Range,
+    diagnostics: &[Diagnostic],
+) -> Result<Vec<CodeActionOrCommand>> {
+    let Some(document) = backend.documents.get(uri) else {
+        return Ok(vec![]);
+    };
+    let Some(syntax) = document.syntax() else {
+        return Ok(vec![]);
+    };
+    let Some(span) = document.link_span_under_cursor(range.start) else {
+        return Ok(vec![]);
+    };
+    let Some(project) = backend.projects.get(root.as_ref()) else {
+        return Ok(vec![]);
+    };
+
+    let Err(node::Error::DuplicateName(name)) =
+        project.find_from_filepart(root.as_ref(), &span.file_part)
+    else {
+        return Ok(vec![]);
+    };
+
+    let candidates = project
+        .find_all_from_filepart(root.as_ref(), &span.file_part)
+        .rpc()?;
+
+    let links = crate::links::get_possible_links(root.as_ref(), &project.config, &project.nodes)
+        .log_err("error while getting links for disambiguation")
+        .rpc()?;
+
+    let line = range.start.line;
+    let edit_range = Range {
+        start: Position::new(line, span.file_range.start as u32),
+        end: Position::new(line, span.file_range.end as u32),
+    };
+    let link_range = Range {
+        start: Position::new(line, span.range.start as u32),
+        end: Position::new(line, span.range.end as u32),
+    };
+
+    // the diagnostics this fixes, so that the client can tie them together
+    let fixed: Vec<Diagnostic> = diagnostics
+        .iter()
+        .filter(|d| d.source.as_deref() == Some("omni"))
+        .filter(|d| d.severity == Some(DiagnosticSeverity::ERROR))
+        .filter(|d| d.range == link_range)
+        .cloned()
+        .collect();
+
+    let actions = candidates
+        .into_iter()
+        .filter_map(|node| {
+            links
+                .iter()
+                .find(|l| l.id == node.id && l.omni_path.name == name)
+                .map(|l| (node, l))
+        })
+        .map(|(node, entry)| {
+            let new_text = syntax.format_file_part(&entry.omni_path);
+            let relative = node.path.strip_prefix(root.as_ref()).unwrap_or(&node.path);
+
+            CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Link to `{new_text}` ({relative})"),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: (!fixed.is_empty()).then(|| fixed.clone()),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(
+                        uri.clone(),
+                        vec![TextEdit {
+                            range: edit_range,
+                            new_text,
+                        }],
+                    )])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    Ok(actions)
+}
+
+/// on a selection, offers to move it into a new node made from each template
+/// with the same extension as the document
+async fn get_extract_actions(
+    backend: &Backend,
+    uri: &Uri,
+    root: impl AsRef<Utf8Path>,
+    range: Range,
+) -> Result<Vec<CodeActionOrCommand>> {
+    if range.start == range.end || !backend.projects.contains_key(root.as_ref()) {
+        return Ok(vec![]);
+    }
+
+    let my_extension = {
+        let Some(document) = backend.documents.get(uri) else {
+            return Ok(vec![]);
+        };
+        if document.project_root.is_none() {
+            return Ok(vec![]);
+        }
+        document.path.extension().unwrap_or_default().to_string()
+    };
+
+    let range_arg = serde_json::to_value(range).rpc()?;
+
+    let actions = list_templates(backend, &root)
+        .await?
+        .into_iter()
+        .filter(|template| {
+            omni::get_template::get_template(&root, template)
+                .is_ok_and(|(_, extension)| extension == my_extension)
+        })
+        .map(|template| {
+            CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Extract into a new node from template \"{}\"", template),
+                kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+                command: Some(Command {
+                    title: "Extract".into(),
+                    command: "code_action_extract".into(),
+                    arguments: Some(vec![
+                        serde_json::Value::String(uri.to_string()),
+                        serde_json::Value::String(template),
+                        range_arg.clone(),
+                    ]),
+                }),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    Ok(actions)
+}
+
 async fn get_build_actions(
     backend: &Backend,
     uri: &Uri,
     root: impl AsRef<Utf8Path>,
-) -> Result<Option<CodeActionOrCommand>> {
+) -> Result<Vec<CodeActionOrCommand>> {
     if !backend.projects.contains_key(&root.as_ref().to_path_buf()) {
-        return Ok(None);
+        return Ok(vec![]);
     };
 
     let target_arg = serde_json::Value::String(uri.to_string());
 
-    Ok(Some(CodeActionOrCommand::Command(Command {
-        title: "Build".into(),
-        command: "code_action_build".into(),
-        arguments: Some(vec![target_arg]),
-    })))
+    Ok(vec![
+        CodeActionOrCommand::Command(Command {
+            title: "Build".into(),
+            command: "code_action_build".into(),
+            arguments: Some(vec![target_arg.clone()]),
+        }),
+        CodeActionOrCommand::Command(Command {
+            title: "Build all".into(),
+            command: "code_action_build_all".into(),
+            arguments: Some(vec![target_arg]),
+        }),
+    ])
 }
 
 async fn get_template_actions(
@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+
+use camino::Utf8PathBuf;
+use itertools::Itertools;
+use omni::{link, node};
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::jsonrpc::Result;
+use tower_lsp_server::ls_types::*;
+
+use crate::backend::Backend;
+use crate::backend::call_hierarchy::link_range;
+
+/// what we keep inside `CodeLens::data` to resolve the backlinks lazily,
+/// as finding where they are means reading every file linking here.
+#[derive(Debug, Serialize, Deserialize)]
+struct BacklinksData {
+    uri: Uri,
+    root: Utf8PathBuf,
+    id: node::Id,
+    count: usize,
+    #[serde(default)]
+    relations: BTreeMap<String, usize>,
+}
+
+/// everything is shown at the top of the file
+const TOP: Range = Range {
+    start: Position {
+        line: 0,
+        character: 0,
+    },
+    end: Position {
+        line: 0,
+        character: 0,
+    },
+};
+
+fn plural(count: usize, what: &str) -> String {
+    match count {
+        1 => format!("1 {what}"),
+        n => format!("{n} {what}s"),
+    }
+}
+
+/// eg. `3 backlinks (2 prerequisite, 1 example)`
+pub fn backlinks_title<'a>(
+    count: usize,
+    relations: impl IntoIterator<Item = (&'a str, usize)>,
+) -> String {
+    let relations = relations
+        .into_iter()
+        .map(|(rel, n)| format!("{n} {rel}"))
+        .join(", ");
+    match relations.is_empty() {
+        true => plural(count, "backlink"),
+        false => format!("{} ({relations})", plural(count, "backlink")),
+    }
+}
+
+/// a lens listing `locations` in the references view when clicked
+fn show_references(title: String, uri: &Uri, locations: Vec<Location>) -> Command {
+    Command {
+        title,
+        command: "editor.action.showReferences".into(),
+        arguments: Some(vec![
+            serde_json::json!(uri),
+            serde_json::json!(TOP.start),
+            serde_json::json!(locations),
+        ]),
+    }
+}
+
+pub async fn code_lens(backend: &Backend, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+    let uri = params.text_document.uri;
+
+    let Some(document) = backend.documents.get(&uri) else {
+        return Ok(None);
+    };
+    let Some(root) = &document.project_root else {
+        return Ok(None);
+    };
+    let Some(project) = backend.projects.get(root) else {
+        return Ok(None);
+    };
+    let uri_arg = serde_json::Value::String(uri.to_string());
+
+    if project.tracked_file(&document.path).is_err() {
+        let mut notes_dir = root.clone();
+        if let Some(prefix_dir) = &project.config.project.prefix_dir {
+            notes_dir.push(prefix_dir);
+        }
+
+        let in_notes_dir = document
+            .path
+            .canonicalize_utf8()
+            .is_ok_and(|p| p.starts_with(&notes_dir));
+        if !in_notes_dir {
+            return Ok(None);
+        }
+
+        return Ok(Some(vec![CodeLens {
+            range: TOP,
+            command: Some(Command {
+                title: "Track".into(),
+                command: "code_action_track".into(),
+                arguments: Some(vec![uri_arg]),
+            }),
+            data: None,
+        }]));
+    }
+
+    let mut lenses = vec![];
+
+    // the node is missing if the file was never built
+    let node = document
+        .path
+        .canonicalize_utf8()
+        .ok()
+        .and_then(|p| project.find_abs(&p).ok());
+
+    if let Some(node) = node {
+        let backlinks = project
+            .links
+            .links
+            .iter()
+            .filter(|l| l.to == link::To::Id(node.id.clone()))
+            .map(|l| &l.from)
+            .unique()
+            .count();
+
+        lenses.push(CodeLens {
+            range: TOP,
+            command: None,
+            data: serde_json::to_value(BacklinksData {
+                uri: uri.clone(),
+                root: root.clone(),
+                id: node.id.clone(),
+                count: backlinks,
+                relations: project
+                    .links
+                    .relations_to(&node.id)
+                    .into_iter()
+                    .map(|(rel, n)| (rel.to_string(), n))
+                    .collect(),
+            })
+            .ok(),
+        });
+
+        let ghosts = project
+            .links
+            .links
+            .iter()
+            .filter(|l| l.from == node.id)
+            .filter_map(|l| match &l.to {
+                link::To::Ghost(file_part) => Some(file_part),
+                link::To::Id(_) => None,
+            })
+            .unique()
+            .count();
+
+        let ghost_locations = match document.syntax() {
+            Some(syntax) => syntax
+                .find_links_in(&document.content.to_string())
+                .into_iter()
+                .map(|(location, span)| (location.line, span))
+                .filter(|(_, span)| {
+                    matches!(
+                        project.find_from_filepart(root, &span.file_part),
+                        Err(node::Error::NameNotFound(_))
+                    )
+                })
+                .map(|(line_no, span)| Location {
+                    uri: uri.clone(),
+                    range: Range {
+                        start: Position::new(line_no as u32, span.range.start as u32),
+                        end: Position::new(line_no as u32, span.range.end as u32),
+                    },
+                })
+                .collect(),
+            None => vec![],
+        };
+
+        lenses.push(CodeLens {
+            range: TOP,
+            command: Some(show_references(
+                plural(ghosts, "ghost"),
+                &uri,
+                ghost_locations,
+            )),
+            data: None,
+        });
+    }
+
+    lenses.push(CodeLens {
+        range: TOP,
+        command: Some(Command {
+            title: "Build".into(),
+            command: "code_action_build".into(),
+            arguments: Some(vec![uri_arg]),
+        }),
+        data: None,
+    });
+
+    Ok(Some(lenses))
+}
+
+/// resolves the backlinks lens, finding where each backlink is
+pub async fn code_lens_resolve(backend: &Backend, mut lens: CodeLens) -> Result<CodeLens> {
+    let Some(data) = lens
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<BacklinksData>(data).ok())
+    else {
+        return Ok(lens);
+    };
+
+    let locations: Vec<Location> = {
+        let Some(project) = backend.projects.get(&data.root) else {
+            return Ok(lens);
+        };
+
+        let by_id = project.nodes.by_id();
+        project
+            .links
+            .backlinks(&data.id)
+            .filter_map(|l| {
+                let from = by_id.get(&l.from)?;
+                Some(Location {
+                    uri: Uri::from_file_path(&from.path)?,
+                    range: link_range(l)?,
+                })
+            })
+            .collect()
+    };
+
+    lens.command = Some(show_references(
+        backlinks_title(
+            data.count,
+            data.relations.iter().map(|(rel, n)| (rel.as_str(), *n)),
+        ),
+        &data.uri,
+        locations,
+    ));
+
+    Ok(lens)
+}
@@ -1,76 +1,358 @@
 use crate::backend::Backend;
 
+use std::cmp::Reverse;
+use std::time::SystemTime;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use itertools::Itertools;
+use omni::format::{
+    outline::Outline,
+    syntax::{CompletionContext, Syntax},
+};
+use omni::link::{self, FilePart, HeadingPart};
+use omni::{node, tag};
+use serde::{Deserialize, Serialize};
 use tower_lsp_server::jsonrpc::Result;
 use tower_lsp_server::ls_types::*;
 
 use crate::err_json_rpc_ext::ResultToJsonRpcExt;
 use crate::err_log_ext::ErrLogExt;
 
+/// how many lines of a node are shown in the documentation of its completion
+const PREVIEW_LINES: usize = 10;
+
 pub async fn completion(
     backend: &Backend,
     params: CompletionParams,
 ) -> Result<Option<CompletionResponse>> {
-    if let Some(context) = &params.context {
-        match context.trigger_kind {
-            CompletionTriggerKind::TRIGGER_CHARACTER => {}
-            _ => return Ok(None),
-        }
-    } else {
-        return Ok(None);
-    }
-
     let uri = params.text_document_position.text_document.uri;
+    let pos = params.text_document_position.position;
+
     let Some(document) = backend.documents.get(&uri) else {
         return Ok(None);
     };
-    let Some(root) = &document.project_root else {
+    let Some(root) = document.project_root.clone() else {
         return Ok(None);
     };
-    let Some(project) = backend.projects.get(root) else {
+    let Some(syntax) = document.syntax() else {
+        return Ok(None);
+    };
+    let Some(context) = document.completion_context(pos) else {
         return Ok(None);
     };
+    drop(document);
 
-    match document.language_id.as_str() {
-        "typst" => {
-            // reject anything that doesnt start with @
-            if let Some(context) = params.context {
-                if let Some(char) = context.trigger_character
-                    && char != "@"
-                {
-                    return Ok(None);
-                }
-            } else {
-                return Ok(None);
-            }
+    match context {
+        CompletionContext::File { typed } => {
+            file_completion(backend, &root, syntax, replace_range(pos, &typed)).await
+        }
+        CompletionContext::Heading { file_part, typed } => {
+            heading_completion(
+                backend,
+                &root,
+                syntax,
+                &file_part,
+                replace_range(pos, &typed),
+            )
+            .await
+        }
+        CompletionContext::Tag { typed } => {
+            tag_completion(backend, &root, replace_range(pos, &typed)).await
+        }
+    }
+}
 
-            let links = crate::links::get_possible_links(root, &project.config, &project.nodes)
-                .log_err("error while getting links for completion")
-                .show_err_client("cmp err", &backend.client)
-                .await
-                .rpc()?;
+/// the range of what has been typed so far, which will be replaced by the completion
+fn replace_range(pos: Position, typed: &str) -> Range {
+    Range {
+        start: Position {
+            line: pos.line,
+            character: pos.character - typed.encode_utf16().count() as u32,
+        },
+        end: pos,
+    }
+}
 
-            let ghost_links = crate::links::get_ghost_links(root, &project.config, &project.links);
+/// what we keep inside `CompletionItem::data` to resolve the documentation lazily
+#[derive(Debug, Serialize, Deserialize)]
+struct ItemData {
+    root: Utf8PathBuf,
+    id: node::Id,
+}
 
-            let real_completions = links.iter().map(|l| CompletionItem {
-                label: l.omni_path.as_typst_style(),
-                kind: Some(CompletionItemKind::FILE),
+/// completes the file part with all the nodes (and ghosts) of the project.
+/// nodes are ranked by backlinks and then by how recently they were modified,
+/// ghosts come after them ranked by how many nodes link to them.
+async fn file_completion(
+    backend: &Backend,
+    root: &Utf8Path,
+    syntax: Syntax,
+    range: Range,
+) -> Result<Option<CompletionResponse>> {
+    let Some(project) = backend.projects.get(root) else {
+        return Ok(None);
+    };
+
+    let links = crate::links::get_possible_links(root, &project.config, &project.nodes)
+        .log_err("error while getting links for completion")
+        .show_err_client("cmp err", &backend.client)
+        .await
+        .rpc()?;
+
+    let backlinks = project
+        .links
+        .links
+        .iter()
+        .filter_map(|l| match &l.to {
+            link::To::Id(id) => Some((id, &l.from)),
+            link::To::Ghost(_) => None,
+        })
+        .unique()
+        .counts_by(|(id, _)| id);
+
+    let modified = |path: &Utf8Path| {
+        std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+    };
 
+    let by_id = project.nodes.by_id();
+    let real_completions = links
+        .iter()
+        .filter_map(|l| Some((l, *by_id.get(&l.id)?)))
+        .map(|(l, node)| {
+            let rank = (
+                Reverse(backlinks.get(&node.id).copied().unwrap_or(0)),
+                Reverse(modified(&node.path)),
+            );
+            (rank, l, node)
+        })
+        .sorted_by(|a, b| a.0.cmp(&b.0))
+        .enumerate()
+        .map(|(i, (_, l, node))| {
+            let label = syntax.format_file_part(&l.omni_path);
+            CompletionItem {
+                label: label.clone(),
+                kind: Some(match node.kind {
+                    node::NodeKind::File => CompletionItemKind::FILE,
+                    node::NodeKind::Directory => CompletionItemKind::FOLDER,
+                    node::NodeKind::Heading | node::NodeKind::Label => {
+                        CompletionItemKind::REFERENCE
+                    }
+                }),
+                detail: Some(node.title.clone()),
+                // so that typing the title or any other name finds it too
+                filter_text: Some(
+                    [label.clone(), node.title.clone()]
+                        .into_iter()
+                        .chain(node.names.iter().map(|n| n.to_string()))
+                        .join(" "),
+                ),
+                sort_text: Some(format!("0{i:06}")),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: label,
+                })),
+                data: serde_json::to_value(ItemData {
+                    root: root.to_path_buf(),
+                    id: node.id.clone(),
+                })
+                .ok(),
                 ..Default::default()
-            });
+            }
+        })
+        .collect_vec();
 
-            let ghost_completions = ghost_links.map(|(p,)| CompletionItem {
-                label: p.as_typst_style(),
+    let ghost_completions = crate::links::get_ghost_links(root, &project.config, &project.links)
+        .sorted_by_key(|(_, count)| Reverse(*count))
+        .enumerate()
+        .map(|(i, (omni_path, count))| {
+            let label = syntax.format_file_part(&omni_path);
+            CompletionItem {
+                label: label.clone(),
                 kind: Some(CompletionItemKind::KEYWORD),
-
+                detail: Some(match count {
+                    1 => "ghost, referenced by 1 note".to_string(),
+                    n => format!("ghost, referenced by {n} notes"),
+                }),
+                sort_text: Some(format!("1{i:06}")),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: label,
+                })),
                 ..Default::default()
-            });
+            }
+        });
 
-            let completions: Vec<CompletionItem> =
-                real_completions.chain(ghost_completions).collect();
+    let completions: Vec<CompletionItem> = real_completions
+        .into_iter()
+        .chain(ghost_completions)
+        .collect();
+
+    let response = CompletionResponse::Array(completions);
+    Ok(Some(response))
+}
 
-            let response = CompletionResponse::Array(completions);
-            Ok(Some(response))
+/// fills in the documentation of a node completion: its tags and a short preview
+pub async fn completion_resolve(
+    backend: &Backend,
+    mut item: CompletionItem,
+) -> Result<CompletionItem> {
+    let Some(data) = item
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<ItemData>(data).ok())
+    else {
+        return Ok(item);
+    };
+
+    let node = {
+        let Some(project) = backend.projects.get(&data.root) else {
+            return Ok(item);
+        };
+        let Ok(node) = project.node_by_id(&data.id) else {
+            return Ok(item);
+        };
+        node
+    };
+
+    let mut documentation = String::new();
+    if !node.tags.is_empty() {
+        documentation.push_str(&format!(
+            "tags: {}\n\n",
+            node.tags.iter().map(|t| format!("`#{t}`")).join(" ")
+        ));
+    }
+
+    if let Ok(content) = backend
+        .read_file(&node.path)
+        .await
+        .log_err("unable to read file for completion")
+    {
+        let mut preview = content.lines().take(PREVIEW_LINES).join("\n");
+        if content.lines().nth(PREVIEW_LINES).is_some() {
+            preview.push_str("\n...");
+        }
+        documentation.push_str(&format!(
+            "```{}\n{}\n```",
+            node.path.extension().unwrap_or_default(),
+            preview
+        ));
+    }
+
+    item.documentation = Some(Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: documentation,
+    }));
+
+    Ok(item)
+}
+
+/// completes the heading part (eg. after the `:` in `@omni.matrix:`)
+/// with the headings and labels of the target
+async fn heading_completion(
+    backend: &Backend,
+    root: &Utf8Path,
+    syntax: Syntax,
+    file_part: &FilePart,
+    range: Range,
+) -> Result<Option<CompletionResponse>> {
+    let target_path = {
+        let Some(project) = backend.projects.get(root) else {
+            return Ok(None);
+        };
+
+        match project.find_from_filepart(root, file_part) {
+            Ok(node) => node.path,
+            Err(_) => return Ok(None),
+        }
+    };
+
+    let Some(target_syntax) = Syntax::from_path(&target_path) else {
+        return Ok(None);
+    };
+
+    let content = backend
+        .read_file(&target_path)
+        .await
+        .log_err("unable to read file for heading completion")
+        .rpc()?;
+
+    let outline = Outline::parse(&content, target_syntax);
+
+    let item = |part: HeadingPart, kind: CompletionItemKind, detail: Option<String>| {
+        let part = syntax.format_heading_part(&part);
+        CompletionItem {
+            label: part.clone(),
+            kind: Some(kind),
+            detail,
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: part,
+            })),
+            ..Default::default()
+        }
+    };
+
+    let mut completions = vec![];
+    for heading in &outline.headings {
+        if let Some(label) = &heading.label {
+            completions.push(item(
+                HeadingPart::Heading(label.clone()),
+                CompletionItemKind::REFERENCE,
+                Some(heading.title.clone()),
+            ));
         }
-        _ => Ok(None),
+
+        completions.push(item(
+            heading.heading_part(),
+            CompletionItemKind::MODULE,
+            Some(heading.title.clone()),
+        ));
+    }
+
+    for label in &outline.labels {
+        completions.push(item(
+            HeadingPart::Heading(label.name.clone()),
+            CompletionItemKind::REFERENCE,
+            None,
+        ));
     }
+
+    Ok(Some(CompletionResponse::Array(completions)))
+}
+
+/// completes a tag with the ones already used in the project,
+/// ranked by how many nodes have them.
+async fn tag_completion(
+    backend: &Backend,
+    root: &Utf8Path,
+    range: Range,
+) -> Result<Option<CompletionResponse>> {
+    let Some(project) = backend.projects.get(root) else {
+        return Ok(None);
+    };
+
+    let items = tag::Db::from_nodes(&project.nodes)
+        .tags
+        .into_iter()
+        .sorted_by_key(|t| Reverse(t.nodes.len()))
+        .enumerate()
+        .map(|(i, t)| CompletionItem {
+            label: t.name.clone(),
+            kind: Some(CompletionItemKind::ENUM_MEMBER),
+            detail: Some(match t.nodes.len() {
+                1 => "1 note".to_string(),
+                n => format!("{n} notes"),
+            }),
+            sort_text: Some(format!("{i:06}")),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: t.name,
+            })),
+            ..Default::default()
+        })
+        .collect();
+
+    Ok(Some(CompletionResponse::Array(items)))
 }
@@ -0,0 +1,58 @@
+use omni::node;
+use tower_lsp_server::ls_types::*;
+
+use crate::backend::Backend;
+
+impl Backend {
+    /// checks every omni link in a document and publishes diagnostics for the ones that
+    /// are ambiguous, invalid or ghosts.
+    pub async fn publish_diagnostics(&self, uri: &Uri) {
+        let (diagnostics, version) = {
+            let Some(document) = self.documents.get(uri) else {
+                return;
+            };
+            let (Some(root), Some(syntax)) = (&document.project_root, document.syntax()) else {
+                return;
+            };
+            let Some(project) = self.projects.get(root) else {
+                return;
+            };
+
+            let mut diagnostics = vec![];
+            // comments and raw text can span lines, so the whole content is needed
+            for (location, span) in syntax.find_links_in(&document.content.to_string()) {
+                let line_no = location.line as u32;
+                let range = Range {
+                    start: Position::new(line_no, span.range.start as u32),
+                    end: Position::new(line_no, span.range.end as u32),
+                };
+
+                let (severity, message) = match project.find_from_filepart(root, &span.file_part) {
+                    Ok(_) => continue,
+                    Err(node::Error::NameNotFound(_)) => {
+                        (DiagnosticSeverity::HINT, "ghost link".to_string())
+                    }
+                    Err(err @ node::Error::DuplicateName(_)) => (
+                        DiagnosticSeverity::ERROR,
+                        format!("{}, try specifying a path for your link", err),
+                    ),
+                    Err(err) => (DiagnosticSeverity::WARNING, err.to_string()),
+                };
+
+                diagnostics.push(Diagnostic {
+                    range,
+                    severity: Some(severity),
+                    source: Some("omni".into()),
+                    message,
+                    ..Default::default()
+                });
+            }
+
+            (diagnostics, document.version)
+        };
+
+        self.client
+            .publish_diagnostics(uri.clone(), diagnostics, Some(version))
+            .await;
+    }
+}
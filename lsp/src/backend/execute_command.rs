@@ -1,12 +1,17 @@
+use std::io::Write;
 use std::str::FromStr;
 
-use camino::Utf8PathBuf;
-use omni::{link, node};
+use camino::{Utf8Path, Utf8PathBuf};
+use omni::build::full::Phase;
+use omni::format::{outline::Outline, syntax::Syntax};
+use omni::{config::Config, link, node, omni_path::OmniPath};
+use tera::Tera;
 use thiserror::Error;
 use tower_lsp_server::jsonrpc::Result;
 use tower_lsp_server::ls_types::*;
 
 use crate::backend::Backend;
+use crate::backend::build::{build_all, build_and_save};
 use crate::err_json_rpc_ext::ResultToJsonRpcExt;
 use crate::err_log_ext::ErrLogExt;
 
@@ -18,6 +23,12 @@ pub async fn execute_command(
         code_action_track(backend, params.arguments).await
     } else if params.command == "code_action_build" {
         code_action_build(backend, params.arguments).await
+    } else if params.command == "code_action_build_all" {
+        code_action_build_all(backend, params.arguments).await
+    } else if params.command == "code_action_materialize" {
+        code_action_materialize(backend, params.arguments).await
+    } else if params.command == "code_action_extract" {
+        code_action_extract(backend, params.arguments).await
     } else {
         Ok(None)
     }
@@ -54,73 +65,111 @@ async fn code_action_build(
         return Ok(None);
     };
 
-    let Some(project) = backend.projects.get(&root) else {
-        return Ok(None);
+    // the guard must not be held across awaits, the build can wait for the lock
+    let (config, file) = {
+        let Some(project) = backend.projects.get(&root) else {
+            return Ok(None);
+        };
+        (
+            project.config.clone(),
+            project.tracked_file(&file_path).cloned(),
+        )
     };
 
-    let path_canonical = file_path.canonicalize_utf8().rpc()?;
-    tracing::debug!(
-        "AXIOM im looking for {file_path}, canonicalized as {path_canonical}, which originally was {}",
-        uri.as_str()
-    );
-
-    let file = project
-        .user_nodes
-        .files
-        .iter()
-        .filter_map(|f| match f.path.canonicalize_utf8() {
-            Ok(p) => Some((f, p)),
-            Err(err) => {
-                tracing::warn!(
-                    "invalid path found in nodes.toml for id {}. error: {}",
-                    f.id,
-                    err,
-                );
-                None
-            }
-        })
-        .find(|file| file.1 == path_canonical)
-        .map(|(f, _)| f)
-        .ok_or(node::Error::UntrackedNode(file_path))
+    let file = file
         .show_err_client("build err", &backend.client)
         .await
         .rpc()?;
 
-    // we have to get new nodes and links because we cannot mutate project.nodes and links
-    let mut nodes: node::Db = {
-        let db_file = tokio::fs::read(root.join("build/nodes.toml"))
-            .await
-            .unwrap_or_else(|_| {
-                let _ = std::fs::File::create(root.join("build/nodes.toml"));
-                vec![]
-            });
-        toml::from_slice(&db_file).rpc()?
-    };
-    let mut links: link::Db = {
-        let db_file = tokio::fs::read(root.join("build/links.toml"))
-            .await
-            .unwrap_or_else(|_| {
-                let _ = std::fs::File::create(root.join("build/links.toml"));
-                vec![]
-            });
-        toml::from_slice(&db_file).rpc()?
-    };
-
-    omni::build::partial::partial(&root, &project.config, &mut nodes, &mut links, file, true)
+    build_and_save(&root, &config, &file)
+        .await
         .show_err_client("build err", &backend.client)
         .await
         .rpc()?;
 
-    // SAVEPOINT(nodes, links, root)
-    let new_nodes_toml = toml::to_string(&nodes).rpc()?;
-    std::fs::write(root.join("build/nodes.toml"), new_nodes_toml).rpc()?;
+    progress.finish_with_message("Done").await;
 
-    let new_links_toml = toml::to_string(&links).rpc()?;
-    std::fs::write(root.join("build/links.toml"), new_links_toml).rpc()?;
+    Ok(None)
+}
 
-    std::fs::write(root.join("build/root"), root.as_str()).rpc()?;
+async fn code_action_build_all(
+    backend: &Backend,
+    args: Vec<serde_json::Value>,
+) -> Result<Option<LSPAny>> {
+    let file_uri = args
+        .first()
+        .ok_or(CodeActionBuildError::InvalidArgs)
+        .rpc()?;
+    let Some(file_uri) = file_uri.as_str() else {
+        return Err(CodeActionBuildError::InvalidArgs).rpc();
+    };
 
-    progress.finish_with_message("Done").await;
+    let uri = Uri::from_str(file_uri)
+        .log_err("cannot make uri from args")
+        .rpc()?;
+
+    let Some(root) = Backend::find_root_from_uri(&uri, true) else {
+        return Ok(None);
+    };
+
+    let Some(config) = backend.projects.get(&root).map(|p| p.config.clone()) else {
+        return Ok(None);
+    };
+
+    let token = NumberOrString::String("build-all".to_string());
+    let progress = backend.client.progress(token, "Building all").begin().await;
+
+    // the build runs on another thread, so its phases are reported from here
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let build = build_all(&root, &config, move |phase| {
+        let _ = tx.send(phase);
+    });
+    let report = async {
+        while let Some(phase) = rx.recv().await {
+            let message = match phase {
+                Phase::Extract { files, of } => format!("querying {files} of {of} files"),
+                Phase::Resolve => "resolving links".to_string(),
+                Phase::Compile { files, of } => format!("compiling {files} of {of} files"),
+                Phase::Pages => "writing pages".to_string(),
+            };
+            progress.report(message).await;
+        }
+    };
+    let (result, ()) = tokio::join!(build, report);
+
+    match result
+        .log_err("build all failed")
+        .show_err_client("build err", &backend.client)
+        .await
+    {
+        Ok(report) if report.failed.is_empty() => {
+            progress
+                .finish_with_message(format!("Built {} of {} files", report.built, report.files))
+                .await
+        }
+        Ok(report) => {
+            for failure in &report.failed {
+                tracing::error!(
+                    "cannot {} {}: {}\n{}",
+                    failure.stage,
+                    failure.path,
+                    failure.error,
+                    failure.stderr.as_deref().unwrap_or_default()
+                );
+            }
+            let message = format!(
+                "{} of {} files failed to build, see the logs",
+                report.failed.len(),
+                report.files
+            );
+            backend
+                .client
+                .show_message(MessageType::ERROR, &message)
+                .await;
+            progress.finish_with_message(message).await
+        }
+        Err(_) => progress.finish_with_message("Failed").await,
+    }
 
     Ok(None)
 }
@@ -131,7 +180,10 @@ enum CodeActionTrackError {
     InvalidArgs,
 }
 
-async fn code_action_track(_: &Backend, args: Vec<serde_json::Value>) -> Result<Option<LSPAny>> {
+async fn code_action_track(
+    backend: &Backend,
+    args: Vec<serde_json::Value>,
+) -> Result<Option<LSPAny>> {
     let file_uri = args
         .first()
         .ok_or(CodeActionTrackError::InvalidArgs)
@@ -150,7 +202,380 @@ async fn code_action_track(_: &Backend, args: Vec<serde_json::Value>) -> Result<
         return Ok(None);
     };
 
-    omni::track::track(&root, &file_path).rpc()?;
+    let Some(project) = backend.projects.get(&root) else {
+        return Ok(None);
+    };
+
+    omni::track::track(&root, &project.config, &file_path).rpc()?;
+
+    Ok(None)
+}
+
+#[derive(Error, Debug)]
+enum CodeActionMaterializeError {
+    #[error("invalid args")]
+    InvalidArgs,
+
+    #[error("a file at {0} already exists")]
+    AlreadyExists(Utf8PathBuf),
+
+    #[error(transparent)]
+    OmniPathError(#[from] omni::omni_path::Error),
+
+    #[error(transparent)]
+    TemplateFetchError(#[from] omni::get_template::Error),
+
+    #[error(transparent)]
+    TemplateError(#[from] tera::Error),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+/// creates the file a ghost points to, applying a template
+fn materialize(
+    root: &Utf8Path,
+    config: &Config,
+    file_part: link::FilePart,
+    template_name: &str,
+) -> std::result::Result<Utf8PathBuf, CodeActionMaterializeError> {
+    let relative: Utf8PathBuf = OmniPath::from(file_part).unalias(config)?.try_into()?;
+    let mut target = root.join(relative);
+
+    let (template, extension) = omni::get_template::get_template(root, template_name)?;
+    target.set_extension(extension);
+
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut context = tera::Context::new();
+    let title = target.file_stem().unwrap_or_default();
+    context.insert("title", title);
+    context.insert("name", title);
+    let content = Tera::one_off(&template, &context, false)?;
+
+    match std::fs::File::create_new(&target) {
+        Ok(mut file) => file.write_all(content.as_bytes())?,
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(CodeActionMaterializeError::AlreadyExists(target));
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(target)
+}
+
+/// args: uri of the document with the ghost link, template name, ghost file part
+async fn code_action_materialize(
+    backend: &Backend,
+    args: Vec<serde_json::Value>,
+) -> Result<Option<LSPAny>> {
+    let [file_uri, template_name, file_part] = args.as_slice() else {
+        return Err(CodeActionMaterializeError::InvalidArgs).rpc();
+    };
+    let (Some(file_uri), Some(template_name)) = (file_uri.as_str(), template_name.as_str()) else {
+        return Err(CodeActionMaterializeError::InvalidArgs).rpc();
+    };
+    let file_part: link::FilePart = serde_json::from_value(file_part.clone())
+        .map_err(|_| CodeActionMaterializeError::InvalidArgs)
+        .rpc()?;
+
+    let uri = Uri::from_str(file_uri)
+        .log_err("cannot make uri from args")
+        .rpc()?;
+
+    let Some(root) = Backend::find_root_from_uri(&uri, true) else {
+        return Ok(None);
+    };
+
+    // the guard must not be held across awaits
+    let Some(config) = backend.projects.get(&root).map(|p| p.config.clone()) else {
+        return Ok(None);
+    };
+
+    let target = materialize(&root, &config, file_part, template_name)
+        .show_err_client("cannot create node", &backend.client)
+        .await
+        .rpc()?;
+
+    let file = omni::track::track(&root, &config, &target)
+        .show_err_client("cannot track node", &backend.client)
+        .await
+        .rpc()?;
+
+    // resolves every document pointing at the ghost
+    build_and_save(&root, &config, &file)
+        .await
+        .show_err_client("build err", &backend.client)
+        .await
+        .rpc()?;
+
+    if let Some(target_uri) = Uri::from_file_path(&target) {
+        let _ = backend
+            .client
+            .show_document(ShowDocumentParams {
+                uri: target_uri,
+                external: None,
+                take_focus: Some(true),
+                selection: None,
+            })
+            .await
+            .log_err("cannot show materialized node");
+    }
+
+    Ok(None)
+}
+
+#[derive(Error, Debug)]
+enum CodeActionExtractError {
+    #[error("invalid args")]
+    InvalidArgs,
+
+    #[error("the document to extract from is not open")]
+    DocumentNotOpen,
+
+    #[error("a file at {0} already exists")]
+    AlreadyExists(Utf8PathBuf),
+
+    #[error("cannot find a link for the new node")]
+    NoLink,
+
+    #[error("the edit was not applied: {0}")]
+    EditNotApplied(String),
+}
+
+/// the title of an extracted selection is its first heading,
+/// or its first non empty line if it has no headings
+fn extracted_title(selection: &str, syntax: Syntax) -> String {
+    if let Some(heading) = Outline::parse(selection, syntax).headings.first() {
+        return heading.title.clone();
+    }
+
+    selection
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("untitled")
+        .to_string()
+}
+
+/// args: uri of the document, template name, selected range and optionally the title
+/// (which is otherwise derived from the selection)
+async fn code_action_extract(
+    backend: &Backend,
+    args: Vec<serde_json::Value>,
+) -> Result<Option<LSPAny>> {
+    let (file_uri, template_name, range, title) = match args.as_slice() {
+        [file_uri, template_name, range] => (file_uri, template_name, range, None),
+        [file_uri, template_name, range, title] => (file_uri, template_name, range, title.as_str()),
+        _ => return Err(CodeActionExtractError::InvalidArgs).rpc(),
+    };
+    let (Some(file_uri), Some(template_name)) = (file_uri.as_str(), template_name.as_str()) else {
+        return Err(CodeActionExtractError::InvalidArgs).rpc();
+    };
+    let range: Range = serde_json::from_value(range.clone())
+        .map_err(|_| CodeActionExtractError::InvalidArgs)
+        .rpc()?;
+
+    let uri = Uri::from_str(file_uri)
+        .log_err("cannot make uri from args")
+        .rpc()?;
+
+    let Some(root) = Backend::find_root_from_uri(&uri, true) else {
+        return Ok(None);
+    };
+
+    let (selection, syntax, version, directory) = {
+        let Some(document) = backend.documents.get(&uri) else {
+            return Err(CodeActionExtractError::DocumentNotOpen).rpc();
+        };
+        let Some(syntax) = document.syntax() else {
+            return Ok(None);
+        };
+
+        let (Some(start), Some(end)) = (
+            document.char_index(range.start),
+            document.char_index(range.end),
+        ) else {
+            return Err(CodeActionExtractError::InvalidArgs).rpc();
+        };
+        let Some(selection) = document.content.get_slice(start..end) else {
+            return Err(CodeActionExtractError::InvalidArgs).rpc();
+        };
+
+        let directory = document
+            .path
+            .canonicalize_utf8()
+            .rpc()?
+            .parent()
+            .expect("a file always has a parent")
+            .to_path_buf();
+
+        (selection.to_string(), syntax, document.version, directory)
+    };
+
+    let title = title
+        .map(|t| t.to_string())
+        .unwrap_or_else(|| extracted_title(&selection, syntax));
+    let name = slug::slugify(&title);
+
+    let (template, extension) = omni::get_template::get_template(&root, template_name)
+        .show_err_client("cannot extract", &backend.client)
+        .await
+        .rpc()?;
+
+    let target = directory.join(&name).with_extension(extension);
+    if target.exists() {
+        return Err(CodeActionExtractError::AlreadyExists(target))
+            .show_err_client("cannot extract", &backend.client)
+            .await
+            .rpc();
+    }
+
+    // the template puts the title inside a string of its own syntax
+    let escaped = Syntax::from_path(&target)
+        .unwrap_or(syntax)
+        .escape_string(&title);
+    let mut context = tera::Context::new();
+    context.insert("title", &escaped);
+    context.insert("name", &name);
+    let mut content = Tera::one_off(&template, &context, false)
+        .show_err_client("cannot extract", &backend.client)
+        .await
+        .rpc()?;
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(&selection);
+
+    // pretend the new node is already there to find the shortest link to it
+    let link = {
+        let Some(project) = backend.projects.get(&root) else {
+            return Ok(None);
+        };
+
+        let placeholder = node::Id("extracted".into());
+        let mut nodes = project.nodes.clone();
+        nodes.nodes.push(node::Node {
+            id: placeholder.clone(),
+            path: target.clone(),
+            kind: node::NodeKind::File,
+            title: title.clone(),
+            names: vec![name.as_str().into()],
+            display: Default::default(),
+            tags: vec![],
+            private: false,
+            parent: None,
+            fields: Default::default(),
+        });
+
+        let entry = crate::links::get_possible_links(&root, &project.config, &nodes)
+            .rpc()?
+            .into_iter()
+            .find(|l| l.id == placeholder)
+            .ok_or(CodeActionExtractError::NoLink)
+            .rpc()?;
+
+        syntax.format_link(&entry.omni_path, None)
+    };
+
+    let target_uri = Uri::from_file_path(&target)
+        .ok_or(CodeActionExtractError::InvalidArgs)
+        .rpc()?;
+
+    let edit = WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(vec![
+            DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                uri: target_uri.clone(),
+                options: Some(CreateFileOptions {
+                    overwrite: Some(false),
+                    ignore_if_exists: Some(false),
+                }),
+                annotation_id: None,
+            })),
+            DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: target_uri,
+                    version: None,
+                },
+                edits: vec![OneOf::Left(TextEdit {
+                    range: Range::default(),
+                    new_text: content.clone(),
+                })],
+            }),
+            DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri,
+                    version: Some(version),
+                },
+                edits: vec![OneOf::Left(TextEdit {
+                    range,
+                    new_text: link,
+                })],
+            }),
+        ])),
+        ..Default::default()
+    };
+
+    let response = backend
+        .client
+        .apply_edit(edit)
+        .await
+        .log_err("cannot apply extract edit")?;
+    if !response.applied {
+        return Err(CodeActionExtractError::EditNotApplied(
+            response.failure_reason.unwrap_or_default(),
+        ))
+        .show_err_client("cannot extract", &backend.client)
+        .await
+        .rpc();
+    }
+
+    // the client might keep the content of the new file in an unsaved buffer,
+    // but it has to be on disk to be tracked and built
+    let on_disk = tokio::fs::read_to_string(&target).await.unwrap_or_default();
+    if on_disk.is_empty() {
+        tokio::fs::write(&target, &content)
+            .await
+            .show_err_client("cannot extract", &backend.client)
+            .await
+            .rpc()?;
+    }
+
+    // the guard must not be held across awaits
+    let Some(config) = backend.projects.get(&root).map(|p| p.config.clone()) else {
+        return Ok(None);
+    };
+
+    let file = omni::track::track(&root, &config, &target)
+        .show_err_client("cannot track node", &backend.client)
+        .await
+        .rpc()?;
+
+    build_and_save(&root, &config, &file)
+        .await
+        .show_err_client("build err", &backend.client)
+        .await
+        .rpc()?;
 
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extracted_title() {
+        assert_eq!(
+            extracted_title("some text\n== Dot product\nlorem", Syntax::Typst),
+            "Dot product"
+        );
+        assert_eq!(
+            extracted_title("\n  The dot product is\n", Syntax::Markdown),
+            "The dot product is"
+        );
+        assert_eq!(extracted_title("", Syntax::Org), "untitled");
+    }
+}
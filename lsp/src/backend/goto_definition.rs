@@ -1,10 +1,10 @@
+use omni::format::{outline::Outline, syntax::Syntax};
+use omni::link::HeadingPart;
 use omni::node;
 use tower_lsp_server::jsonrpc::Result;
 use tower_lsp_server::ls_types::*;
 
 use crate::backend::Backend;
-use crate::err_json_rpc_ext::ResultToJsonRpcExt;
-use crate::err_log_ext::ErrLogExt;
 
 pub async fn goto_definition(
     backend: &Backend,
@@ -28,15 +28,11 @@ pub async fn goto_definition(
     };
 
     // TODO: switch to resolve_link without the from part
-    let maybe_node =
-        match project
-            .nodes
-            .find_from_filepart(&root, &unresolved.file_part, &project.config)
-        {
-            Ok(node) => Some(node),
-            Err(node::Error::NameNotFound(_)) => None,
-            Err(_) => return Ok(None),
-        };
+    let maybe_node = match project.find_from_filepart(root, &unresolved.file_part) {
+        Ok(node) => Some(node),
+        Err(node::Error::NameNotFound(_)) => None,
+        Err(_) => return Ok(None),
+    };
 
     match maybe_node {
         Some(node) => {
@@ -51,17 +47,30 @@ pub async fn goto_definition(
                 }
             };
 
+            // sub-document nodes are at their label
+            let heading_part = unresolved.heading_part.or_else(|| {
+                node.parent
+                    .as_ref()
+                    .map(|p| HeadingPart::Heading(p.label.clone()))
+            });
+            let path = node.path.clone();
+            drop(project);
+            drop(document);
+
+            let mut line = 0;
+            if let Some(heading_part) = heading_part
+                && let Some(syntax) = Syntax::from_path(&path)
+                && let Ok(content) = backend.read_file(&path).await
+                && let Some(range) = Outline::parse(&content, syntax).find(&heading_part)
+            {
+                line = range.start as u32;
+            }
+
             Ok(Some(GotoDefinitionResponse::Scalar(Location {
                 uri: target_uri,
                 range: Range {
-                    start: Position {
-                        line: 0,
-                        character: 0,
-                    },
-                    end: Position {
-                        line: 0,
-                        character: 0,
-                    },
+                    start: Position { line, character: 0 },
+                    end: Position { line, character: 0 },
                 },
             })))
         }
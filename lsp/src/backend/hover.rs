@@ -1,72 +1,277 @@
+use camino::Utf8Path;
+use itertools::Itertools;
+use omni::build::directory;
+use omni::format::{outline::Outline, syntax::Syntax};
+use omni::link::{self, HeadingPart};
 use omni::node;
+use omni::omni_path::OmniPath;
 use tower_lsp_server::jsonrpc::Result;
 use tower_lsp_server::ls_types::*;
 
 use crate::backend::Backend;
-use crate::err_json_rpc_ext::ResultToJsonRpcExt;
+use crate::backend::code_lens::backlinks_title;
 use crate::err_log_ext::ErrLogExt;
 
+/// previews longer than this get cut
+const PREVIEW_LINES: usize = 30;
+
+/// how many existing names are suggested for a ghost
+const SUGGESTIONS: usize = 3;
+
+enum Hovered {
+    /// a node, its backlinks by relation and, for directories, what's inside
+    Node(Box<node::Node>, String, Option<String>),
+    /// an already rendered ghost hover
+    Ghost(String),
+}
+
 pub async fn hover(backend: &Backend, params: HoverParams) -> Result<Option<Hover>> {
     let uri = params.text_document_position_params.text_document.uri;
-    let Some(document) = backend.documents.get(&uri) else {
-        return Ok(None);
+    let pos = params.text_document_position_params.position;
+
+    let (root, syntax, span, my_path) = {
+        let Some(document) = backend.documents.get(&uri) else {
+            return Ok(None);
+        };
+        let Some(root) = document.project_root.clone() else {
+            return Ok(None);
+        };
+        let Some(syntax) = document.syntax() else {
+            return Ok(None);
+        };
+        let Some(span) = document.link_span_under_cursor(pos) else {
+            return Ok(None);
+        };
+        (root, syntax, span, document.path.clone())
     };
-    let Some(root) = &document.project_root else {
-        return Ok(None);
+
+    tracing::debug!("hovering: {span:?}");
+
+    let hovered = {
+        let Some(project) = backend.projects.get(&root) else {
+            return Ok(None);
+        };
+
+        match project.find_from_filepart(&root, &span.file_part) {
+            Ok(node) => {
+                let backlinks = project
+                    .links
+                    .links
+                    .iter()
+                    .filter(|l| l.to == link::To::Id(node.id.clone()))
+                    .map(|l| &l.from)
+                    .unique()
+                    .count();
+                let listing = (node.kind == node::NodeKind::Directory).then(|| {
+                    directory::children(&project.nodes, &node)
+                        .iter()
+                        .map(|child| match child.kind {
+                            node::NodeKind::Directory => format!("- **{}/**", child.title),
+                            _ => format!("- {}", child.title),
+                        })
+                        .join("\n")
+                });
+                let backlinks = backlinks_title(backlinks, project.links.relations_to(&node.id));
+                Hovered::Node(Box::new(node.clone()), backlinks, listing)
+            }
+            Err(node::Error::NameNotFound(_)) => {
+                let me = my_path
+                    .canonicalize_utf8()
+                    .ok()
+                    .and_then(|p| project.find_abs(&p).ok())
+                    .map(|n| n.id.clone());
+                let ghost = OmniPath::from(span.file_part.clone());
+
+                let referenced_by = project
+                    .links
+                    .links
+                    .iter()
+                    .filter(|l| match &l.to {
+                        link::To::Ghost(file_part) => OmniPath::from(file_part.clone()) == ghost,
+                        link::To::Id(_) => false,
+                    })
+                    .map(|l| &l.from)
+                    .filter(|from| Some(*from) != me.as_ref())
+                    .unique()
+                    .count();
+
+                let suggestions = closest_names(&root, &project, syntax, &ghost.name);
+
+                Hovered::Ghost(ghost_hover(syntax, &ghost, referenced_by, &suggestions))
+            }
+            Err(_) => return Ok(None),
+        }
     };
-    let Some(project) = backend.projects.get(root) else {
-        return Ok(None);
+
+    let value = match hovered {
+        Hovered::Node(node, backlinks, listing) => {
+            node_hover(
+                backend,
+                &root,
+                &node,
+                backlinks,
+                listing,
+                span.heading_part.as_ref(),
+            )
+            .await
+        }
+        Hovered::Ghost(value) => value,
     };
 
-    let unresolved = match document.link_under_cursor(params.text_document_position_params.position)
-    {
-        Some(v) => v,
-        None => return Ok(None),
+    Ok(Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(Range {
+            start: Position::new(pos.line, span.range.start as u32),
+            end: Position::new(pos.line, span.range.end as u32),
+        }),
+    }))
+}
+
+/// the existing links whose name is the closest to `name`
+fn closest_names(
+    root: &Utf8Path,
+    project: &crate::project::Project,
+    syntax: Syntax,
+    name: &str,
+) -> Vec<String> {
+    let Ok(links) = crate::links::get_possible_links(root, &project.config, &project.nodes)
+        .log_err("error while getting links for hover")
+    else {
+        return vec![];
     };
 
-    tracing::debug!("unresolved: {unresolved:?}");
+    // levenshtein counts chars, so the lengths are in chars too
+    let name_len = name.chars().count();
+    links
+        .iter()
+        .map(|l| (strsim::levenshtein(name, &l.omni_path.name), l))
+        .filter(|(distance, l)| *distance <= l.omni_path.name.chars().count().max(name_len) / 2)
+        .sorted_by_key(|(distance, _)| *distance)
+        .take(SUGGESTIONS)
+        .map(|(_, l)| syntax.format_file_part(&l.omni_path))
+        .collect()
+}
 
-    // TODO: switch to resolve_link without the from part
-    let maybe_node =
-        match project
-            .nodes
-            .find_from_filepart(&root, &unresolved.file_part, &project.config)
-        {
-            Ok(node) => Some(node),
-            Err(node::Error::NameNotFound(_)) => None,
-            Err(_) => return Ok(None),
-        };
+fn ghost_hover(
+    syntax: Syntax,
+    ghost: &OmniPath,
+    referenced_by: usize,
+    suggestions: &[String],
+) -> String {
+    let mut value = format!("**Ghost** `{}`\n\n", syntax.format_file_part(ghost));
+
+    value.push_str(&match referenced_by {
+        0 => "not referenced by any other note".to_string(),
+        1 => "referenced by 1 other note".to_string(),
+        n => format!("referenced by {n} other notes"),
+    });
+
+    if !suggestions.is_empty() {
+        let suggestions = suggestions.iter().map(|s| format!("`{s}`")).join(", ");
+        value.push_str(&format!("\n\ndid you mean {suggestions}?"));
+    }
+
+    value
+}
+
+async fn node_hover(
+    backend: &Backend,
+    root: &Utf8Path,
+    node: &node::Node,
+    backlinks: String,
+    listing: Option<String>,
+    heading_part: Option<&HeadingPart>,
+) -> String {
+    let mut value = format!(
+        "**{}** at `{}`\n\n",
+        node.title,
+        node.path.strip_prefix(root).unwrap_or(&node.path)
+    );
+
+    let mut metadata = vec![];
+    if !node.names.is_empty() {
+        metadata.push(format!(
+            "names: {}",
+            node.names
+                .iter()
+                .map(|n| match node.display.get(n) {
+                    Some(display) => format!("`{n}` ({display})"),
+                    None => format!("`{n}`"),
+                })
+                .join(", ")
+        ));
+    }
+    if !node.tags.is_empty() {
+        metadata.push(format!(
+            "tags: {}",
+            node.tags.iter().map(|t| format!("`#{t}`")).join(" ")
+        ));
+    }
+    for (field, value) in &node.fields {
+        metadata.push(format!("{field}: {value}"));
+    }
+    if node.private {
+        metadata.push("private".into());
+    }
+    metadata.push(backlinks);
+    value.push_str(&metadata.join(" · "));
 
-    // tracing::debug!("resolved: {resolved:?}");
-
-    match maybe_node {
-        Some(node) => {
-            let content = tokio::fs::read_to_string(&node.path)
-                .await
-                .log_err_client("unable to read file", &backend.client)
-                .await
-                .unwrap_or("**Unable to read file**".to_string());
-
-            Ok(Some(Hover {
-                contents: HoverContents::Markup(MarkupContent {
-                    kind: MarkupKind::Markdown,
-                    value: format!(
-                        "omni node `{}` at **{}**\n\n```{}\n{}\n```",
-                        node.id,
-                        node.path.strip_prefix(root).unwrap_or(&node.path),
-                        node.path.extension().unwrap_or_default(),
-                        content
-                    ),
-                }),
-                range: None, // TODO: when we have heading parts we need to take this into consideration
-            }))
+    if let Some(listing) = listing {
+        value.push_str(&format!("\n\n---\n\n{listing}"));
+    }
+    // there's no file to preview
+    if directory::is_generated(node) {
+        return value;
+    }
+
+    let content = match backend
+        .read_file(&node.path)
+        .await
+        .log_err_client("unable to read file", &backend.client)
+        .await
+    {
+        Ok(content) => content,
+        Err(_) => {
+            value.push_str("\n\n**Unable to read file**");
+            return value;
+        }
+    };
+
+    // sub-document nodes only preview their own section
+    let own_label = node
+        .parent
+        .as_ref()
+        .map(|p| HeadingPart::Heading(p.label.clone()));
+    let heading_part = heading_part.or(own_label.as_ref());
+
+    let lines = content.lines().collect_vec();
+    let mut preview = 0..lines.len();
+    if let Some(heading_part) = heading_part {
+        let found = Syntax::from_path(&node.path)
+            .and_then(|syntax| Outline::parse(&content, syntax).find(heading_part));
+        match found {
+            Some(range) => preview = range,
+            None => value.push_str(&format!(
+                "\n\n**heading `{}` not found**",
+                heading_part.as_typst_style()
+            )),
         }
-        None => Ok(Some(Hover {
-            contents: HoverContents::Markup(MarkupContent {
-                kind: MarkupKind::Markdown,
-                value: "Ghost".into(),
-            }),
-            range: None,
-        })),
     }
+
+    let cut = preview.len() > PREVIEW_LINES;
+    let mut shown = lines[preview.start..preview.end.min(preview.start + PREVIEW_LINES)].join("\n");
+    if cut {
+        shown.push_str("\n...");
+    }
+
+    value.push_str(&format!(
+        "\n\n---\n\n```{}\n{}\n```",
+        node.path.extension().unwrap_or_default(),
+        shown
+    ));
+
+    value
 }
@@ -7,7 +7,18 @@ use crate::document;
 
 impl LanguageServer for Backend {
     #[tracing::instrument(skip_all)]
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        // register projects right away, so that they work before opening any file
+        #[allow(deprecated)]
+        let folders = match (params.workspace_folders, params.root_uri) {
+            (Some(folders), _) => folders.into_iter().map(|f| f.uri).collect(),
+            (None, Some(root_uri)) => vec![root_uri],
+            (None, None) => vec![],
+        };
+        for folder in &folders {
+            self.register_workspace_folder(folder).await;
+        }
+
         Ok(InitializeResult {
             capabilities: Self::capabilities(),
             ..Default::default()
@@ -31,6 +42,11 @@ impl LanguageServer for Backend {
         crate::backend::completion::completion(self, params).await
     }
 
+    #[tracing::instrument(skip_all)]
+    async fn completion_resolve(&self, params: CompletionItem) -> Result<CompletionItem> {
+        crate::backend::completion::completion_resolve(self, params).await
+    }
+
     #[tracing::instrument(skip_all)]
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
         crate::backend::hover::hover(self, params).await
@@ -44,16 +60,60 @@ impl LanguageServer for Backend {
         crate::backend::goto_definition::goto_definition(self, params).await
     }
 
+    #[tracing::instrument(skip_all)]
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        crate::backend::call_hierarchy::prepare_call_hierarchy(self, params).await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        crate::backend::call_hierarchy::incoming_calls(self, params).await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        crate::backend::call_hierarchy::outgoing_calls(self, params).await
+    }
+
     #[tracing::instrument(skip_all)]
     async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
         crate::backend::code_action::code_action(self, params).await
     }
 
+    #[tracing::instrument(skip_all)]
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        crate::backend::code_lens::code_lens(self, params).await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn code_lens_resolve(&self, params: CodeLens) -> Result<CodeLens> {
+        crate::backend::code_lens::code_lens_resolve(self, params).await
+    }
+
     #[tracing::instrument(skip_all)]
     async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<LSPAny>> {
         crate::backend::execute_command::execute_command(self, params).await
     }
 
+    #[tracing::instrument(skip_all)]
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        for folder in &params.event.removed {
+            self.unregister_workspace_folder(&folder.uri);
+        }
+        for folder in &params.event.added {
+            self.register_workspace_folder(&folder.uri).await;
+        }
+    }
+
     #[tracing::instrument(skip_all)]
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         tracing::debug!("client did open {}", params.text_document.uri.as_str());
@@ -78,6 +138,8 @@ impl LanguageServer for Backend {
                 content: ropey::Rope::from(params.text_document.text),
             },
         );
+
+        self.publish_diagnostics(&params.text_document.uri).await;
     }
 
     #[tracing::instrument(skip_all)]
@@ -92,11 +154,12 @@ impl LanguageServer for Backend {
                     .and_modify(|doc| {
                         doc.version = params.text_document.version;
 
-                        let start_idx = doc.content.line_to_char(range.start.line as usize)
-                            + range.start.character as usize;
-
-                        let end_idx = doc.content.line_to_char(range.end.line as usize)
-                            + range.end.character as usize;
+                        let (Some(start_idx), Some(end_idx)) =
+                            (doc.char_index(range.start), doc.char_index(range.end))
+                        else {
+                            tracing::warn!("change out of the document range");
+                            return;
+                        };
 
                         doc.content.remove(start_idx..end_idx);
                         doc.content.insert(start_idx, &change.text);
@@ -111,5 +174,14 @@ impl LanguageServer for Backend {
                     });
             }
         }
+
+        self.publish_diagnostics(&params.text_document.uri).await;
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        tracing::debug!("client did save {}", params.text_document.uri.as_str());
+
+        self.build_on_save(&params.text_document.uri).await;
     }
 }
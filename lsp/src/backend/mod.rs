@@ -1,16 +1,21 @@
+mod build;
+mod call_hierarchy;
 mod code_action;
+mod code_lens;
 mod completion;
+mod diagnostics;
 mod execute_command;
 mod find_root;
 mod goto_definition;
 mod hover;
 mod language_server;
 mod register_project;
+mod workspace_folders;
 
 use std::sync::Arc;
 
-use camino::Utf8PathBuf;
-use dashmap::DashMap;
+use camino::{Utf8Path, Utf8PathBuf};
+use dashmap::{DashMap, DashSet};
 use tower_lsp_server::Client;
 use tower_lsp_server::ls_types::*;
 
@@ -21,6 +26,10 @@ pub struct Backend {
     client: Client,
     documents: Arc<DashMap<Uri, document::Document>>,
     projects: Arc<DashMap<Utf8PathBuf, project::Project>>,
+    save_builds: Arc<DashMap<Utf8PathBuf, build::SaveBuilds>>,
+    watchers: Arc<DashMap<Utf8PathBuf, tokio::task::AbortHandle>>,
+    /// canonical paths of the open workspace folders
+    workspace_folders: Arc<DashSet<Utf8PathBuf>>,
 }
 
 impl Backend {
@@ -29,6 +38,9 @@ impl Backend {
             client,
             documents: Arc::new(DashMap::new()),
             projects: Arc::new(DashMap::new()),
+            save_builds: Arc::new(DashMap::new()),
+            watchers: Arc::new(DashMap::new()),
+            workspace_folders: Arc::new(DashSet::new()),
         }
     }
 
@@ -36,19 +48,69 @@ impl Backend {
         ServerCapabilities {
             hover_provider: Some(HoverProviderCapability::Simple(true)),
             completion_provider: Some(CompletionOptions {
-                trigger_characters: Some(vec!["@".into()]),
+                trigger_characters: Some(vec![
+                    "@".into(),
+                    ":".into(),
+                    "#".into(),
+                    "\"".into(),
+                    "/".into(),
+                ]),
+                resolve_provider: Some(true),
                 ..Default::default()
             }),
-            text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                TextDocumentSyncKind::INCREMENTAL,
+            text_document_sync: Some(TextDocumentSyncCapability::Options(
+                TextDocumentSyncOptions {
+                    open_close: Some(true),
+                    change: Some(TextDocumentSyncKind::INCREMENTAL),
+                    save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                    ..Default::default()
+                },
             )),
             definition_provider: Some(OneOf::Left(true)),
+            call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+            code_lens_provider: Some(CodeLensOptions {
+                resolve_provider: Some(true),
+            }),
             code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+            workspace: Some(WorkspaceServerCapabilities {
+                workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                    supported: Some(true),
+                    change_notifications: Some(OneOf::Left(true)),
+                }),
+                file_operations: None,
+            }),
             execute_command_provider: Some(ExecuteCommandOptions {
-                commands: vec!["code_action_track".into(), "code_action_build".into()],
+                commands: vec![
+                    "code_action_track".into(),
+                    "code_action_build".into(),
+                    "code_action_build_all".into(),
+                    "code_action_materialize".into(),
+                    "code_action_extract".into(),
+                ],
                 ..Default::default()
             }),
             ..Default::default()
         }
     }
+
+    /// `window/workDoneProgress/cancel` is not part of [`tower_lsp_server::LanguageServer`],
+    /// so it's registered as a custom method
+    pub async fn work_done_progress_cancel(&self, params: WorkDoneProgressCancelParams) {
+        self.cancel_build(&params.token);
+    }
+
+    /// reads the content of a file,
+    /// preferring the (possibly unsaved) content of an open document if there is one.
+    pub async fn read_file(&self, path: &Utf8Path) -> std::io::Result<String> {
+        let open_content = self
+            .documents
+            .iter()
+            .find(|doc| doc.path == path)
+            .map(|doc| doc.content.to_string());
+
+        match open_content {
+            Some(content) => Ok(content),
+            None => tokio::fs::read_to_string(path).await,
+        }
+    }
 }
@@ -31,7 +31,7 @@ impl Backend {
             let projects_clone = Arc::clone(&self.projects);
             let client_clone = self.client.clone();
 
-            tokio::spawn(async move {
+            let watcher = tokio::spawn(async move {
                 // WARNING: watching files might cause a data race
                 // if we have a mutated project,
                 // and in the meantime a CLI edits the project or something.
@@ -40,8 +40,20 @@ impl Backend {
                     .await
                     .log_err("cannot watch project");
             });
+            self.watchers.insert(root.clone(), watcher.abort_handle());
         }
 
         Ok(())
     }
+
+    /// forgets about a project and stops watching it
+    pub fn unregister_project(&self, root: &Utf8PathBuf) {
+        if let Some((_, watcher)) = self.watchers.remove(root) {
+            watcher.abort();
+        }
+        self.projects.remove(root);
+        self.save_builds.remove(root);
+
+        tracing::info!("unregistered project {root}");
+    }
 }
@@ -0,0 +1,77 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use tower_lsp_server::ls_types::Uri;
+
+use crate::backend::Backend;
+use crate::err_log_ext::ErrLogExt;
+
+fn folder_path(uri: &Uri) -> Option<Utf8PathBuf> {
+    let path = uri.to_file_path()?;
+    let path = Utf8PathBuf::from_path_buf(path.to_path_buf()).ok()?;
+    // the folder might be gone already
+    Some(path.canonicalize_utf8().unwrap_or(path))
+}
+
+/// whether the project at `root` was found from `folder`,
+/// ie. it is inside it, or `folder` is inside the project
+fn covers(folder: &Utf8Path, root: &Utf8Path) -> bool {
+    root.starts_with(folder) || folder.starts_with(root)
+}
+
+impl Backend {
+    /// registers every project found in a workspace folder
+    pub async fn register_workspace_folder(&self, uri: &Uri) {
+        let Some(folder) = folder_path(uri) else {
+            tracing::error!("got workspace folder with invalid uri: {:#?}", uri);
+            return;
+        };
+        self.workspace_folders.insert(folder.clone());
+
+        let Ok(roots) = omni::config::find_project_roots(&folder)
+            .log_err("cannot look for projects in workspace folder")
+        else {
+            return;
+        };
+
+        for root in roots {
+            tracing::info!("registering project {root} from workspace folder {folder}");
+            let _ = self
+                .register_project(&root)
+                .await
+                .log_err("err while registering project");
+        }
+    }
+
+    /// unregisters every project inside (or containing) a workspace folder,
+    /// unless another workspace folder still needs it
+    pub fn unregister_workspace_folder(&self, uri: &Uri) {
+        let Some(folder) = folder_path(uri) else {
+            return;
+        };
+        self.workspace_folders.remove(&folder);
+
+        let roots: Vec<Utf8PathBuf> = self
+            .projects
+            .iter()
+            .map(|p| p.key().clone())
+            .filter(|root| covers(&folder, root))
+            .filter(|root| !self.workspace_folders.iter().any(|f| covers(&f, root)))
+            .collect();
+
+        for root in roots {
+            self.unregister_project(&root);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_covers() {
+        let root = Utf8Path::new("/home/me/notes");
+        assert!(covers(Utf8Path::new("/home/me"), root));
+        assert!(covers(Utf8Path::new("/home/me/notes/linalg"), root));
+        assert!(!covers(Utf8Path::new("/home/me/work"), root));
+    }
+}
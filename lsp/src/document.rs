@@ -1,5 +1,8 @@
 use camino::Utf8PathBuf;
-use omni::link::{self, FilePart, HeadingPart};
+use omni::{
+    format::syntax::{CompletionContext, LinkSpan, Syntax},
+    link,
+};
 use tower_lsp_server::ls_types;
 
 #[derive(Debug)]
@@ -12,123 +15,55 @@ pub struct Document {
     pub content: ropey::Rope,
 }
 
-fn is_typst_ref_char(c: char) -> bool {
-    // https://typst.app/docs/reference/foundations/label#syntax
-    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':' | '.')
-}
-
 impl Document {
+    pub fn syntax(&self) -> Option<Syntax> {
+        Syntax::from_language_id(&self.language_id)
+    }
+
+    /// the char index in the content of `pos`, whose character is in UTF-16 code units
+    pub fn char_index(&self, pos: ls_types::Position) -> Option<usize> {
+        let line = self.content.try_line_to_char(pos.line as usize).ok()?;
+        let line_utf16 = self.content.char_to_utf16_cu(line);
+        self.content
+            .try_utf16_cu_to_char(line_utf16 + pos.character as usize)
+            .ok()
+    }
+
+    /// finds the link span under the cursor (if there is one)
+    /// links can never span more than 1 line.
+    pub fn link_span_under_cursor(&self, pos: ls_types::Position) -> Option<LinkSpan> {
+        let syntax = self.syntax()?;
+        let line = self.content.get_line(pos.line as usize)?.to_string();
+        syntax.link_at(&line, pos.character as usize)
+    }
+
     /// finds a link under the cursor (if there is one)
     /// caveats:
     /// - on typst documents, it will NOT be able to parse #ref(...)
-    ///   it can only parse links in the @ form.
+    ///   it can only parse links in the @ form, and the targets of #omni-link(...).
     /// - on typst documents, the alias will be ignored.
     pub fn link_under_cursor(&self, pos: ls_types::Position) -> Option<link::UnresolvedLink> {
-        if self.language_id == "typst" {
-            // links can never span more than 1 line
-            let line = self.content.line(pos.line as usize);
-
-            // get the start:
-            // go back from pos.char until we end valid chars
-            // is the ending character a '@'?
-            // if so, then YES this is a ref!
-            // else return None
-            let mut start = pos.character as usize;
-            loop {
-                if start == 0 {
-                    break;
-                }
-
-                match line.get_char(start) {
-                    Some(c) if is_typst_ref_char(c) => {
-                        start -= 1;
-                    }
-                    Some('@') => {
-                        break;
-                    }
-                    _ => {
-                        return None;
-                    }
-                }
-            }
-
-            // get the end:
-            // go forth until we end valid chars
-            let mut end = pos.character as usize;
-            loop {
-                match line.get_char(end) {
-                    Some(c) if is_typst_ref_char(c) || c == '@' => {
-                        end += 1;
-                    }
-                    _ => {
-                        break;
-                    }
-                }
-            }
-
-            // slice the line to get the final string
-            let text = line.slice(start..end);
-            tracing::debug!("under cursor we have: {start}..{end} -> {text}");
-
-            // does it start with @omni.link?
-            // if not, return None
-            let preamble = text.get_slice(0..6)?;
-            if preamble != "@omni." {
-                return None;
-            }
-
-            // build the UnresolvedLink
-            let text = text.get_slice(6..)?.to_string();
-
-            // TODO: write a dedicated func for this
-            // WHICH WE ALREADY Have called from_typst_style
-            let mut raw_splits = text.split(":");
-            let raw_file_part = raw_splits.next()?;
-            let raw_heading_part = raw_splits.next().unwrap_or_default();
-
-            let file_splits: Vec<_> = raw_file_part.split('.').collect();
-            let file_part = if file_splits.is_empty() {
-                return None;
-            } else if file_splits.len() == 1 {
-                let title = file_splits[0].to_string();
-                FilePart::Name(title)
-            } else {
-                let mut path = vec![];
-                for component in file_splits.iter().take(file_splits.len() - 1) {
-                    path.push(component.to_string());
-                }
-
-                let last = file_splits.last()?;
-                let title = last.to_string();
-                FilePart::PathAndName(path, title)
-            };
-
-            let heading_splits: Vec<_> = raw_heading_part.split('.').collect();
-            let heading_part = if heading_splits.is_empty() {
-                None
-            } else if heading_splits.len() == 1 {
-                let head = heading_splits[0].to_string();
-                Some(HeadingPart::Heading(head))
-            } else {
-                let mut path = vec![];
-                for component in heading_splits.iter().take(heading_splits.len() - 1) {
-                    path.push(component.to_string());
-                }
-
-                let last = heading_splits.last()?;
-                let head = last.to_string();
-                Some(HeadingPart::PathAndHeading(path, head))
-            };
+        let span = self.link_span_under_cursor(pos)?;
+
+        Some(link::UnresolvedLink {
+            from: self.path.clone(),
+            file_part: span.file_part,
+            heading_part: span.heading_part,
+            alias: span.alias,
+            rel: span.rel,
+        })
+    }
 
-            Some(link::UnresolvedLink {
-                from: self.path.clone(),
-                file_part,
-                heading_part,
-                alias: None, // by design always empty in this case
-            })
-        } else {
-            None
-        }
+    /// figures out what kind of link is being typed right before the cursor,
+    /// eg. `@omni.matrix:oper|` is a heading part of `omni.matrix`.
+    pub fn completion_context(&self, pos: ls_types::Position) -> Option<CompletionContext> {
+        let syntax = self.syntax()?;
+        let start = self.content.try_line_to_char(pos.line as usize).ok()?;
+        let before_cursor = self
+            .content
+            .get_slice(start..self.char_index(pos)?)?
+            .to_string();
+        syntax.completion_context(&before_cursor)
     }
 }
 
@@ -164,6 +99,7 @@ mod tests {
                     "sit".into()
                 )),
                 alias: None,
+                rel: None,
             })
         );
 
@@ -174,4 +110,91 @@ mod tests {
 
         assert_eq!(document.link_under_cursor(pos), None);
     }
+
+    #[test]
+    fn test_under_cursor_markdown() {
+        let document = Document {
+            project_root: Some("/Users/me/vault/".into()),
+            path: "/Users/me/vault/lorem.md".into(),
+            version: 1,
+            language_id: "markdown".into(),
+            content: ropey::Rope::from("# Title\nlorem [[omni:lorem/ipsum#dolor|ALIAS]] ipsum"),
+        };
+
+        let pos = ls_types::Position {
+            line: 1,
+            character: 10,
+        };
+
+        assert_eq!(
+            document.link_under_cursor(pos),
+            Some(link::UnresolvedLink {
+                from: "/Users/me/vault/lorem.md".into(),
+                file_part: link::FilePart::PathAndName(vec!["lorem".into()], "ipsum".into()),
+                heading_part: Some(link::HeadingPart::Heading("dolor".into())),
+                alias: Some("ALIAS".into()),
+                rel: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_completion_context() {
+        let document = Document {
+            project_root: Some("/Users/me/vault/".into()),
+            path: "/Users/me/vault/lorem.typ".into(),
+            version: 1,
+            language_id: "typst".into(),
+            content: ropey::Rope::from("= Title\nsee @omni.linalg.matrix:oper and more"),
+        };
+
+        let pos = ls_types::Position {
+            line: 1,
+            character: 28,
+        };
+        assert_eq!(
+            document.completion_context(pos),
+            Some(CompletionContext::Heading {
+                file_part: link::FilePart::PathAndName(vec!["linalg".into()], "matrix".into()),
+                typed: "oper".into()
+            })
+        );
+
+        let pos = ls_types::Position {
+            line: 1,
+            character: 2,
+        };
+        assert_eq!(document.completion_context(pos), None);
+    }
+
+    #[test]
+    fn test_utf16_positions() {
+        // 𝐀 is 2 UTF-16 code units, but 1 char
+        let document = Document {
+            project_root: Some("/Users/me/vault/".into()),
+            path: "/Users/me/vault/lorem.typ".into(),
+            version: 1,
+            language_id: "typst".into(),
+            content: ropey::Rope::from("= Title\n𝐀 @omni.linalg.matrix:oper"),
+        };
+
+        let pos = ls_types::Position {
+            line: 1,
+            character: 3,
+        };
+        assert_eq!(document.char_index(pos), Some(10));
+        assert!(document.link_under_cursor(pos).is_some());
+
+        let pos = ls_types::Position {
+            line: 1,
+            character: 27,
+        };
+        assert_eq!(
+            document.completion_context(pos),
+            Some(CompletionContext::Heading {
+                file_part: link::FilePart::PathAndName(vec!["linalg".into()], "matrix".into()),
+                typed: "oper".into()
+            })
+        );
+    }
 }
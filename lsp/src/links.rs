@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use camino::{Utf8Path, Utf8PathBuf};
 use itertools::Itertools;
 use omni::{config::Config, link, node, omni_path::OmniPath};
@@ -5,6 +7,7 @@ use thiserror::Error;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct LinkEntry {
+    pub id: node::Id,
     pub omni_path: OmniPath,
     pub true_path: Utf8PathBuf,
     valid: bool,
@@ -23,11 +26,23 @@ pub fn get_possible_links(
 ) -> Result<Vec<LinkEntry>, GetPossibleLinksError> {
     let mut links: Vec<LinkEntry> = vec![];
 
+    // directories lose to files of the same name when resolving links
+    let file_names: HashSet<&str> = nodes
+        .nodes
+        .iter()
+        .filter(|n| n.kind != node::NodeKind::Directory)
+        .flat_map(|n| n.names.iter().map(|name| name.as_str()))
+        .collect();
+
     // build linkentries based on the nodes db
     for node in &nodes.nodes {
         for name in &node.names {
+            if node.kind == node::NodeKind::Directory && file_names.contains(name.as_str()) {
+                continue;
+            }
             let omni_path = OmniPath::new(vec![], name.to_string()).force_unalias();
             links.push(LinkEntry {
+                id: node.id.clone(),
                 omni_path,
                 true_path: node.path.clone(), // PERF:
                 valid: true,
@@ -119,19 +134,23 @@ fn dedup(
     Ok(edited)
 }
 
+/// all the ghosts, along with how many nodes link to them
 pub fn get_ghost_links(
     _root: impl AsRef<Utf8Path>,
     _config: &Config,
     links: &link::Db,
-) -> impl Iterator<Item = (OmniPath,)> {
+) -> impl Iterator<Item = (OmniPath, usize)> {
     links
         .links
         .iter()
         .filter_map(|l| match &l.to {
             link::To::Id(_) => None,
-            link::To::Ghost(file_part) => Some((OmniPath::from(file_part.clone()),)),
+            link::To::Ghost(file_part) => Some((OmniPath::from(file_part.clone()), &l.from)),
         })
         .unique()
+        .counts_by(|(omni_path, _)| omni_path)
+        .into_iter()
+        .sorted()
 }
 
 #[cfg(test)]
@@ -147,29 +166,23 @@ mod tests {
                 node::Node {
                     id: "id1".into(),
                     path: "/Users/me/docs/vault/cs/linear-algebra/vector.typ".into(),
-                    kind: node::NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 node::Node {
                     id: "id2".into(),
                     path: "/Users/me/docs/vault/cs/linear-algebra/matrix.typ".into(),
-                    kind: node::NodeKind::File,
                     title: "Matrix".into(),
                     names: vec!["matrix".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 node::Node {
                     id: "id3".into(),
                     path: "/Users/me/docs/vault/cs/rust/vector.typ".into(),
-                    kind: node::NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
             ],
         };
@@ -185,16 +198,19 @@ mod tests {
             get_possible_links(root, &config, &nodes).unwrap(),
             [
                 LinkEntry {
+                    id: "id2".into(),
                     omni_path: OmniPath::new(vec![], "matrix".into()).force_unalias(),
                     true_path: "/Users/me/docs/vault/cs/linear-algebra/matrix.typ".into(),
                     valid: true,
                 },
                 LinkEntry {
+                    id: "id1".into(),
                     omni_path: OmniPath::new(vec!["linalg".into()], "vector".into()),
                     true_path: "/Users/me/docs/vault/cs/linear-algebra/vector.typ".into(),
                     valid: true,
                 },
                 LinkEntry {
+                    id: "id3".into(),
                     omni_path: OmniPath::new(vec!["cs".into(), "rust".into()], "vector".into())
                         .force_unalias(),
                     true_path: "/Users/me/docs/vault/cs/rust/vector.typ".into(),
@@ -204,6 +220,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_ghost_links() {
+        let ghost = |name: &str| link::To::Ghost(link::FilePart::Name(name.into()));
+        let link = |from: &str, to: link::To| link::Link {
+            from: from.into(),
+            to,
+            ..Default::default()
+        };
+
+        let links = link::Db {
+            links: vec![
+                link("id1", ghost("tensor")),
+                link("id1", ghost("tensor")),
+                link("id2", ghost("tensor")),
+                link("id2", ghost("field")),
+                link("id2", link::To::Id("id1".into())),
+            ],
+        };
+
+        assert_eq!(
+            get_ghost_links("", &Config::default(), &links).collect_vec(),
+            [
+                (OmniPath::new(vec![], "field".into()), 1),
+                (OmniPath::new(vec![], "tensor".into()), 2),
+            ]
+        );
+    }
+
     #[test]
     fn test_get_possible_links_bad_dup() {
         let nodes = node::Db {
@@ -211,29 +255,23 @@ mod tests {
                 node::Node {
                     id: "id1".into(),
                     path: "/Users/me/docs/vault/cs/linear-algebra/vector.typ".into(),
-                    kind: node::NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 node::Node {
                     id: "id2".into(),
                     path: "/Users/me/docs/vault/vector.typ".into(),
-                    kind: node::NodeKind::File,
                     title: "vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
                 node::Node {
                     id: "id3".into(),
                     path: "/Users/me/docs/vault/cs/rust/vector.typ".into(),
-                    kind: node::NodeKind::File,
                     title: "Vector".into(),
                     names: vec!["vector".into()],
-                    tags: vec![],
-                    private: false,
+                    ..Default::default()
                 },
             ],
         };
@@ -249,11 +287,13 @@ mod tests {
             get_possible_links(root, &config, &nodes).unwrap(),
             [
                 LinkEntry {
+                    id: "id1".into(),
                     omni_path: OmniPath::new(vec!["linalg".into()], "vector".into()),
                     true_path: "/Users/me/docs/vault/cs/linear-algebra/vector.typ".into(),
                     valid: true
                 },
                 LinkEntry {
+                    id: "id3".into(),
                     omni_path: OmniPath::new(vec!["cs".into(), "rust".into()], "vector".into())
                         .force_unalias(),
                     true_path: "/Users/me/docs/vault/cs/rust/vector.typ".into(),
@@ -20,6 +20,11 @@ async fn main() {
         .with_filter(filter::LevelFilter::DEBUG);
     tracing_subscriber::registry().with(stderr_layer).init();
 
-    let (service, socket) = LspService::new(Backend::new);
+    let (service, socket) = LspService::build(Backend::new)
+        .custom_method(
+            "window/workDoneProgress/cancel",
+            Backend::work_done_progress_cancel,
+        )
+        .finish();
     Server::new(stdin, stdout, socket).serve(service).await;
 }
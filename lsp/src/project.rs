@@ -1,12 +1,16 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
 
 use camino::{Utf8Path, Utf8PathBuf};
 use dashmap::DashMap;
-use notify::{
-    Watcher,
-    event::{DataChange, ModifyKind},
+use notify::{Watcher, event::ModifyKind};
+use omni::{
+    link, node,
+    storage::{self, Storage, StorageError},
+    transaction::{self, TransactionError},
 };
-use omni::{link, node};
 use serde::Deserialize;
 use thiserror::Error;
 use tower_lsp_server::Client;
@@ -15,14 +19,16 @@ use crate::err_log_ext::ErrLogExt;
 
 #[derive(Debug)]
 /// NOTE: the ultimate source of truth for projects is the filesystem.
-/// To avoid data races, you should NEVER mutate project
-/// with the intent of then saving it to disk.
-/// Leave it to the CLI to do everything.
+/// You should NEVER mutate project with the intent of then saving it to disk,
+/// as it might be stale: writes go through `omni::transaction` while holding the build lock,
+/// reading the databases again once they have it.
 pub struct Project {
     pub config: omni::config::Config,
     pub user_nodes: omni::node::UserDb,
     pub nodes: omni::node::Db,
     pub links: omni::link::Db,
+    /// for looking nodes up by id, name or path without scanning `nodes`
+    pub storage: Mutex<Box<dyn Storage>>,
 }
 
 #[derive(Error, Debug)]
@@ -31,10 +37,110 @@ pub enum LoadError {
     IoError(#[from] tokio::io::Error),
 
     #[error(transparent)]
-    TomlDeserializeError(#[from] toml::de::Error),
+    TransactionError(#[from] TransactionError),
+
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
 }
 
 impl Project {
+    /// finds the file in `nodes.toml` which is at `path`
+    pub fn tracked_file(&self, path: &Utf8Path) -> Result<&node::File, node::Error> {
+        let path_canonical = path
+            .canonicalize_utf8()
+            .map_err(|_| node::Error::UntrackedNode(path.to_path_buf()))?;
+
+        self.user_nodes
+            .files
+            .iter()
+            .filter_map(|f| match f.path.canonicalize_utf8() {
+                Ok(p) => Some((f, p)),
+                Err(err) => {
+                    tracing::warn!(
+                        "invalid path found in nodes.toml for id {}. error: {}",
+                        f.id,
+                        err,
+                    );
+                    None
+                }
+            })
+            .find(|file| file.1 == path_canonical)
+            .map(|(f, _)| f)
+            .ok_or(node::Error::UntrackedNode(path.to_path_buf()))
+    }
+
+    /// runs `query` on the storage, or on the nodes in memory if it fails
+    fn query(
+        &self,
+        query: impl FnOnce(&dyn Storage) -> Result<Vec<node::Node>, StorageError>,
+        fallback: impl Fn(&node::Node) -> bool,
+    ) -> node::Db {
+        let found = match self.storage.lock() {
+            Ok(storage) => query(storage.as_ref())
+                .log_err("cannot query the storage")
+                .ok(),
+            Err(_) => None,
+        };
+        node::Db {
+            nodes: found.unwrap_or_else(|| {
+                self.nodes
+                    .nodes
+                    .iter()
+                    .filter(|n| fallback(n))
+                    .cloned()
+                    .collect()
+            }),
+        }
+    }
+
+    pub fn node_by_id(&self, id: &node::Id) -> Result<node::Node, node::Error> {
+        self.query(
+            |storage| Ok(storage.node_by_id(id)?.into_iter().collect()),
+            |n| &n.id == id,
+        )
+        .find_from_id(id, &self.config)
+        .cloned()
+    }
+
+    /// the file node at `path` (canonical)
+    pub fn find_abs(&self, path: &Utf8Path) -> Result<node::Node, node::Error> {
+        self.query(|storage| storage.nodes_by_path(path), |n| n.path == path)
+            .find_abs(path, &self.config)
+            .cloned()
+    }
+
+    /// only the nodes named like `part` can match, so just those are looked at
+    fn named(&self, part: &link::FilePart) -> node::Db {
+        let (link::FilePart::Name(name) | link::FilePart::PathAndName(_, name)) = part;
+        self.query(
+            |storage| storage.nodes_by_name(name),
+            |n| n.names.contains(name),
+        )
+    }
+
+    pub fn find_from_filepart(
+        &self,
+        root: &Utf8Path,
+        part: &link::FilePart,
+    ) -> Result<node::Node, node::Error> {
+        self.named(part)
+            .find_from_filepart(root, part, &self.config)
+            .cloned()
+    }
+
+    pub fn find_all_from_filepart(
+        &self,
+        root: &Utf8Path,
+        part: &link::FilePart,
+    ) -> Result<Vec<node::Node>, node::Error> {
+        Ok(self
+            .named(part)
+            .find_all_from_filepart(root, part, &self.config)?
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
     #[tracing::instrument]
     pub async fn read_and_parse_file<T, F>(file: F) -> Result<T, LoadError>
     where
@@ -42,13 +148,55 @@ impl Project {
         F: AsRef<Utf8Path> + Debug,
     {
         let db_file = tokio::fs::read(file.as_ref()).await?;
-        Ok(toml::from_slice(&db_file)?)
+        Ok(transaction::from_toml(&db_file, file)?)
+    }
+
+    /// reads the links from the storage chosen in `config`
+    pub fn load_links(
+        root: &Utf8Path,
+        config: &omni::config::Config,
+    ) -> Result<link::Db, LoadError> {
+        Ok(storage::open(root, config)?.load_links()?)
     }
 
     #[tracing::instrument]
     pub async fn load_project(root: &Utf8PathBuf) -> Result<Self, LoadError> {
+        let config: omni::config::Config =
+            Self::read_and_parse_file(root.join("omni.toml")).await?;
+        // build/nodes.toml is there whatever the storage, for the typst plugin
+        let nodes = match Self::read_and_parse_file(root.join("build/nodes.toml")).await {
+            Ok(v) => v,
+            Err(err) => {
+                tracing::error!(
+                    "error while loading {}. err: {}",
+                    root.join("build/nodes.toml"),
+                    err
+                );
+                node::Db { nodes: vec![] }
+            }
+        };
+        let links = match Self::load_links(root, &config) {
+            Ok(v) => v,
+            Err(err) => {
+                tracing::error!(
+                    "error while loading {}. err: {}",
+                    storage::links_path(root, &config),
+                    err
+                );
+                link::Db { links: vec![] }
+            }
+        };
+
+        let storage = match storage::open(root, &config) {
+            Ok(v) => v,
+            Err(err) => {
+                tracing::error!("error while opening the storage. err: {}", err);
+                Box::new(storage::toml_storage::TomlStorage::new(root))
+            }
+        };
+
         Ok(Self {
-            config: Self::read_and_parse_file(root.join("omni.toml")).await?,
+            storage: Mutex::new(storage),
             user_nodes: match Self::read_and_parse_file(root.join("nodes.toml")).await {
                 Ok(v) => v,
                 Err(err) => {
@@ -60,28 +208,9 @@ impl Project {
                     node::UserDb { files: vec![] }
                 }
             },
-            nodes: match Self::read_and_parse_file(root.join("build/nodes.toml")).await {
-                Ok(v) => v,
-                Err(err) => {
-                    tracing::error!(
-                        "error while loading {}. err: {}",
-                        root.join("build/nodes.toml"),
-                        err
-                    );
-                    node::Db { nodes: vec![] }
-                }
-            },
-            links: match Self::read_and_parse_file(root.join("build/links.toml")).await {
-                Ok(v) => v,
-                Err(err) => {
-                    tracing::error!(
-                        "error while loading {}. err: {}",
-                        root.join("build/links.toml"),
-                        err
-                    );
-                    link::Db { links: vec![] }
-                }
-            },
+            config,
+            nodes,
+            links,
         })
     }
 }
@@ -110,25 +239,34 @@ pub async fn start_watching_project(
     let config_path = root.join("omni.toml");
     let user_nodes_path = root.join("nodes.toml");
     let nodes_path = root.join("build/nodes.toml");
-    let links_path = root.join("build/links.toml");
+    let links_path = match projects.get(&root) {
+        Some(project) => storage::links_path(&root, &project.config),
+        None => return Ok(()),
+    };
 
     let targets = [&config_path, &user_nodes_path, &nodes_path, &links_path];
 
-    for path in &targets {
-        watcher.watch(&root.join_os(path), notify::RecursiveMode::NonRecursive)?;
+    // the databases get replaced by renaming over them, which a watch on the file itself would miss
+    for dir in [root.clone(), root.join("build")] {
+        watcher.watch(dir.as_std_path(), notify::RecursiveMode::NonRecursive)?;
     }
 
     while let Some(event) = rx.recv().await {
         let event = event?;
-        if let notify::EventKind::Modify(ModifyKind::Data(DataChange::Content)) = event.kind {
+        if let notify::EventKind::Create(_)
+        | notify::EventKind::Modify(
+            ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any,
+        ) = event.kind
+        {
             for path in event.paths {
                 if !targets.map(|t| root.join_os(t)).contains(&path) {
                     continue;
                 }
 
-                let mut project = projects
-                    .get_mut(&root)
-                    .expect("project should always exist");
+                // the project might have been unregistered in the meantime
+                let Some(mut project) = projects.get_mut(&root) else {
+                    return Ok(());
+                };
 
                 tracing::info!("reloading {}", path.display());
 
@@ -157,10 +295,16 @@ pub async fn start_watching_project(
                         continue;
                     };
                     project.nodes = nodes;
-                } else if path == root.join_os("build/links.toml") {
-                    let Ok(links) = Project::read_and_parse_file(root.join("build/links.toml"))
-                        .await
-                        .log_err("cannot read build/links.toml")
+                    // the toml storage keeps what it read
+                    let Ok(storage) =
+                        storage::open(&root, &project.config).log_err("cannot open the storage")
+                    else {
+                        continue;
+                    };
+                    project.storage = Mutex::new(storage);
+                } else if path == links_path {
+                    let Ok(links) = Project::load_links(&root, &project.config)
+                        .log_err(&format!("cannot read {links_path}"))
                     else {
                         continue;
                     };
@@ -13,6 +13,8 @@ struct State {
     pub db: node::Db,
     pub config: Config,
     pub root: Utf8PathBuf,
+    /// links to private nodes show up as ghosts, see `set_public`
+    pub public: bool,
 }
 
 static STATE: spin::Mutex<Option<State>> = spin::Mutex::new(None);
@@ -34,7 +36,26 @@ fn init(nodes_toml: &[u8], config_toml: &[u8], raw_root: &[u8]) -> Vec<u8> {
     let root = Utf8PathBuf::from(String::from_utf8_lossy(raw_root).to_string());
 
     let mut guard = STATE.lock();
-    *guard = Some(State { db, config, root });
+    *guard = Some(State {
+        db,
+        config,
+        root,
+        public: false,
+    });
+
+    b"ok".to_vec()
+}
+
+/// `public`: "true" in public builds, where private nodes must not show up, or "false"
+#[wasm_func]
+fn set_public(public: &[u8]) -> Vec<u8> {
+    let mut lock = STATE.lock();
+    let state = match lock.as_mut() {
+        Some(s) => s,
+        None => return "err: state is None. There might have been an error while parsing the config or the db.".into(),
+    };
+
+    state.public = public == b"true";
 
     b"ok".to_vec()
 }
@@ -45,7 +66,7 @@ fn init(nodes_toml: &[u8], config_toml: &[u8], raw_root: &[u8]) -> Vec<u8> {
 ///
 /// returns (comma separated bytes):
 /// `content`: content to display as the link in rendered output
-/// `target`: url to link to or "ghost"
+/// `target`: url to link to (with a `#label` fragment for sub-document nodes) or "ghost"
 /// `to`: id of target node or "ghost"
 #[wasm_func]
 fn parse_link(raw_file_part: &[u8], _raw_heading_part: &[u8], alias: &[u8]) -> Vec<u8> {
@@ -80,6 +101,8 @@ fn parse_link(raw_file_part: &[u8], _raw_heading_part: &[u8], alias: &[u8]) -> V
         .db
         .find_from_filepart(&state.root, &file_part, &state.config)
     {
+        // a ghost, so that its title doesn't show up
+        Ok(node) if state.public && state.db.is_private(node) => None,
         Ok(node) => Some(node),
         Err(node::Error::NameNotFound(_)) => None,
         Err(err) => return format!("err: {}", err).into_bytes(),
@@ -90,8 +113,13 @@ fn parse_link(raw_file_part: &[u8], _raw_heading_part: &[u8], alias: &[u8]) -> V
 
     match maybe_node {
         Some(node) => {
+            // the name the link uses decides how it shows up
+            let name = match &file_part {
+                FilePart::Name(name) => name,
+                FilePart::PathAndName(_, name) => name,
+            };
             let content = if alias.is_empty() {
-                &node.title
+                &node.display(name).to_string()
             } else {
                 &String::from_utf8_lossy(alias).to_string()
             };
@@ -102,7 +130,13 @@ fn parse_link(raw_file_part: &[u8], _raw_heading_part: &[u8], alias: &[u8]) -> V
             //     + ",ghost,ghost")
             //     .into_bytes();
             //
-            format!("{},{},{}", content, node.path, node.id.0).into_bytes()
+            // sub-document nodes point inside their file
+            let target = match &node.parent {
+                Some(parent) => format!("{}#{}", node.path, parent.label),
+                None => node.path.to_string(),
+            };
+
+            format!("{},{},{}", content, target, node.id.0).into_bytes()
         }
         None => {
             let content = if alias.is_empty() {